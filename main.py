@@ -56,6 +56,8 @@ class AppStack(TerraformStack):
 
     def __s3_resources(self):
         self.s3_bucket = S3Bucket(self, 'S3Bucket', force_destroy=True)
+        # the object lambda's reports, kept apart from the data bucket whose keys the API lists as task ids
+        self.reports_bucket = S3Bucket(self, 'ReportsBucket', force_destroy=True)
 
         self.s3_access_point = S3AccessPoint(
             self,
@@ -88,6 +90,29 @@ class AppStack(TerraformStack):
             policy_arn="arn:aws:iam::aws:policy/service-role/AmazonS3ObjectLambdaExecutionRolePolicy"
         )
 
+        # reads the suppression list, writes the stats and the quarantines to the reports bucket
+        IamRolePolicy(
+            self, "ObjectLambdaExecutionRoleInlinePolicy",
+            role=self.object_lambda_execution_role.id,
+            policy=f"""{{
+                "Version": "2012-10-17",
+                "Statement": [{{
+                    "Effect": "Allow",
+                    "Action": [
+                        "s3:GetObject"
+                    ],
+                    "Resource": "arn:aws:s3:::{self.s3_bucket.id}/*"
+                }},{{
+                    "Effect": "Allow",
+                    "Action": [
+                        "s3:PutObject",
+                        "s3:AbortMultipartUpload"
+                    ],
+                    "Resource": "arn:aws:s3:::{self.reports_bucket.id}/*"
+                }}]
+            }}"""
+        )
+
         self.object_lambda_code = TerraformAsset(
             self, "ObjectLambdaCode", path=str(BASE_DIR / "src/object_lambda/target/lambda/object_lambda"),
             type=AssetType.ARCHIVE
@@ -104,7 +129,10 @@ class AppStack(TerraformStack):
             source_code_hash=cdktf.Fn.filebase64sha256(self.object_lambda_code.path),
             # 1749 means also 1 vCPU
             memory_size=1769,
-            timeout=60
+            timeout=60,
+            environment={"variables": {
+                "REPORTS_BUCKET": self.reports_bucket.id
+            }}
         )
 
         self.object_lambda_access_point = S3ControlObjectLambdaAccessPoint(
@@ -0,0 +1,87 @@
+//! Throughput of the default match stage filtering on the runtime thread, offloaded to the
+//! worker pool, and with large chunks split across the workers.
+//!
+//! Run with `cargo bench --bench line_filter`.
+
+use std::time::Duration;
+
+use bytes::Bytes;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use futures::{stream, StreamExt};
+use regex::Regex;
+
+use object_lambda::libs::phone::HU_PATTERN;
+use object_lambda::libs::stream_filter::line::{LineStreamFilter, Parallelism};
+use object_lambda::libs::stream_filter::{StreamFilter, StreamItem};
+
+/// About 8 MiB of numbers in various spellings, with every fourth line rejected.
+fn input() -> Vec<u8> {
+    let mut input = String::new();
+    let mut i = 0u64;
+    while input.len() < 8 * 1024 * 1024 {
+        let line = match i % 4 {
+            0 => format!("+36 1 {:03} {:04}\n", i % 1000, i % 10_000),
+            1 => format!("0036 30 {:03} {:04}\n", i % 1000, i % 10_000),
+            2 => format!("+36 (70) {:03}-{:04}\n", i % 1000, i % 10_000),
+            _ => format!("call me at {i}\n"),
+        };
+        input.push_str(&line);
+        i += 1;
+    }
+    input.into_bytes()
+}
+
+fn filter_lines(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    let input = input();
+    let regex = Regex::new(HU_PATTERN).unwrap();
+    let modes = [
+        ("inline", None),
+        (
+            "offloaded",
+            Some(Parallelism {
+                min_split_bytes: usize::MAX,
+                ..Default::default()
+            }),
+        ),
+        ("parallel", Some(Parallelism::default())),
+    ];
+
+    let mut group = c.benchmark_group("filter_lines");
+    group.throughput(Throughput::Bytes(input.len() as u64));
+    group.measurement_time(Duration::from_secs(10));
+    for chunk_size in [16 * 1024, 1024 * 1024] {
+        let chunks: Vec<_> = input
+            .chunks(chunk_size)
+            .map(Bytes::copy_from_slice)
+            .collect();
+        for (mode, parallelism) in modes {
+            let filter = LineStreamFilter::with_parallelism(regex.clone(), parallelism);
+            group.bench_with_input(
+                BenchmarkId::new(mode, format!("{}KiB chunks", chunk_size / 1024)),
+                &chunks,
+                |b, chunks| {
+                    b.iter(|| {
+                        runtime.block_on(async {
+                            let input: Vec<StreamItem> = chunks.iter().cloned().map(Ok).collect();
+                            filter
+                                .filter_stream(Box::new(stream::iter(input)))
+                                .fold(
+                                    0,
+                                    |length, chunk| async move { length + chunk.unwrap().len() },
+                                )
+                                .await
+                        })
+                    })
+                },
+            );
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, filter_lines);
+criterion_main!(benches);
@@ -0,0 +1,48 @@
+;; Example line filter plugin: drops empty lines and `#` comments, keeps the lines starting
+;; with `+` and upper-cases the other lines.
+;;
+;; Build with `wat2wasm example.wat`, or load the text format as it is.
+(module
+  (memory (export "memory") 1)
+
+  ;; A single buffer at 1024, grown as needed and reused by every call.
+  (func (export "alloc") (param $len i32) (result i32)
+    (local $pages i32)
+    (local.set $pages
+      (i32.shr_u
+        (i32.add (local.get $len) (i32.const 66559)) ;; 1024 + 65535
+        (i32.const 16)))
+    (if (i32.gt_u (local.get $pages) (memory.size))
+      (then
+        (if (i32.eq (memory.grow (i32.sub (local.get $pages) (memory.size))) (i32.const -1))
+          (then unreachable))))
+    (i32.const 1024))
+
+  ;; -1 drops the line, -2 keeps it, anything else is the replacement as `ptr << 32 | len`.
+  (func (export "filter_line") (param $ptr i32) (param $len i32) (result i64)
+    (local $i i32)
+    (local $c i32)
+    (if (i32.eqz (local.get $len))
+      (then (return (i64.const -1))))
+    (local.set $c (i32.load8_u (local.get $ptr)))
+    (if (i32.eq (local.get $c) (i32.const 35)) ;; #
+      (then (return (i64.const -1))))
+    (if (i32.eq (local.get $c) (i32.const 43)) ;; +
+      (then (return (i64.const -2))))
+    (block $done
+      (loop $next
+        (br_if $done (i32.ge_u (local.get $i) (local.get $len)))
+        (local.set $c (i32.load8_u (i32.add (local.get $ptr) (local.get $i))))
+        (if (i32.and
+              (i32.ge_u (local.get $c) (i32.const 97))   ;; a
+              (i32.le_u (local.get $c) (i32.const 122))) ;; z
+          (then
+            (i32.store8
+              (i32.add (local.get $ptr) (local.get $i))
+              (i32.sub (local.get $c) (i32.const 32)))))
+        (local.set $i (i32.add (local.get $i) (i32.const 1)))
+        (br $next)))
+    (i64.or
+      (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+      (i64.extend_i32_u (local.get $len))))
+)
@@ -0,0 +1,5 @@
+// faux generated mocks elide lifetimes they also name, and move `new` to the mocked type,
+// away from its `Default` implementation
+#![cfg_attr(test, allow(mismatched_lifetime_syntaxes, clippy::new_without_default))]
+
+pub mod libs;
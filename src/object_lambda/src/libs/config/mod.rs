@@ -0,0 +1,261 @@
+use std::env::VarError;
+
+use anyhow::{bail, Context};
+use serde::Deserialize;
+
+use crate::libs::deps::env;
+use crate::libs::pipeline::{self, Output, StageConfig};
+use crate::libs::stats::StatsConfig;
+use crate::libs::stream_filter::aggregate::AggregateConfig;
+use crate::libs::stream_filter::archive::{ArchiveConfig, ArchiveOutput};
+use crate::libs::stream_filter::csv::CsvConfig;
+use crate::libs::stream_filter::dedupe::DedupeConfig;
+use crate::libs::stream_filter::encoding::{EncodingConfig, OutputEncoding};
+use crate::libs::stream_filter::jsonl::JsonlConfig;
+use crate::libs::stream_filter::numbers::{Keep, MatchConfig};
+use crate::libs::stream_filter::pseudonymize::PseudonymizeConfig;
+use crate::libs::stream_filter::quarantine::QuarantineConfig;
+use crate::libs::stream_filter::rewrite::RewriteConfig;
+use crate::libs::stream_filter::sample::SampleConfig;
+use crate::libs::stream_filter::sort::SortConfig;
+use crate::libs::stream_filter::spreadsheet::SpreadsheetConfig;
+use crate::libs::stream_filter::suppression::SuppressionConfig;
+use crate::libs::stream_filter::vcard::VcardConfig;
+
+/// Environment variable holding the JSON or TOML configuration of the object lambda.
+pub const CONFIG_VAR: &str = "FILTER_CONFIG";
+
+/// Environment variable naming the bucket of the stats, apart from the data bucket whose keys
+/// the API lists as task ids.
+pub const REPORTS_BUCKET_VAR: &str = "REPORTS_BUCKET";
+
+#[derive(Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct Config {
+    #[serde(default)]
+    pub input: InputConfig,
+    #[serde(default)]
+    pub encoding: EncodingConfig,
+    /// drops repeated output lines
+    #[serde(default)]
+    pub dedupe: Option<DedupeConfig>,
+    /// outputs a random sample of the lines instead of all of them
+    #[serde(default)]
+    pub sample: Option<SampleConfig>,
+    /// outputs the numbers sorted and without duplicates
+    #[serde(default)]
+    pub sort: Option<SortConfig>,
+    /// drops the numbers on a do-not-call list, or keeps only those
+    #[serde(default)]
+    pub suppression: Option<SuppressionConfig>,
+    /// outputs the numbers in a custom layout, after the filters above
+    #[serde(default)]
+    pub rewrite: Option<RewriteConfig>,
+    /// replaces every output number with its keyed hash
+    #[serde(default)]
+    pub pseudonymize: Option<PseudonymizeConfig>,
+    /// writes a stats document for every task
+    #[serde(default)]
+    pub stats: Option<StatsConfig>,
+    /// writes the rejected lines of every task to a separate object
+    #[serde(default)]
+    pub quarantine: Option<QuarantineConfig>,
+    /// outputs only a histogram of the input lines instead of the numbers
+    #[serde(default)]
+    pub aggregate: Option<AggregateConfig>,
+    /// the stages of the filter, replacing the input and the filter options above
+    #[serde(default)]
+    pub pipeline: Option<Vec<StageConfig>>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(tag = "format", rename_all = "camelCase")]
+pub enum InputConfig {
+    /// newline-delimited text, one number per line
+    #[default]
+    Text,
+    Csv(CsvConfig),
+    Jsonl(JsonlConfig),
+    Vcard(VcardConfig),
+    /// ZIP or TAR archive of text files
+    Archive(ArchiveConfig),
+    /// XLSX, XLS or ODS workbook
+    Spreadsheet(SpreadsheetConfig),
+}
+
+impl InputConfig {
+    /// Whether the input is text, as opposed to a binary container which cannot be transcoded.
+    pub fn is_text(&self) -> bool {
+        !matches!(self, InputConfig::Archive(_) | InputConfig::Spreadsheet(_))
+    }
+
+    /// Whether the filter outputs lines, as opposed to a filtered archive.
+    pub fn has_line_output(&self) -> bool {
+        !matches!(self, InputConfig::Archive(archive) if archive.output == ArchiveOutput::Archive)
+    }
+
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            InputConfig::Text => Ok(()),
+            InputConfig::Csv(csv) => csv.validate(),
+            InputConfig::Jsonl(jsonl) => jsonl.validate(),
+            InputConfig::Spreadsheet(spreadsheet) => spreadsheet.validate(),
+            InputConfig::Vcard(vcard) => vcard.validate(),
+            InputConfig::Archive(archive) => archive.validate(),
+        }
+    }
+}
+
+impl Config {
+    /// Reads and validates the configuration, falling back to the defaults if it is not set.
+    /// A value starting with `{` is read as JSON, anything else as TOML.
+    pub fn load(env: &env::Env) -> anyhow::Result<Self> {
+        let mut config = match env.var(CONFIG_VAR) {
+            Ok(value) if value.trim_start().starts_with('{') => {
+                serde_json::from_str::<Config>(&value)
+                    .with_context(|| format!("{CONFIG_VAR} is not a valid configuration"))?
+            }
+            Ok(value) => toml::from_str::<Config>(&value)
+                .with_context(|| format!("{CONFIG_VAR} is not a valid configuration"))?,
+            Err(VarError::NotPresent) => Config::default(),
+            Err(error) => return Err(error).context(format!("{CONFIG_VAR} could not be read")),
+        };
+        config.default_reports_bucket(env);
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Writes the stats and the quarantines to the reports bucket unless a bucket is configured.
+    fn default_reports_bucket(&mut self, env: &env::Env) {
+        let buckets = (self.stats.iter_mut().map(|stats| &mut stats.bucket)).chain(
+            self.quarantine
+                .iter_mut()
+                .map(|quarantine| &mut quarantine.bucket),
+        );
+        for bucket in buckets.filter(|bucket| bucket.is_empty()) {
+            if let Ok(reports) = env.var(REPORTS_BUCKET_VAR) {
+                *bucket = reports;
+            }
+        }
+    }
+
+    /// The stages of the filter, translated from the legacy options unless a pipeline is set.
+    pub fn stages(&self) -> Vec<StageConfig> {
+        if let Some(pipeline) = &self.pipeline {
+            return pipeline.clone();
+        }
+        let mut stages = vec![StageConfig::Decode(self.input.clone())];
+        // aggregation counts the rejected lines too, so it takes over from the text filter
+        if self.input == InputConfig::Text && self.aggregate.is_none() {
+            stages.push(StageConfig::Match(Default::default()));
+        }
+        stages.extend(self.suppression.clone().map(StageConfig::Suppression));
+        stages.extend(self.dedupe.clone().map(StageConfig::Dedupe));
+        stages.extend(self.sample.clone().map(StageConfig::Sample));
+        stages.extend(self.sort.clone().map(StageConfig::Sort));
+        stages.extend(self.rewrite.clone().map(StageConfig::Rewrite));
+        stages.extend(self.pseudonymize.clone().map(StageConfig::Pseudonymize));
+        stages.extend(self.aggregate.clone().map(StageConfig::Aggregate));
+        stages
+    }
+
+    /// The input format, which decides whether the input can be transcoded.
+    pub fn decode(&self) -> &InputConfig {
+        match self.pipeline.as_deref() {
+            Some([StageConfig::Decode(input), ..]) => input,
+            _ => &self.input,
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        if self.pipeline.is_some()
+            && (self.input != InputConfig::Text
+                || self.dedupe.is_some()
+                || self.sample.is_some()
+                || self.sort.is_some()
+                || self.rewrite.is_some()
+                || self.suppression.is_some()
+                || self.pseudonymize.is_some()
+                || self.aggregate.is_some())
+        {
+            bail!("a pipeline replaces input, dedupe, sample, sort, rewrite, suppression, pseudonymize and aggregate");
+        }
+        if !self.decode().is_text() && self.encoding != EncodingConfig::default() {
+            bail!("encoding can only be configured for text inputs");
+        }
+        if let Some(dedupe) = &self.dedupe {
+            if !self.input.has_line_output() {
+                bail!("dedupe needs line output, not a filtered archive");
+            }
+            dedupe.validate()?;
+        }
+        if let Some(suppression) = &self.suppression {
+            if !self.input.has_line_output() {
+                bail!("suppression needs line output, not a filtered archive");
+            }
+            suppression.validate()?;
+        }
+        if let Some(sample) = &self.sample {
+            if !self.input.has_line_output() {
+                bail!("sampling needs line output, not a filtered archive");
+            }
+            if self.aggregate.is_some() {
+                bail!("sampling cannot be combined with aggregation");
+            }
+            sample.validate()?;
+        }
+        if let Some(sort) = &self.sort {
+            if !self.input.has_line_output() {
+                bail!("sorting needs line output, not a filtered archive");
+            }
+            if self.aggregate.is_some() {
+                bail!("sorting cannot be combined with aggregation");
+            }
+            sort.validate()?;
+        }
+        if self.rewrite.is_some() && !self.input.has_line_output() {
+            bail!("rewriting needs line output, not a filtered archive");
+        }
+        if let Some(pseudonymize) = &self.pseudonymize {
+            if !self.input.has_line_output() {
+                bail!("pseudonymization needs line output, not a filtered archive");
+            }
+            pseudonymize.validate()?;
+        }
+        if let Some(aggregate) = &self.aggregate {
+            if self.input != InputConfig::Text {
+                bail!("aggregation is only supported for text input");
+            }
+            if self.pseudonymize.is_some() {
+                bail!("aggregation cannot be combined with pseudonymization");
+            }
+            aggregate.validate()?;
+        }
+        let stages = self.stages();
+        let output = pipeline::validate(&stages)?;
+        if self.encoding.output != OutputEncoding::Utf8 && output == Output::Binary {
+            bail!("encoding output needs line output, not binary output");
+        }
+        if let Some(stats) = &self.stats {
+            if stats.sketch.is_some() && output == Output::Binary {
+                bail!("stats sketch needs line output, not binary output");
+            }
+            stats.validate()?;
+        }
+        if let Some(quarantine) = &self.quarantine {
+            if *self.decode() != InputConfig::Text {
+                bail!("quarantine is only supported for text input");
+            }
+            // the quarantine stands in for that stage, the others see the lines as they are
+            let keep_valid = StageConfig::Match(MatchConfig { keep: Keep::Valid });
+            if !stages.contains(&keep_valid) {
+                bail!("quarantine needs a `match` stage of the valid numbers, whose dropped lines it holds");
+            }
+            quarantine.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests;
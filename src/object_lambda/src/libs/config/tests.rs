@@ -0,0 +1,370 @@
+use std::env::VarError;
+
+use crate::libs::stream_filter::csv::{CsvColumn, CsvOutput};
+use crate::libs::stream_filter::encoding::OutputEncoding;
+use crate::libs::stream_filter::limit::LimitConfig;
+
+use super::*;
+
+#[test]
+fn test_defaults_when_not_set() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Err(VarError::NotPresent));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert_eq!(config.unwrap(), Config::default());
+}
+
+#[test]
+fn test_csv_input() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"input": {"format": "csv", "column": "phone", "output": "number"}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env).unwrap();
+
+    // then
+    let InputConfig::Csv(csv) = config.input else {
+        panic!("expected CSV input, got {:?}", config.input);
+    };
+    assert_eq!(csv.column, CsvColumn::Name("phone".into()));
+    assert_eq!(csv.delimiter, ',');
+    assert!(csv.has_header);
+    assert_eq!(csv.output, CsvOutput::Number);
+}
+
+#[test]
+fn test_csv_column_name_without_header() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"input": {"format": "csv", "column": "phone", "hasHeader": false}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_unknown_field() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{"inptu": {}}"#.into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_jsonl_input_with_invalid_path() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"input": {"format": "jsonl", "fields": ["contact.phones[x]"]}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_encoding() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"encoding": {"source": "windows-1250", "output": "source"}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env).unwrap();
+
+    // then
+    assert_eq!(config.encoding.source, Some(encoding_rs::WINDOWS_1250));
+    assert_eq!(config.encoding.output, OutputEncoding::Source);
+}
+
+#[test]
+fn test_unknown_encoding() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR))
+        .then_return(Ok(r#"{"encoding": {"source": "klingon"}}"#.into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_encoding_of_binary_input() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"input": {"format": "spreadsheet"}, "encoding": {"source": "latin2"}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_encoding_of_binary_output() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{
+            "encoding": {"source": "latin2", "output": "source"},
+            "pipeline": [{"stage": "decode", "format": "text"}, {"stage": "compress", "format": "gzip"}]
+        }"#
+    .into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_zero_archive_entry_limit() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"input": {"format": "archive", "maxEntryBytes": 0}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_dedupe_of_filtered_archive() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"input": {"format": "archive", "output": "archive"}, "dedupe": {"mode": "exact"}}"#
+            .into(),
+    ));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_stats_in_reports_bucket() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{"stats": {}}"#.into()));
+    faux::when!(env.var(REPORTS_BUCKET_VAR)).then_return(Ok("reports".into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert_eq!(config.unwrap().stats.unwrap().bucket, "reports");
+}
+
+#[test]
+fn test_stats_without_bucket() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{"stats": {}}"#.into()));
+    faux::when!(env.var(REPORTS_BUCKET_VAR)).then_return(Err(VarError::NotPresent));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_quarantine_in_reports_bucket() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{"quarantine": {}}"#.into()));
+    faux::when!(env.var(REPORTS_BUCKET_VAR)).then_return(Ok("reports".into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert_eq!(config.unwrap().quarantine.unwrap().bucket, "reports");
+}
+
+#[test]
+fn test_quarantine_without_match() {
+    for config in [
+        r#"{"aggregate": {}, "quarantine": {"bucket": "bucket"}}"#,
+        r#"{"pipeline": [{"stage": "decode", "format": "text"}, {"stage": "pii"}],
+            "quarantine": {"bucket": "bucket"}}"#,
+        r#"{"pipeline": [{"stage": "decode", "format": "text"}, {"stage": "match", "keep": "invalid"}],
+            "quarantine": {"bucket": "bucket"}}"#,
+    ] {
+        // given
+        let mut env = env::Env::faux();
+        faux::when!(env.var(CONFIG_VAR)).then_return(Ok(config.into()));
+
+        // when
+        let loaded = Config::load(&env);
+
+        // then
+        assert!(loaded.is_err(), "{config}");
+    }
+}
+
+#[test]
+fn test_quarantine_of_csv_input() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"input": {"format": "vcard"}, "quarantine": {"bucket": "bucket"}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_aggregate_with_pseudonymize() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(
+        r#"{"aggregate": {}, "pseudonymize": {"keyId": "2024a"}}"#.into(),
+    ));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_empty_sample() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{"sample": {"size": 0}}"#.into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_toml_pipeline() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"
+        [[pipeline]]
+        stage = "decode"
+        format = "text"
+
+        [[pipeline]]
+        stage = "normalize"
+
+        [[pipeline]]
+        stage = "limit"
+        lines = 100
+        "#
+    .into()));
+
+    // when
+    let config = Config::load(&env).unwrap();
+
+    // then
+    assert_eq!(
+        config.stages(),
+        vec![
+            StageConfig::Decode(InputConfig::Text),
+            StageConfig::Normalize,
+            StageConfig::Limit(LimitConfig { lines: 100 }),
+        ]
+    );
+}
+
+#[test]
+fn test_pipeline_with_legacy_options() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{
+        "pipeline": [{"stage": "decode", "format": "text"}],
+        "dedupe": {"mode": "exact"}
+    }"#
+    .into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
+
+#[test]
+fn test_legacy_stages() {
+    // given
+    let config = Config {
+        sample: Some(SampleConfig {
+            size: 10,
+            seed: None,
+        }),
+        ..Default::default()
+    };
+
+    // when
+    let stages = config.stages();
+
+    // then
+    assert_eq!(
+        stages.iter().map(StageConfig::name).collect::<Vec<_>>(),
+        ["decode", "match", "sample"]
+    );
+}
+
+#[test]
+fn test_rewrite_with_aggregate() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(CONFIG_VAR)).then_return(Ok(r#"{
+        "rewrite": {"template": "${area};${subscriber}"},
+        "aggregate": {}
+    }"#
+    .into()));
+
+    // when
+    let config = Config::load(&env);
+
+    // then
+    assert!(config.is_err());
+}
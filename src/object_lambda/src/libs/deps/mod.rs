@@ -1,2 +1,3 @@
-pub(crate) mod reqwest;
-pub(crate) mod s3;
+pub mod env;
+pub mod reqwest;
+pub mod s3;
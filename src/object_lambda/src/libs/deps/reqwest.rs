@@ -1,14 +1,36 @@
-use reqwest::{Response, Result};
+use std::time::Duration;
+
+use reqwest::header::CONTENT_TYPE;
+use reqwest::{Client, Response, Result};
 
 #[cfg_attr(test, faux::create)]
-pub struct Reqwest {}
+pub struct Reqwest {
+    client: Client,
+}
 
 #[cfg_attr(test, faux::methods)]
 impl Reqwest {
     pub fn new() -> Self {
-        Self {}
+        Self {
+            client: Client::new(),
+        }
     }
     pub async fn get(&self, url: &str) -> Result<Response> {
-        reqwest::get(url).await
+        self.client.get(url).send().await
+    }
+    pub async fn post_json(&self, url: &str, body: String, timeout: Duration) -> Result<Response> {
+        self.client
+            .post(url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(body)
+            .timeout(timeout)
+            .send()
+            .await
+    }
+}
+
+impl Default for Reqwest {
+    fn default() -> Self {
+        Self::new()
     }
 }
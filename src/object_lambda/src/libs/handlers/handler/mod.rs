@@ -2,15 +2,21 @@ use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
 
+use ::reqwest::header::{CONTENT_TYPE, ETAG};
 use anyhow::{anyhow, Context};
 use aws_lambda_events::s3::object_lambda::S3ObjectLambdaEvent;
 use clone_all::clone_all;
+use futures::StreamExt;
 use serde::Serialize;
 
 use crate::libs::deps::reqwest;
 use crate::libs::deps::s3;
+use crate::libs::stats::{self, Invocation, StatsWriter};
 use crate::libs::stream_byte_stream_adapter::{StreamByteStreamAdapter, StreamToByteStream};
-use crate::libs::stream_filter::{RegexStreamFilter, StreamFilter};
+use crate::libs::stream_filter::encoding::Transcoder;
+use crate::libs::stream_filter::quarantine::Quarantine;
+use crate::libs::stream_filter::suppression::SuppressionList;
+use crate::libs::stream_filter::DynStreamFilter;
 
 #[derive(Serialize, Debug)]
 pub struct ObjectLambdaResponse {
@@ -26,37 +32,106 @@ pub type HandlerFn = Box<
         + 'static,
 >;
 
+#[allow(clippy::too_many_arguments)]
 pub fn factory(
     s3: Arc<s3::S3>,
     reqwest: Arc<reqwest::Reqwest>,
-    filter: Arc<RegexStreamFilter>,
+    filter: Arc<DynStreamFilter>,
+    transcoder: Arc<Transcoder>,
+    suppression: Option<Arc<SuppressionList>>,
+    stats: Option<Arc<StatsWriter>>,
+    quarantine: Option<Arc<Quarantine>>,
     adapter: Arc<StreamByteStreamAdapter>,
 ) -> HandlerFn {
     Box::new(move |event| {
-        clone_all!(s3, reqwest, filter, adapter);
+        clone_all!(
+            s3,
+            reqwest,
+            filter,
+            transcoder,
+            suppression,
+            stats,
+            quarantine,
+            adapter
+        );
         Box::pin(async move {
             tracing::info!("Received event: {:?}", event);
+            let invocation = stats
+                .as_ref()
+                .map_or_else(Invocation::disabled, |stats| stats.start());
             let get_object_context = event
                 .get_object_context
                 .ok_or(anyhow!("GetObjectContext not defined"))?;
             let output_route = get_object_context.output_route;
             let output_token = get_object_context.output_token;
             let input_s3_url = get_object_context.input_s3_url;
-            let stream = reqwest
+            if let Some(suppression) = &suppression {
+                suppression
+                    .refresh(&s3)
+                    .await
+                    .context("could not load the suppression list")?;
+            }
+            let response = reqwest
                 .get(&input_s3_url)
                 .await
-                .context("could not fetch input_s3_url")?
-                .bytes_stream();
+                .context("could not fetch input_s3_url")?;
+            let content_type = response
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let etag = response
+                .headers()
+                .get(ETAG)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            let stream = response
+                .bytes_stream()
+                .map(|item| item.map_err(anyhow::Error::from));
 
-            let stream = filter.filter_stream(Box::new(stream));
+            let stream = invocation.tap_input(Box::new(stream));
+            let (stream, encoding) = transcoder.decode(stream, content_type.as_deref()).await;
+            let stream = invocation.tap_lines(stream);
+            let stream = filter.filter_stream(stream);
+            // the quarantine runs the stages after the match stage it stands in for
+            let (stream, rejected) = match &quarantine {
+                Some(quarantine) => {
+                    let (stream, rejected) = quarantine.split(stream);
+                    (stream, Some(rejected))
+                }
+                None => (stream, None),
+            };
+            let stream = invocation.tap_matched(stream);
+            let stream = invocation.tap_output(transcoder.encode(stream, encoding));
 
-            s3.write_get_object_response(
+            let response = s3.write_get_object_response(
                 &output_route,
                 &output_token,
                 adapter.stream_to_byte_stream(stream),
-            )
-            .await
-            .context("error in writing get_object_response")?;
+            );
+            // the rejected lines are uploaded while the response streams
+            let upload = async {
+                let (Some(quarantine), Some(rejected)) = (&quarantine, rejected) else {
+                    return;
+                };
+                match stats::task_id(&event.user_request.url) {
+                    Ok(task_id) => quarantine.upload(&s3, &task_id, rejected).await,
+                    Err(error) => tracing::warn!("no quarantine written: {error:#}"),
+                }
+            };
+            let (result, ()) = futures::join!(response, upload);
+            let result = result.context("error in writing get_object_response");
+
+            if let Some(stats) = &stats {
+                match stats::task_id(&event.user_request.url) {
+                    Ok(task_id) => {
+                        let report = invocation.report(task_id, etag, result.as_ref().err());
+                        stats.write(&s3, &report).await;
+                    }
+                    Err(error) => tracing::warn!("no stats written: {error:#}"),
+                }
+            }
+            result?;
 
             Ok(ObjectLambdaResponse { status_code: 200 })
         })
@@ -1,8 +1,21 @@
+#![allow(clippy::result_large_err)]
+
 use crate::libs::stream_byte_stream_adapter::StreamByteStreamAdapter;
+use crate::libs::stream_filter::RegexStreamFilter;
 use aws_lambda_events::http;
 use aws_lambda_events::s3::object_lambda::GetObjectContext;
+use aws_lambda_events::s3::object_lambda::UserRequest;
+use aws_sdk_s3::operation::put_object::PutObjectOutput;
 use aws_sdk_s3::operation::write_get_object_response::WriteGetObjectResponseOutput;
 use aws_sdk_s3::primitives::ByteStream;
+use futures::stream;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+use crate::libs::deps::env;
+use crate::libs::pipeline::{Pipeline, StageConfig};
+use crate::libs::stats::{FilterStats, LineCounts, StatsConfig};
+use crate::libs::stream_filter::quarantine::QuarantineConfig;
 
 use super::*;
 
@@ -23,12 +36,7 @@ async fn test_happy_path() {
     });
 
     let mut mock_stream_filter = RegexStreamFilter::faux();
-    faux::when!(mock_stream_filter.filter_stream).then(|_| {
-        Box::new(
-            ::reqwest::Response::from(http::Response::builder().status(200).body("").unwrap())
-                .bytes_stream(),
-        )
-    });
+    faux::when!(mock_stream_filter.filter_stream).then(|_| Box::new(stream::empty()));
 
     let mut mock_stream_byte_stream_adapter = StreamByteStreamAdapter::faux();
     faux::when!(mock_stream_byte_stream_adapter.stream_to_byte_stream)
@@ -38,6 +46,10 @@ async fn test_happy_path() {
         Arc::new(mock_s3),
         Arc::new(mock_reqwest),
         Arc::new(mock_stream_filter),
+        Arc::new(Transcoder::new(Default::default())),
+        None,
+        None,
+        None,
         Arc::new(mock_stream_byte_stream_adapter),
     );
 
@@ -46,7 +58,6 @@ async fn test_happy_path() {
             input_s3_url: "https://example.com".to_string(),
             output_route: "output_route".to_string(),
             output_token: "output_token".to_string(),
-            ..Default::default()
         }),
         ..Default::default()
     };
@@ -59,6 +70,174 @@ async fn test_happy_path() {
     // ... and so on
 }
 
+#[tokio::test]
+async fn test_stats_written() {
+    // given
+    let written = Arc::new(AtomicBool::new(false));
+    let mut mock_s3 = s3::S3::faux();
+    faux::when!(mock_s3.write_get_object_response)
+        .then(|(_, _, _)| Ok(WriteGetObjectResponseOutput::builder().build()));
+    faux::when!(mock_s3.put_object("bucket", "stats/task-1.json", _, "application/json")).then({
+        let written = written.clone();
+        move |_| {
+            written.store(true, Ordering::SeqCst);
+            Ok(PutObjectOutput::builder().build())
+        }
+    });
+
+    let mut mock_reqwest = reqwest::Reqwest::faux();
+    faux::when!(mock_reqwest.get).then(|_| {
+        Ok(http::Response::builder()
+            .status(200)
+            .header("ETag", "\"abc\"")
+            .body("+36 1 234 5678\n")
+            .unwrap()
+            .into())
+    });
+
+    let mut mock_stream_byte_stream_adapter = StreamByteStreamAdapter::faux();
+    faux::when!(mock_stream_byte_stream_adapter.stream_to_byte_stream)
+        .then(|_| ByteStream::from_static(b""));
+
+    let stats = StatsWriter::new(
+        StatsConfig {
+            bucket: "bucket".into(),
+            prefix: "stats/".into(),
+            max_line_bytes: 4096,
+            sketch: None,
+        },
+        LineCounts::WithRejected,
+        Arc::new(FilterStats::default()),
+    );
+    let handler = factory(
+        Arc::new(mock_s3),
+        Arc::new(mock_reqwest),
+        Arc::new(RegexStreamFilter::new(regex::Regex::new("").unwrap())),
+        Arc::new(Transcoder::new(Default::default())),
+        None,
+        Some(Arc::new(stats)),
+        None,
+        Arc::new(mock_stream_byte_stream_adapter),
+    );
+
+    let event = S3ObjectLambdaEvent {
+        get_object_context: Some(GetObjectContext {
+            input_s3_url: "https://example.com".to_string(),
+            output_route: "output_route".to_string(),
+            output_token: "output_token".to_string(),
+        }),
+        user_request: UserRequest {
+            url: "https://ap-123.s3-object-lambda.eu-central-1.amazonaws.com/task-1".to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // when
+    let response = handler(event).await;
+
+    // then
+    assert_eq!(response.unwrap().status_code, 200);
+    assert!(written.load(Ordering::SeqCst));
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn test_quarantine_after_pii() {
+    // given
+    let quarantined = Arc::new(Mutex::new(vec![]));
+    let output = Arc::new(Mutex::new(vec![]));
+    let mut mock_s3 = s3::S3::faux();
+    faux::when!(mock_s3.write_get_object_response)
+        .then(|(_, _, _)| Ok(WriteGetObjectResponseOutput::builder().build()));
+    faux::when!(mock_s3.put_object("bucket", "quarantine/task-1.csv", _, "text/csv")).then({
+        let quarantined = quarantined.clone();
+        move |(_, _, body, _)| {
+            *quarantined.lock().unwrap() = body.into_inner().bytes().unwrap().to_vec();
+            Ok(PutObjectOutput::builder().build())
+        }
+    });
+
+    let mut mock_reqwest = reqwest::Reqwest::faux();
+    faux::when!(mock_reqwest.get).then(|_| {
+        Ok(http::Response::builder()
+            .status(200)
+            .body("+36 1 234 5678\nmail jane@example.hu\n")
+            .unwrap()
+            .into())
+    });
+
+    let mut mock_stream_byte_stream_adapter = StreamByteStreamAdapter::faux();
+    faux::when!(mock_stream_byte_stream_adapter.stream_to_byte_stream).then({
+        let output = output.clone();
+        move |stream| {
+            let chunks = futures::executor::block_on(stream.collect::<Vec<_>>());
+            *output.lock().unwrap() = chunks.into_iter().map(Result::unwrap).collect();
+            ByteStream::from_static(b"")
+        }
+    });
+
+    let stages: Vec<StageConfig> = serde_json::from_str(
+        r#"[
+            {"stage": "decode", "format": "text"},
+            {"stage": "pii", "mode": "redact"},
+            {"stage": "match"}
+        ]"#,
+    )
+    .unwrap();
+    let pipeline = Pipeline::build(
+        &stages,
+        &env::Env::faux(),
+        &s3::S3::faux(),
+        &Arc::new(reqwest::Reqwest::faux()),
+    )
+    .await
+    .unwrap();
+    let (before, after) = pipeline.around_match.unwrap();
+    let quarantine = Quarantine::new(
+        QuarantineConfig {
+            bucket: "bucket".into(),
+            prefix: "quarantine/".into(),
+            max_line_bytes: 4096,
+        },
+        after,
+    );
+    let handler = factory(
+        Arc::new(mock_s3),
+        Arc::new(mock_reqwest),
+        before,
+        Arc::new(Transcoder::new(Default::default())),
+        None,
+        None,
+        Some(Arc::new(quarantine)),
+        Arc::new(mock_stream_byte_stream_adapter),
+    );
+
+    let event = S3ObjectLambdaEvent {
+        get_object_context: Some(GetObjectContext {
+            input_s3_url: "https://example.com".to_string(),
+            output_route: "output_route".to_string(),
+            output_token: "output_token".to_string(),
+        }),
+        user_request: UserRequest {
+            url: "https://ap-123.s3-object-lambda.eu-central-1.amazonaws.com/task-1".to_string(),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    // when
+    let response = handler(event).await;
+
+    // then
+    assert_eq!(response.unwrap().status_code, 200);
+    assert_eq!(output.lock().unwrap().concat(), b"+36 1 234 5678\n");
+    // the pii stage sees every line, and the quarantine only what the match stage drops
+    assert_eq!(
+        *quarantined.lock().unwrap(),
+        b"2,illegal_character,mail [email]\n"
+    );
+}
+
 #[tokio::test]
 async fn and_so_on() {
     // ...
@@ -1 +1 @@
-pub(crate) mod handler;
+pub mod handler;
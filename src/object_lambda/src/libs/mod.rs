@@ -1,4 +1,10 @@
-pub(crate) mod deps;
-pub(crate) mod handlers;
-pub(crate) mod stream_byte_stream_adapter;
-pub(crate) mod stream_filter;
+pub mod config;
+pub mod deps;
+pub mod handlers;
+pub mod phone;
+pub mod pii;
+pub mod pipeline;
+pub mod sketch;
+pub mod stats;
+pub mod stream_byte_stream_adapter;
+pub mod stream_filter;
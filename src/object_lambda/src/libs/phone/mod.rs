@@ -0,0 +1,208 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// Hungarian numbers in international format, with arbitrary whitespace between the digits.
+pub const HU_PATTERN: &str = r"^\s*(\+|0\s*0)\s*3\s*6\s*(1|[2-9]\s*[0-9])\s*([0-9]\s*){7}$";
+
+/// Reduces an internationally formatted number to E.164, e.g. `00 36 1 234 5678` to `+3612345678`.
+///
+/// Returns `None` if the number has neither a `+` nor a `00` prefix.
+pub fn normalize(number: &str) -> Option<String> {
+    let digits: String = number.chars().filter(char::is_ascii_digit).collect();
+    if number.trim_start().starts_with('+') {
+        Some(format!("+{digits}"))
+    } else {
+        digits.strip_prefix("00").map(|rest| format!("+{rest}"))
+    }
+}
+
+/// Why a line is not a Hungarian number in international format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rejection {
+    Empty,
+    /// neither a `+` nor a `00` prefix, e.g. the national `06` format
+    MissingPrefix,
+    WrongCountryCode,
+    /// an area code starting with `0`
+    InvalidAreaCode,
+    TooFewDigits,
+    TooManyDigits,
+    /// a character other than a digit or whitespace within the number
+    IllegalCharacter(char),
+    /// text after a complete number
+    TrailingText,
+}
+
+impl Rejection {
+    /// A stable snake case code, e.g. `wrong_country_code`.
+    pub fn code(self) -> &'static str {
+        match self {
+            Rejection::Empty => "empty",
+            Rejection::MissingPrefix => "missing_prefix",
+            Rejection::WrongCountryCode => "wrong_country_code",
+            Rejection::InvalidAreaCode => "invalid_area_code",
+            Rejection::TooFewDigits => "too_few_digits",
+            Rejection::TooManyDigits => "too_many_digits",
+            Rejection::IllegalCharacter(_) => "illegal_character",
+            Rejection::TrailingText => "trailing_text",
+        }
+    }
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Rejection::Empty => write!(f, "empty line"),
+            Rejection::MissingPrefix => write!(f, "no international prefix `+` or `00`"),
+            Rejection::WrongCountryCode => write!(f, "country code is not 36"),
+            Rejection::InvalidAreaCode => write!(f, "area code starts with 0"),
+            Rejection::TooFewDigits => write!(f, "too few digits"),
+            Rejection::TooManyDigits => write!(f, "too many digits"),
+            Rejection::IllegalCharacter(c) => write!(f, "illegal character {c:?}"),
+            Rejection::TrailingText => write!(f, "text after the number"),
+        }
+    }
+}
+
+/// The outcome of validating a line against [`HU_PATTERN`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+    /// the number in E.164, e.g. `+3612345678`
+    Valid(String),
+    Rejected(Rejection),
+}
+
+/// Validates `line` like [`HU_PATTERN`] does, explaining the first reason a line is rejected.
+pub fn verdict(line: &str) -> Verdict {
+    let mut chars = line.trim_start().chars().peekable();
+    match chars.next() {
+        None => return Verdict::Rejected(Rejection::Empty),
+        Some('+') => {}
+        Some('0') => {
+            while chars.next_if(|c| c.is_whitespace()).is_some() {}
+            if chars.next_if_eq(&'0').is_none() {
+                return Verdict::Rejected(Rejection::MissingPrefix);
+            }
+        }
+        Some(c) if c.is_ascii_digit() => return Verdict::Rejected(Rejection::MissingPrefix),
+        Some(c) => return Verdict::Rejected(Rejection::IllegalCharacter(c)),
+    }
+    let mut digits = String::new();
+    let mut illegal = None;
+    for c in chars {
+        if c.is_ascii_digit() {
+            digits.push(c);
+        } else if !c.is_whitespace() {
+            illegal = Some(c);
+            break;
+        }
+    }
+    if digits.len() >= 2 && !digits.starts_with("36") {
+        return Verdict::Rejected(Rejection::WrongCountryCode);
+    }
+    // Budapest has the one-digit area code 1, other areas two digits, followed by 7 digits
+    let expected = match digits.as_bytes().get(2) {
+        Some(b'0') => return Verdict::Rejected(Rejection::InvalidAreaCode),
+        Some(b'1') => 2 + 1 + 7,
+        _ => 2 + 2 + 7,
+    };
+    let rejection = match (illegal, digits.len()) {
+        (Some(_), len) if len == expected => Rejection::TrailingText,
+        (Some(c), len) if len < expected => Rejection::IllegalCharacter(c),
+        (None, len) if len < expected => Rejection::TooFewDigits,
+        (_, len) if len > expected => Rejection::TooManyDigits,
+        _ => return Verdict::Valid(format!("+{digits}")),
+    };
+    Verdict::Rejected(rejection)
+}
+
+/// The kind of service behind a Hungarian area code or mobile prefix.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum NumberType {
+    /// geographic, Budapest or a county area code
+    Fixed,
+    Mobile,
+    /// nomadic numbers, mostly VoIP
+    Voip,
+    SharedCost,
+    TollFree,
+    PremiumRate,
+    /// an unassigned area code
+    Other,
+}
+
+impl NumberType {
+    /// The name of the type, as serialized, e.g. `tollFree`.
+    pub fn code(self) -> &'static str {
+        match self {
+            NumberType::Fixed => "fixed",
+            NumberType::Mobile => "mobile",
+            NumberType::Voip => "voip",
+            NumberType::SharedCost => "sharedCost",
+            NumberType::TollFree => "tollFree",
+            NumberType::PremiumRate => "premiumRate",
+            NumberType::Other => "other",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Classification<'a> {
+    /// the area code or mobile prefix, e.g. `1` for Budapest or `30` for a mobile operator
+    pub area_code: &'a str,
+    pub number_type: NumberType,
+}
+
+/// Classifies a Hungarian number in E.164, as returned by [`verdict`].
+///
+/// Returns `None` for other numbers.
+pub fn classify(number: &str) -> Option<Classification<'_>> {
+    let national = number.strip_prefix("+36")?;
+    let area_code = match national.as_bytes() {
+        [b'1', ..] => &national[..1],
+        [b'2'..=b'9', b'0'..=b'9', ..] => &national[..2],
+        _ => return None,
+    };
+    let number_type = match area_code {
+        "20" | "30" | "31" | "50" | "70" => NumberType::Mobile,
+        "21" => NumberType::Voip,
+        "40" => NumberType::SharedCost,
+        "80" => NumberType::TollFree,
+        "90" | "91" => NumberType::PremiumRate,
+        "1" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" | "32" | "33" | "34" | "35"
+        | "36" | "37" | "42" | "44" | "45" | "46" | "47" | "48" | "49" | "52" | "53" | "54"
+        | "56" | "57" | "59" | "62" | "63" | "66" | "68" | "69" | "72" | "73" | "74" | "75"
+        | "76" | "77" | "78" | "79" | "82" | "83" | "84" | "85" | "87" | "88" | "89" | "92"
+        | "93" | "94" | "95" | "96" | "99" => NumberType::Fixed,
+        _ => NumberType::Other,
+    };
+    Some(Classification {
+        area_code,
+        number_type,
+    })
+}
+
+/// The ITU-T E.164 country code at the start of `digits`, e.g. `49` of `4930123456`.
+///
+/// Returns `None` if there are too few digits.
+pub fn country_code(digits: &str) -> Option<&str> {
+    let len = match digits.as_bytes() {
+        [b'1' | b'7', ..] => 1,
+        [b'2', b'0' | b'7', ..]
+        | [b'3', b'0'..=b'4' | b'6' | b'9', ..]
+        | [b'4', b'0' | b'1' | b'3'..=b'9', ..]
+        | [b'5', b'1'..=b'8', ..]
+        | [b'6', b'0'..=b'6', ..]
+        | [b'8', b'1' | b'2' | b'4' | b'6', ..]
+        | [b'9', b'0'..=b'5' | b'8', ..] => 2,
+        _ => 3,
+    };
+    digits
+        .get(..len)
+        .filter(|code| code.bytes().all(|b| b.is_ascii_digit()))
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,210 @@
+use regex::Regex;
+
+use super::*;
+
+#[test]
+fn test_normalize_plus_prefix() {
+    // when
+    let normalized = normalize(" +36 1 234 5678 ");
+
+    // then
+    assert_eq!(normalized.as_deref(), Some("+3612345678"));
+}
+
+#[test]
+fn test_normalize_double_zero_prefix() {
+    // when
+    let normalized = normalize("0 0 36 30 123 4567");
+
+    // then
+    assert_eq!(normalized.as_deref(), Some("+36301234567"));
+}
+
+#[test]
+fn test_normalize_national_format() {
+    // when
+    let normalized = normalize("06 30 123 4567");
+
+    // then
+    assert_eq!(normalized, None);
+}
+
+#[test]
+fn test_hu_pattern() {
+    // given
+    let regex = Regex::new(HU_PATTERN).unwrap();
+
+    // then
+    assert!(regex.is_match("+36 1 234 5678"));
+    assert!(regex.is_match("0036301234567"));
+    assert!(!regex.is_match("+36 1 234 567"));
+    assert!(!regex.is_match("+49 30 1234567"));
+}
+
+const SAMPLES: &str = include_str!("../../../../../artifacts/phone_numbers.txt");
+
+#[test]
+fn test_verdict_agrees_with_hu_pattern_on_samples() {
+    // given
+    let regex = Regex::new(HU_PATTERN).unwrap();
+
+    for line in SAMPLES.lines() {
+        // when
+        let verdict = verdict(line);
+
+        // then
+        assert_eq!(
+            matches!(verdict, Verdict::Valid(_)),
+            regex.is_match(line),
+            "{line:?}"
+        );
+        if let Verdict::Valid(number) = verdict {
+            assert_eq!(normalize(line), Some(number));
+        }
+    }
+}
+
+#[test]
+fn test_verdict_agrees_with_hu_pattern_on_edited_samples() {
+    // given
+    let regex = Regex::new(HU_PATTERN).unwrap();
+    let edits: [fn(&str) -> String; 6] = [
+        |line| format!("{line}7"),
+        |line| line.trim_end()[..line.trim_end().len() - 1].to_string(),
+        |line| format!("{line} ext"),
+        |line| line.replacen(' ', "-", 1),
+        |line| line.replacen("36", "360", 1),
+        |line| line.replacen("00", "06", 1),
+    ];
+
+    for line in SAMPLES.lines() {
+        for edit in edits {
+            // when
+            let line = edit(line);
+
+            // then
+            assert_eq!(
+                matches!(verdict(&line), Verdict::Valid(_)),
+                regex.is_match(&line),
+                "{line:?}"
+            );
+        }
+    }
+}
+
+#[test]
+fn test_verdict_of_samples() {
+    // then
+    assert_eq!(
+        verdict("  +   36  18424259"),
+        Verdict::Valid("+3618424259".into())
+    );
+    assert_eq!(
+        verdict("   00  36   47 915   7463"),
+        Verdict::Valid("+36479157463".into())
+    );
+    assert_eq!(
+        verdict(" 00 49   888 86352350"),
+        Verdict::Rejected(Rejection::WrongCountryCode)
+    );
+    assert_eq!(
+        verdict("+ 4845  3946752"),
+        Verdict::Rejected(Rejection::WrongCountryCode)
+    );
+}
+
+#[test]
+fn test_rejections() {
+    // given
+    let cases = [
+        ("", Rejection::Empty),
+        ("   ", Rejection::Empty),
+        ("06 30 123 4567", Rejection::MissingPrefix),
+        ("36 30 123 4567", Rejection::MissingPrefix),
+        ("+49 30 1234567", Rejection::WrongCountryCode),
+        ("+36 06 123 4567", Rejection::InvalidAreaCode),
+        ("+36 1 234 567", Rejection::TooFewDigits),
+        ("+36 30 123 456", Rejection::TooFewDigits),
+        ("+36 1 234 56789", Rejection::TooManyDigits),
+        ("+36 30 123 4567 8", Rejection::TooManyDigits),
+        ("+36 (30) 123 4567", Rejection::IllegalCharacter('(')),
+        ("+36-1-234-5678", Rejection::IllegalCharacter('-')),
+        ("tel: +36 1 234 5678", Rejection::IllegalCharacter('t')),
+        ("+36 1 234 5678 (home)", Rejection::TrailingText),
+        ("+36 30 123 4567;", Rejection::TrailingText),
+    ];
+
+    for (line, rejection) in cases {
+        // when
+        let verdict = verdict(line);
+
+        // then
+        assert_eq!(verdict, Verdict::Rejected(rejection), "{line:?}");
+    }
+}
+
+#[test]
+fn test_rejection_codes() {
+    // then
+    assert_eq!(Rejection::IllegalCharacter('-').code(), "illegal_character");
+    assert_eq!(
+        Rejection::IllegalCharacter('-').to_string(),
+        "illegal character '-'"
+    );
+}
+
+#[test]
+fn test_classify() {
+    // then
+    assert_eq!(
+        classify("+3612345678"),
+        Some(Classification {
+            area_code: "1",
+            number_type: NumberType::Fixed,
+        })
+    );
+    assert_eq!(
+        classify("+36301234567"),
+        Some(Classification {
+            area_code: "30",
+            number_type: NumberType::Mobile,
+        })
+    );
+    assert_eq!(
+        classify("+36801234567").map(|c| c.number_type),
+        Some(NumberType::TollFree)
+    );
+    assert_eq!(
+        classify("+36381234567").map(|c| c.number_type),
+        Some(NumberType::Other)
+    );
+    assert_eq!(classify("+49301234567"), None);
+}
+
+#[test]
+fn test_country_code() {
+    // then
+    assert_eq!(country_code("3612345678"), Some("36"));
+    assert_eq!(country_code("4930123456"), Some("49"));
+    assert_eq!(country_code("12025550123"), Some("1"));
+    assert_eq!(country_code("420123456789"), Some("420"));
+    assert_eq!(country_code("42"), None);
+}
+
+#[test]
+fn test_number_type_code_matches_serde() {
+    for number_type in [
+        NumberType::Fixed,
+        NumberType::Mobile,
+        NumberType::Voip,
+        NumberType::SharedCost,
+        NumberType::TollFree,
+        NumberType::PremiumRate,
+        NumberType::Other,
+    ] {
+        assert_eq!(
+            serde_json::to_value(number_type).unwrap(),
+            number_type.code()
+        );
+    }
+}
@@ -0,0 +1,36 @@
+use super::digits;
+
+pub const PATTERN: &str = r"\b[0-9](?:[ -]?[0-9]){12,18}\b";
+
+/// Checks the issuer prefix of the major card schemes and the Luhn checksum.
+pub fn is_valid(candidate: &str) -> bool {
+    let digits = digits(candidate);
+    let prefix = |length: usize| {
+        digits[..length]
+            .iter()
+            .fold(0, |number, &digit| number * 10 + digit)
+    };
+    let known_issuer = match digits[0] {
+        // Visa
+        4 => true,
+        // Mastercard
+        2 => (2221..=2720).contains(&prefix(4)),
+        5 => (51..=55).contains(&prefix(2)),
+        // American Express, JCB
+        3 => matches!(prefix(2), 34 | 37) || (3528..=3589).contains(&prefix(4)),
+        // Discover
+        6 => prefix(4) == 6011 || prefix(2) == 65,
+        _ => false,
+    };
+    let sum: u32 = digits
+        .iter()
+        .rev()
+        .enumerate()
+        .map(|(index, &digit)| match index % 2 {
+            0 => digit,
+            _ if digit > 4 => digit * 2 - 9,
+            _ => digit * 2,
+        })
+        .sum();
+    known_issuer && sum.is_multiple_of(10)
+}
@@ -0,0 +1,19 @@
+pub const PATTERN: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9-]+(?:\.[A-Za-z0-9-]+)*\.[A-Za-z]{2,}\b";
+
+/// Checks the limits of RFC 5321 and the dots and hyphens the pattern lets through.
+pub fn is_valid(candidate: &str) -> bool {
+    let Some((local, domain)) = candidate.rsplit_once('@') else {
+        return false;
+    };
+    candidate.len() <= 254
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && domain.split('.').all(|label| {
+            !label.is_empty()
+                && label.len() <= 63
+                && !label.starts_with('-')
+                && !label.ends_with('-')
+        })
+}
@@ -0,0 +1,40 @@
+pub const PATTERN: &str = r"\b[A-Z]{2}[0-9]{2}(?: ?[A-Z0-9]){11,30}\b";
+
+/// The IBAN lengths of the SEPA countries most often seen, other countries are only checked for
+/// the 15 to 34 characters of any IBAN.
+const LENGTHS: [(&str, usize); 12] = [
+    ("AT", 20),
+    ("BE", 16),
+    ("CZ", 24),
+    ("DE", 22),
+    ("ES", 24),
+    ("FR", 27),
+    ("GB", 22),
+    ("HU", 28),
+    ("IT", 27),
+    ("NL", 18),
+    ("PL", 28),
+    ("SK", 24),
+];
+
+/// Checks the length of the country and the ISO 13616 mod 97 checksum.
+pub fn is_valid(candidate: &str) -> bool {
+    let iban: Vec<u8> = candidate.bytes().filter(|&b| b != b' ').collect();
+    let length = LENGTHS
+        .iter()
+        .find(|(country, _)| country.as_bytes() == &iban[..2])
+        .map(|&(_, length)| length);
+    match length {
+        Some(length) if iban.len() != length => return false,
+        None if !(15..=34).contains(&iban.len()) => return false,
+        _ => {}
+    }
+    let remainder = iban[4..]
+        .iter()
+        .chain(&iban[..4])
+        .fold(0u32, |remainder, &b| match b {
+            b'0'..=b'9' => (remainder * 10 + u32::from(b - b'0')) % 97,
+            _ => (remainder * 100 + u32::from(b - b'A') + 10) % 97,
+        });
+    remainder == 1
+}
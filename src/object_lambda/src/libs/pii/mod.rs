@@ -0,0 +1,129 @@
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+mod card;
+mod email;
+mod iban;
+mod taj;
+mod tax_id;
+
+/// A kind of personal data the [`Detector`] finds in free text.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "camelCase")]
+pub enum Entity {
+    Email,
+    Iban,
+    /// Hungarian tax identification number (adóazonosító jel)
+    TaxId,
+    /// Hungarian social security number (TAJ szám)
+    Taj,
+    /// payment card number passing the Luhn check
+    Card,
+}
+
+impl Entity {
+    pub const ALL: [Entity; 5] = [
+        Entity::Email,
+        Entity::Iban,
+        Entity::TaxId,
+        Entity::Taj,
+        Entity::Card,
+    ];
+
+    /// The label of the entity in the output, the same as its configuration name.
+    pub fn code(self) -> &'static str {
+        match self {
+            Entity::Email => "email",
+            Entity::Iban => "iban",
+            Entity::TaxId => "taxId",
+            Entity::Taj => "taj",
+            Entity::Card => "card",
+        }
+    }
+
+    fn pattern(self) -> &'static str {
+        match self {
+            Entity::Email => email::PATTERN,
+            Entity::Iban => iban::PATTERN,
+            Entity::TaxId => tax_id::PATTERN,
+            Entity::Taj => taj::PATTERN,
+            Entity::Card => card::PATTERN,
+        }
+    }
+
+    fn is_valid(self, candidate: &str) -> bool {
+        match self {
+            Entity::Email => email::is_valid(candidate),
+            Entity::Iban => iban::is_valid(candidate),
+            Entity::TaxId => tax_id::is_valid(candidate),
+            Entity::Taj => taj::is_valid(candidate),
+            Entity::Card => card::is_valid(candidate),
+        }
+    }
+}
+
+/// An entity found at `start..end` of a line.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Detection {
+    pub entity: Entity,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// Finds the entities in a line: candidates are matched by a pattern per entity, then checked by
+/// the validator of the entity, e.g. the IBAN or Luhn checksum.
+pub struct Detector {
+    patterns: Vec<(Entity, Regex)>,
+}
+
+impl Detector {
+    pub fn new(entities: &[Entity]) -> Self {
+        Self {
+            patterns: entities
+                .iter()
+                .map(|&entity| (entity, Regex::new(entity.pattern()).unwrap()))
+                .collect(),
+        }
+    }
+
+    /// The valid entities of `line` in order. Overlapping ones are merged into a single span,
+    /// labelled by the longest of them.
+    pub fn detect(&self, line: &str) -> Vec<Detection> {
+        let mut candidates: Vec<_> = self
+            .patterns
+            .iter()
+            .flat_map(|(entity, regex)| {
+                regex
+                    .find_iter(line)
+                    .filter(|found| entity.is_valid(found.as_str()))
+                    .map(|found| Detection {
+                        entity: *entity,
+                        start: found.start(),
+                        end: found.end(),
+                    })
+            })
+            .collect();
+        candidates.sort_by_key(|detection| (detection.start, usize::MAX - detection.end));
+        let mut detections: Vec<Detection> = vec![];
+        for candidate in candidates {
+            match detections.last_mut() {
+                Some(last) if candidate.start < last.end => {
+                    if candidate.end - candidate.start > last.end - last.start {
+                        last.entity = candidate.entity;
+                    }
+                    last.end = last.end.max(candidate.end);
+                }
+                _ => detections.push(candidate),
+            }
+        }
+        detections
+    }
+}
+
+/// The decimal digits of `candidate`, ignoring separators.
+fn digits(candidate: &str) -> Vec<u32> {
+    candidate.chars().filter_map(|c| c.to_digit(10)).collect()
+}
+
+#[cfg(test)]
+mod tests;
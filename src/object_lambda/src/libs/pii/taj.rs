@@ -0,0 +1,15 @@
+use super::digits;
+
+pub const PATTERN: &str = r"\b[0-9]{3}[ -]?[0-9]{3}[ -]?[0-9]{3}\b";
+
+/// Checks the check digit: the sum of the first eight digits weighted alternately by 3 and 7,
+/// modulo 10.
+pub fn is_valid(candidate: &str) -> bool {
+    let digits = digits(candidate);
+    let sum: u32 = digits[..8]
+        .iter()
+        .zip([3, 7].into_iter().cycle())
+        .map(|(digit, weight)| digit * weight)
+        .sum();
+    sum % 10 == digits[8]
+}
@@ -0,0 +1,15 @@
+use super::digits;
+
+pub const PATTERN: &str = r"\b8[0-9]{9}\b";
+
+/// Checks the check digit: the sum of the first nine digits weighted by their position,
+/// modulo 11.
+pub fn is_valid(candidate: &str) -> bool {
+    let digits = digits(candidate);
+    let sum: u32 = digits[..9]
+        .iter()
+        .zip(1..)
+        .map(|(digit, weight)| digit * weight)
+        .sum();
+    sum % 11 == digits[9]
+}
@@ -0,0 +1,115 @@
+use super::*;
+
+fn detect(entities: &[Entity], line: &str) -> Vec<(Entity, String)> {
+    Detector::new(entities)
+        .detect(line)
+        .into_iter()
+        .map(|detection| {
+            (
+                detection.entity,
+                line[detection.start..detection.end].to_string(),
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_validators() {
+    for (entity, candidate, valid) in [
+        (Entity::Email, "jane.doe+news@mail.example.hu", true),
+        (Entity::Email, "jane..doe@example.hu", false),
+        (Entity::Email, "jane@-example.hu", false),
+        (Entity::Iban, "HU42 1177 3016 1111 1018 0000 0000", true),
+        (Entity::Iban, "HU42117730161111101800000000", true),
+        (Entity::Iban, "DE89 3704 0044 0532 0130 00", true),
+        (Entity::Iban, "GB82 WEST 1234 5698 7654 32", true),
+        (Entity::Iban, "HU43 1177 3016 1111 1018 0000 0000", false),
+        (Entity::Iban, "DE89 3704 0044 0532 0130", false),
+        (Entity::TaxId, "8123456786", true),
+        (Entity::TaxId, "8123456787", false),
+        (Entity::Taj, "123 456 788", true),
+        (Entity::Taj, "123-456-788", true),
+        (Entity::Taj, "123456789", false),
+        (Entity::Card, "4111 1111 1111 1111", true),
+        (Entity::Card, "5500-0000-0000-0004", true),
+        (Entity::Card, "378282246310005", true),
+        (Entity::Card, "4111 1111 1111 1112", false),
+        // passes the Luhn check, but no card scheme issues it
+        (Entity::Card, "9111 1111 1111 1110", false),
+    ] {
+        // when
+        let is_valid = entity.is_valid(candidate);
+
+        // then
+        assert_eq!(is_valid, valid, "{candidate}");
+    }
+}
+
+#[test]
+fn test_detect_in_text() {
+    // given
+    let line = "Jane (jane@example.hu, TAJ 123 456 788, adószám 8123456786) \
+                paid with 4111-1111-1111-1111 to HU42 1177 3016 1111 1018 0000 0000";
+
+    // when
+    let detections = detect(&Entity::ALL, line);
+
+    // then
+    assert_eq!(
+        detections,
+        [
+            (Entity::Email, "jane@example.hu".into()),
+            (Entity::Taj, "123 456 788".into()),
+            (Entity::TaxId, "8123456786".into()),
+            (Entity::Card, "4111-1111-1111-1111".into()),
+            (Entity::Iban, "HU42 1177 3016 1111 1018 0000 0000".into()),
+        ]
+    );
+}
+
+#[test]
+fn test_only_selected_entities() {
+    // when
+    let detections = detect(&[Entity::Taj], "jane@example.hu 123 456 788");
+
+    // then
+    assert_eq!(detections, [(Entity::Taj, "123 456 788".into())]);
+}
+
+#[test]
+fn test_longest_overlapping_entity_wins() {
+    // given a card number whose first digits also pass as a TAJ number
+    let line = "card 411 111 119 111 1114";
+
+    // when
+    let detections = detect(&[Entity::Taj, Entity::Card], line);
+
+    // then
+    assert_eq!(detections, [(Entity::Card, "411 111 119 111 1114".into())]);
+}
+
+#[test]
+fn test_overlapping_entities_are_merged() {
+    // given an email whose local part is the end of a longer card number
+    let line = "paid with 4111 1111 1111 1111@example.hu";
+
+    // when
+    let detections = detect(&[Entity::Email, Entity::Card], line);
+
+    // then
+    assert_eq!(
+        detections,
+        [(Entity::Card, "4111 1111 1111 1111@example.hu".into())]
+    );
+}
+
+#[test]
+fn test_entity_code_matches_serde() {
+    for entity in Entity::ALL {
+        // when
+        let json = serde_json::to_value(entity).unwrap();
+
+        // then
+        assert_eq!(json, entity.code());
+    }
+}
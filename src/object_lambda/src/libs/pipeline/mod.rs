@@ -0,0 +1,404 @@
+use std::sync::Arc;
+
+use anyhow::bail;
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::libs::config::InputConfig;
+use crate::libs::deps::{env, reqwest, s3};
+use crate::libs::phone::HU_PATTERN;
+use crate::libs::stats::FilterStats;
+use crate::libs::stream_filter::aggregate::{AggregateConfig, AggregateStreamFilter};
+use crate::libs::stream_filter::archive::{ArchiveOutput, ArchiveStreamFilter};
+use crate::libs::stream_filter::compress::{CompressConfig, CompressStreamFilter};
+use crate::libs::stream_filter::csv::CsvStreamFilter;
+use crate::libs::stream_filter::csv::{CsvColumn, CsvOutput};
+use crate::libs::stream_filter::dedupe::{DedupeConfig, DedupeStreamFilter};
+use crate::libs::stream_filter::enrich::{EnrichConfig, EnrichStreamFilter};
+use crate::libs::stream_filter::expression::{ExpressionConfig, ExpressionFilter};
+use crate::libs::stream_filter::jsonl::{JsonlOutput, JsonlStreamFilter};
+use crate::libs::stream_filter::limit::{LimitConfig, LimitStreamFilter};
+use crate::libs::stream_filter::line::LineStreamFilter;
+use crate::libs::stream_filter::numbers::{
+    ClassifyConfig, ClassifyFilter, Keep, MatchConfig, MatchFilter, NormalizeFilter,
+};
+use crate::libs::stream_filter::pii::{PiiConfig, PiiFilter, PiiMode};
+use crate::libs::stream_filter::pseudonymize::{PseudonymizeConfig, PseudonymizeStreamFilter};
+use crate::libs::stream_filter::rewrite::{RewriteConfig, RewriteFilter};
+use crate::libs::stream_filter::sample::{SampleConfig, SampleStreamFilter};
+use crate::libs::stream_filter::script::{ScriptConfig, ScriptStreamFilter};
+use crate::libs::stream_filter::serialize::{SerializeConfig, SerializeStreamFilter};
+use crate::libs::stream_filter::sort::{SortConfig, SortStreamFilter};
+use crate::libs::stream_filter::spreadsheet::SpreadsheetStreamFilter;
+use crate::libs::stream_filter::suppression::{
+    NumberField, SuppressionConfig, SuppressionList, SuppressionStreamFilter,
+};
+use crate::libs::stream_filter::vcard::VcardStreamFilter;
+use crate::libs::stream_filter::wasm::{WasmConfig, WasmStreamFilter};
+use crate::libs::stream_filter::{
+    ChainStreamFilter, DynStreamFilter, LineFormat, RegexStreamFilter,
+};
+
+/// A stage of a pipeline, applied to the output of the previous one.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "stage", rename_all = "camelCase")]
+pub enum StageConfig {
+    /// parses the input format into lines, text lines are passed as they are
+    Decode(InputConfig),
+    Match(MatchConfig),
+    Normalize,
+    Classify(ClassifyConfig),
+    /// keeps the lines for which a boolean expression holds
+    Filter(ExpressionConfig),
+    /// finds, keeps or redacts personal data other than phone numbers
+    Pii(PiiConfig),
+    /// runs a WebAssembly line filter plugin
+    Wasm(WasmConfig),
+    /// runs a Rhai script on every line
+    Script(ScriptConfig),
+    /// appends the data of a lookup service to every line
+    Enrich(EnrichConfig),
+    Suppression(SuppressionConfig),
+    Dedupe(DedupeConfig),
+    Sample(SampleConfig),
+    Sort(SortConfig),
+    Limit(LimitConfig),
+    Pseudonymize(PseudonymizeConfig),
+    Aggregate(AggregateConfig),
+    /// outputs the numbers in a custom layout
+    Rewrite(RewriteConfig),
+    Serialize(SerializeConfig),
+    Compress(CompressConfig),
+}
+
+impl StageConfig {
+    pub fn name(&self) -> &'static str {
+        match self {
+            StageConfig::Decode(_) => "decode",
+            StageConfig::Match(_) => "match",
+            StageConfig::Normalize => "normalize",
+            StageConfig::Classify(_) => "classify",
+            StageConfig::Filter(_) => "filter",
+            StageConfig::Pii(_) => "pii",
+            StageConfig::Wasm(_) => "wasm",
+            StageConfig::Script(_) => "script",
+            StageConfig::Enrich(_) => "enrich",
+            StageConfig::Suppression(_) => "suppression",
+            StageConfig::Dedupe(_) => "dedupe",
+            StageConfig::Sample(_) => "sample",
+            StageConfig::Sort(_) => "sort",
+            StageConfig::Limit(_) => "limit",
+            StageConfig::Pseudonymize(_) => "pseudonymize",
+            StageConfig::Aggregate(_) => "aggregate",
+            StageConfig::Rewrite(_) => "rewrite",
+            StageConfig::Serialize(_) => "serialize",
+            StageConfig::Compress(_) => "compress",
+        }
+    }
+
+    /// The output of the stage given its input, if it accepts that input.
+    fn output(&self, input: Output) -> Option<Output> {
+        match (self, input) {
+            (StageConfig::Decode(_), _) => None,
+            (StageConfig::Compress(_), Output::Binary) => None,
+            (StageConfig::Compress(_), _) => Some(Output::Binary),
+            (StageConfig::Dedupe(_) | StageConfig::Sample(_) | StageConfig::Limit(_), input)
+                if input == Output::Lines || input == Output::Pseudonyms =>
+            {
+                Some(input)
+            }
+            (_, Output::Lines) => Some(match self {
+                StageConfig::Pseudonymize(_) => Output::Pseudonyms,
+                StageConfig::Aggregate(_) => Output::Report,
+                StageConfig::Pii(PiiConfig {
+                    mode: PiiMode::Extract,
+                    ..
+                })
+                | StageConfig::Enrich(_)
+                | StageConfig::Rewrite(_)
+                | StageConfig::Serialize(_) => Output::Records,
+                _ => Output::Lines,
+            }),
+            _ => None,
+        }
+    }
+
+    fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            StageConfig::Decode(input) => input.validate(),
+            StageConfig::Classify(classify) => classify.validate(),
+            StageConfig::Filter(expression) => expression.validate(),
+            StageConfig::Pii(pii) => pii.validate(),
+            StageConfig::Wasm(wasm) => wasm.validate(),
+            StageConfig::Script(script) => script.validate(),
+            StageConfig::Enrich(enrich) => enrich.validate(),
+            StageConfig::Suppression(suppression) => suppression.validate(),
+            StageConfig::Dedupe(dedupe) => dedupe.validate(),
+            StageConfig::Sample(sample) => sample.validate(),
+            StageConfig::Sort(sort) => sort.validate(),
+            StageConfig::Limit(limit) => limit.validate(),
+            StageConfig::Pseudonymize(pseudonymize) => pseudonymize.validate(),
+            StageConfig::Aggregate(aggregate) => aggregate.validate(),
+            StageConfig::Rewrite(rewrite) => rewrite.validate(),
+            StageConfig::Compress(compress) => compress.validate(),
+            StageConfig::Match(_) | StageConfig::Normalize | StageConfig::Serialize(_) => Ok(()),
+        }
+    }
+}
+
+/// What flows out of a stage, which decides the stages that may follow it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Output {
+    /// text lines, which may hold numbers
+    Lines,
+    /// lines of keyed hashes, without numbers
+    Pseudonyms,
+    /// serialized records
+    Records,
+    /// a single JSON report
+    Report,
+    /// a filtered archive or compressed output
+    Binary,
+}
+
+/// Checks that every stage accepts the output of the previous one, returning the output of
+/// the last stage.
+pub fn validate(stages: &[StageConfig]) -> anyhow::Result<Output> {
+    let Some(StageConfig::Decode(input)) = stages.first() else {
+        bail!("a pipeline starts with a decode stage");
+    };
+    let mut output = match input {
+        InputConfig::Archive(archive) if archive.output == ArchiveOutput::Archive => Output::Binary,
+        _ => Output::Lines,
+    };
+    let mut previous = stages[0].name();
+    // the suppression stage finds the numbers where the decode stage put them
+    let mut rewritten_by = None;
+    stages[0].validate()?;
+    for stage in &stages[1..] {
+        match stage {
+            StageConfig::Decode(_) => bail!("a pipeline has a single decode stage"),
+            StageConfig::Suppression(_) => {
+                if let Some(rewriter) = rewritten_by {
+                    bail!(
+                        "suppression cannot find the numbers of lines rewritten by `{rewriter}`, \
+                         it has to come first"
+                    );
+                }
+            }
+            StageConfig::Wasm(_) | StageConfig::Script(_) => rewritten_by = Some(stage.name()),
+            _ => (),
+        }
+        let Some(next) = stage.output(output) else {
+            bail!(
+                "stage `{}` cannot follow `{previous}`, which outputs {output:?}",
+                stage.name()
+            );
+        };
+        stage.validate()?;
+        output = next;
+        previous = stage.name();
+    }
+    let suppressions = stages
+        .iter()
+        .filter(|stage| matches!(stage, StageConfig::Suppression(_)))
+        .count();
+    if suppressions > 1 {
+        bail!("a pipeline has at most one suppression stage");
+    }
+    Ok(output)
+}
+
+/// Whether every line output by the stages is a line of their input, if rewritten in place, so
+/// that they only drop lines rather than split, join or add them.
+pub fn keeps_input_lines(stages: &[StageConfig]) -> bool {
+    stages.iter().all(|stage| match stage {
+        StageConfig::Decode(input) => *input == InputConfig::Text,
+        StageConfig::Pii(pii) => pii.mode != PiiMode::Extract,
+        StageConfig::Match(_)
+        | StageConfig::Normalize
+        | StageConfig::Classify(_)
+        | StageConfig::Filter(_)
+        | StageConfig::Suppression(_)
+        | StageConfig::Dedupe(_)
+        | StageConfig::Sample(_)
+        | StageConfig::Sort(_)
+        | StageConfig::Limit(_)
+        | StageConfig::Pseudonymize(_) => true,
+        // a line may become several, or records with a header row or a report
+        StageConfig::Wasm(_)
+        | StageConfig::Script(_)
+        | StageConfig::Enrich(_)
+        | StageConfig::Aggregate(_)
+        | StageConfig::Rewrite(_)
+        | StageConfig::Serialize(_)
+        | StageConfig::Compress(_) => false,
+    })
+}
+
+/// Where the lines output by a decode stage hold their number.
+fn decoded_number_field(input: &InputConfig) -> NumberField {
+    match input {
+        InputConfig::Csv(csv) if csv.output == CsvOutput::Row => NumberField::Csv {
+            column: csv.column.clone(),
+            delimiter: csv.delimiter as u8,
+            has_header: csv.has_header,
+        },
+        InputConfig::Jsonl(jsonl) if jsonl.output == JsonlOutput::Records => {
+            NumberField::Json(jsonl.fields.clone())
+        }
+        // the normalized number, then the type and name
+        InputConfig::Vcard(vcard) if vcard.include_type || vcard.include_name => NumberField::Csv {
+            column: CsvColumn::Index(0),
+            delimiter: b',',
+            has_header: false,
+        },
+        // the entry name, then the line
+        InputConfig::Archive(archive)
+            if archive.include_entry_name && archive.output == ArchiveOutput::Combined =>
+        {
+            NumberField::Csv {
+                column: CsvColumn::Index(1),
+                delimiter: b',',
+                has_header: false,
+            }
+        }
+        _ => NumberField::Line,
+    }
+}
+
+/// How the lines output by a decode stage are delimited: the fields of the CSV records it writes
+/// may hold line breaks.
+fn decoded_line_format(field: &NumberField) -> LineFormat {
+    match field {
+        NumberField::Csv { has_header, .. } => LineFormat::Csv {
+            has_header: *has_header,
+        },
+        _ => LineFormat::Text,
+    }
+}
+
+/// The stream filter of a validated pipeline, with the state shared across invocations.
+pub struct Pipeline {
+    pub filter: Arc<DynStreamFilter>,
+    /// refreshed before every invocation
+    pub suppression: Option<Arc<SuppressionList>>,
+    /// what the stages report for the stats of an invocation
+    pub stats: Arc<FilterStats>,
+    pub output: Output,
+    /// the stages before and after the first `match` of the valid numbers, for a quarantine to
+    /// stand in for that stage
+    pub around_match: Option<(Arc<DynStreamFilter>, Arc<DynStreamFilter>)>,
+}
+
+impl Pipeline {
+    /// Builds the stages, loading the suppression list and the pseudonymization key.
+    pub async fn build(
+        stages: &[StageConfig],
+        env: &env::Env,
+        s3: &s3::S3,
+        reqwest: &Arc<reqwest::Reqwest>,
+    ) -> anyhow::Result<Self> {
+        let output = validate(stages)?;
+        let regex = Regex::new(HU_PATTERN)?;
+        let mut filters: Vec<Arc<DynStreamFilter>> = vec![];
+        let mut suppression = None;
+        let mut number_field = NumberField::Line;
+        let mut line_format = LineFormat::Text;
+        let mut match_at = None;
+        let stats = Arc::new(FilterStats::default());
+        for stage in stages {
+            if let StageConfig::Decode(input) = stage {
+                number_field = decoded_number_field(input);
+                line_format = decoded_line_format(&number_field);
+            }
+            let filter: Arc<DynStreamFilter> = match stage.clone() {
+                StageConfig::Decode(input) => match input {
+                    InputConfig::Text => continue,
+                    InputConfig::Csv(csv) => Arc::new(CsvStreamFilter::new(regex.clone(), csv)),
+                    InputConfig::Jsonl(jsonl) => {
+                        Arc::new(JsonlStreamFilter::new(regex.clone(), jsonl, stats.clone()))
+                    }
+                    InputConfig::Vcard(vcard) => {
+                        Arc::new(VcardStreamFilter::new(regex.clone(), vcard))
+                    }
+                    InputConfig::Archive(archive) => {
+                        Arc::new(ArchiveStreamFilter::new(regex.clone(), archive))
+                    }
+                    InputConfig::Spreadsheet(spreadsheet) => {
+                        Arc::new(SpreadsheetStreamFilter::new(regex.clone(), spreadsheet))
+                    }
+                },
+                StageConfig::Match(MatchConfig { keep: Keep::Valid }) => {
+                    match_at.get_or_insert(filters.len());
+                    Arc::new(RegexStreamFilter::new(regex.clone()))
+                }
+                StageConfig::Match(config) => {
+                    Arc::new(LineStreamFilter::new(MatchFilter::new(config)))
+                }
+                StageConfig::Normalize => Arc::new(LineStreamFilter::new(NormalizeFilter::new())),
+                StageConfig::Classify(config) => {
+                    Arc::new(LineStreamFilter::new(ClassifyFilter::new(config)))
+                }
+                StageConfig::Filter(config) => {
+                    Arc::new(LineStreamFilter::new(ExpressionFilter::new(&config)?))
+                }
+                StageConfig::Pii(config) => {
+                    Arc::new(LineStreamFilter::new(PiiFilter::new(&config)))
+                }
+                StageConfig::Wasm(config) => Arc::new(WasmStreamFilter::load(config, s3).await?),
+                StageConfig::Script(config) => Arc::new(ScriptStreamFilter::new(&config)?),
+                StageConfig::Enrich(config) => {
+                    Arc::new(EnrichStreamFilter::new(config, reqwest.clone()))
+                }
+                StageConfig::Suppression(config) => {
+                    let list = Arc::new(SuppressionList::new(config));
+                    list.refresh(s3).await?;
+                    suppression = Some(list.clone());
+                    Arc::new(SuppressionStreamFilter::new(
+                        regex.clone(),
+                        list,
+                        number_field.clone(),
+                    ))
+                }
+                StageConfig::Dedupe(config) => Arc::new(DedupeStreamFilter::new(
+                    regex.clone(),
+                    config,
+                    stats.clone(),
+                    line_format,
+                )),
+                StageConfig::Sample(config) => {
+                    Arc::new(SampleStreamFilter::new(config, line_format))
+                }
+                StageConfig::Sort(config) => Arc::new(SortStreamFilter::new(regex.clone(), config)),
+                StageConfig::Limit(config) => Arc::new(LimitStreamFilter::new(config, line_format)),
+                StageConfig::Pseudonymize(config) => {
+                    Arc::new(PseudonymizeStreamFilter::new(regex.clone(), config, env)?)
+                }
+                StageConfig::Aggregate(config) => Arc::new(AggregateStreamFilter::new(config)),
+                StageConfig::Rewrite(config) => {
+                    Arc::new(LineStreamFilter::new(RewriteFilter::new(&config)?))
+                }
+                StageConfig::Serialize(config) => Arc::new(SerializeStreamFilter::new(config)),
+                StageConfig::Compress(config) => Arc::new(CompressStreamFilter::new(config)),
+            };
+            filters.push(filter);
+        }
+        let around_match = match_at.map(|at| -> (Arc<DynStreamFilter>, Arc<DynStreamFilter>) {
+            (
+                Arc::new(ChainStreamFilter::new(filters[..at].to_vec())),
+                Arc::new(ChainStreamFilter::new(filters[at + 1..].to_vec())),
+            )
+        });
+        Ok(Self {
+            filter: Arc::new(ChainStreamFilter::new(filters)),
+            suppression,
+            stats,
+            output,
+            around_match,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,141 @@
+use std::str::from_utf8;
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+
+use super::*;
+
+fn stages(json: &str) -> Vec<StageConfig> {
+    serde_json::from_str(json).unwrap()
+}
+
+#[test]
+fn test_output_of_stages() {
+    // given
+    let stages = stages(
+        r#"[
+            {"stage": "decode", "format": "csv", "column": 0},
+            {"stage": "pseudonymize", "keyId": "k1"},
+            {"stage": "limit", "lines": 10},
+            {"stage": "compress", "format": "gzip"}
+        ]"#,
+    );
+
+    // when
+    let output = validate(&stages);
+
+    // then
+    assert_eq!(output.unwrap(), Output::Binary);
+}
+
+#[test]
+fn test_stage_rejecting_its_input() {
+    for json in [
+        // match needs numbers, not pseudonyms
+        r#"[{"stage": "decode", "format": "text"}, {"stage": "pseudonymize", "keyId": "k1"}, {"stage": "match"}]"#,
+        // nothing but compression follows a report
+        r#"[{"stage": "decode", "format": "text"}, {"stage": "aggregate"}, {"stage": "limit", "lines": 1}]"#,
+        // a filtered archive is already binary
+        r#"[{"stage": "decode", "format": "archive", "output": "archive"}, {"stage": "compress", "format": "gzip"}]"#,
+        r#"[{"stage": "normalize"}]"#,
+        r#"[{"stage": "decode", "format": "text"}, {"stage": "decode", "format": "text"}]"#,
+        // the script may move the numbers anywhere
+        r#"[{"stage": "decode", "format": "text"}, {"stage": "script", "script": "line"}, {"stage": "suppression", "bucket": "b", "key": "k"}]"#,
+        r#"[]"#,
+    ] {
+        // given
+        let stages = stages(json);
+
+        // when
+        let output = validate(&stages);
+
+        // then
+        assert!(output.is_err(), "{json}");
+    }
+}
+
+#[test]
+fn test_invalid_stage_config() {
+    // given
+    let stages =
+        stages(r#"[{"stage": "decode", "format": "text"}, {"stage": "limit", "lines": 0}]"#);
+
+    // when
+    let output = validate(&stages);
+
+    // then
+    assert!(output.is_err());
+}
+
+#[tokio::test]
+async fn test_build() {
+    // given
+    let stages = stages(
+        r#"[
+            {"stage": "decode", "format": "text"},
+            {"stage": "normalize"},
+            {"stage": "classify", "types": ["mobile"]},
+            {"stage": "serialize", "format": "csv"}
+        ]"#,
+    );
+    let input = stream::iter([Ok(Bytes::from_static(
+        b"+36 1 234 5678\n0036 30 123 4567\nhello\n",
+    ))]);
+
+    // when
+    let pipeline = Pipeline::build(
+        &stages,
+        &env::Env::faux(),
+        &s3::S3::faux(),
+        &Arc::new(reqwest::Reqwest::faux()),
+    )
+    .await
+    .unwrap();
+    let output: Vec<_> = pipeline
+        .filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    // then
+    assert_eq!(pipeline.output, Output::Records);
+    assert!(pipeline.suppression.is_none());
+    assert_eq!(
+        from_utf8(&output.concat()).unwrap(),
+        "number,areaCode,type\n+36301234567,30,mobile\n"
+    );
+}
+
+#[test]
+fn test_keeps_input_lines() {
+    for (json, keeps) in [
+        (
+            r#"[{"stage": "decode", "format": "text"}, {"stage": "pii"}, {"stage": "match"}, {"stage": "limit", "lines": 10}]"#,
+            true,
+        ),
+        // the header row is added
+        (
+            r#"[{"stage": "decode", "format": "text"}, {"stage": "match"}, {"stage": "serialize", "format": "csv"}]"#,
+            false,
+        ),
+        (
+            r#"[{"stage": "decode", "format": "text"}, {"stage": "script", "script": "line"}]"#,
+            false,
+        ),
+        // a record may span several lines
+        (
+            r#"[{"stage": "decode", "format": "csv", "column": 0}, {"stage": "match"}]"#,
+            false,
+        ),
+    ] {
+        // given
+        let stages = stages(json);
+
+        // when
+        let keeps_lines = keeps_input_lines(&stages);
+
+        // then
+        assert_eq!(keeps_lines, keeps, "{json}");
+    }
+}
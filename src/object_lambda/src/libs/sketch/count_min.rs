@@ -0,0 +1,40 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+const DEPTH: u64 = 4;
+
+/// Fixed-size frequency table which never underestimates a count, and overestimates it by at
+/// most `e / width` of all additions with a probability of `1 - e^-4`.
+pub(super) struct CountMinSketch {
+    width: u64,
+    counters: Vec<u32>,
+}
+
+impl CountMinSketch {
+    pub fn new(width: usize) -> Self {
+        Self {
+            width: width as u64,
+            counters: vec![0; width * DEPTH as usize],
+        }
+    }
+
+    /// Counts `hash` once more, returning its estimated count.
+    pub fn add(&mut self, hash: u64) -> u64 {
+        // double hashing: the column of row i is h1 + i * h2
+        let mut hasher = DefaultHasher::new();
+        hash.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+        let mut estimate = u32::MAX;
+        for row in 0..DEPTH {
+            let column = hash.wrapping_add(row.wrapping_mul(h2)) % self.width;
+            let counter = &mut self.counters[(row * self.width + column) as usize];
+            *counter = counter.saturating_add(1);
+            estimate = estimate.min(*counter);
+        }
+        estimate as u64
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.counters.len() * 4
+    }
+}
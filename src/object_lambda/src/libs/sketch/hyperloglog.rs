@@ -0,0 +1,50 @@
+/// Estimates the number of distinct hashes in `2^precision` one-byte registers,
+/// with a standard error of about `1.04 / sqrt(2^precision)`.
+pub(super) struct HyperLogLog {
+    precision: u32,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    pub fn new(precision: u8) -> Self {
+        Self {
+            precision: precision as u32,
+            registers: vec![0; 1 << precision],
+        }
+    }
+
+    pub fn add(&mut self, hash: u64) {
+        let index = (hash >> (64 - self.precision)) as usize;
+        // the sentinel bit bounds the rank when the remaining bits are all zero
+        let rest = (hash << self.precision) | (1 << (self.precision - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self
+            .registers
+            .iter()
+            .map(|&rank| 2f64.powi(-(rank as i32)))
+            .sum();
+        let estimate = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&rank| rank == 0).count();
+        // linear counting is more accurate while many registers are empty
+        if estimate <= 2.5 * m && zeros > 0 {
+            (m * (m / zeros as f64).ln()).round() as u64
+        } else {
+            estimate.round() as u64
+        }
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.registers.len()
+    }
+}
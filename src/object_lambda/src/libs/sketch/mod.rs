@@ -0,0 +1,166 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+use std::sync::Arc;
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+use self::count_min::CountMinSketch;
+use self::hyperloglog::HyperLogLog;
+
+mod count_min;
+mod hyperloglog;
+
+/// The widest count-min sketch, taking 64 MiB.
+const MAX_WIDTH: usize = 4 * 1024 * 1024;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SketchConfig {
+    /// HyperLogLog uses `2^precision` bytes, 14 giving a standard error of 0.8%
+    #[serde(default = "default_precision")]
+    pub precision: u8,
+    /// the number of heavy hitters reported
+    #[serde(default = "default_top_k")]
+    pub top_k: usize,
+    /// the count-min sketch uses `16 * width` bytes, a count is overestimated by at most
+    /// `2.72 / width` of the lines
+    #[serde(default = "default_width")]
+    pub width: usize,
+}
+
+fn default_precision() -> u8 {
+    14
+}
+
+fn default_top_k() -> usize {
+    10
+}
+
+fn default_width() -> usize {
+    64 * 1024
+}
+
+impl Default for SketchConfig {
+    fn default() -> Self {
+        Self {
+            precision: default_precision(),
+            top_k: default_top_k(),
+            width: default_width(),
+        }
+    }
+}
+
+impl SketchConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(4..=18).contains(&self.precision) {
+            bail!("sketch precision must be between 4 and 18");
+        }
+        if !(1..=1000).contains(&self.top_k) {
+            bail!("sketch topK must be between 1 and 1000");
+        }
+        if !(16..=MAX_WIDTH).contains(&self.width) {
+            bail!("sketch width must be between 16 and {MAX_WIDTH}");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SketchReport {
+    pub lines: u64,
+    /// estimated number of distinct values
+    pub distinct: u64,
+    /// the most frequent values, most frequent first, with their estimated counts
+    pub top: Vec<HeavyHitter>,
+    pub memory_bytes: usize,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct HeavyHitter {
+    pub value: String,
+    pub count: u64,
+}
+
+/// Approximate distinct count and heavy hitters of a stream of values, in fixed memory.
+pub struct Sketch {
+    lines: u64,
+    distinct: HyperLogLog,
+    counts: CountMinSketch,
+    top_k: usize,
+    /// the candidates for the heavy hitters with their estimated counts
+    top: HashMap<Arc<str>, u64>,
+    /// the candidates ordered by their counts, the least frequent one first
+    by_count: BTreeSet<(u64, Arc<str>)>,
+}
+
+impl Sketch {
+    pub fn new(config: &SketchConfig) -> Self {
+        Self {
+            lines: 0,
+            distinct: HyperLogLog::new(config.precision),
+            counts: CountMinSketch::new(config.width),
+            top_k: config.top_k,
+            top: HashMap::with_capacity(config.top_k),
+            by_count: BTreeSet::new(),
+        }
+    }
+
+    pub fn add(&mut self, value: &str) {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        let hash = hasher.finish();
+        self.lines += 1;
+        self.distinct.add(hash);
+        let count = self.counts.add(hash);
+        self.update_top(value, count);
+    }
+
+    /// Keeps `value` as a candidate if it is one already or `count` beats the least frequent
+    /// one, in `O(log top_k)`.
+    fn update_top(&mut self, value: &str, count: u64) {
+        if let Some((candidate, previous)) = self.top.get_key_value(value) {
+            let (candidate, previous) = (candidate.clone(), *previous);
+            self.by_count.remove(&(previous, candidate.clone()));
+            self.by_count.insert((count, candidate.clone()));
+            self.top.insert(candidate, count);
+            return;
+        }
+        if self.top.len() >= self.top_k {
+            match self.by_count.first() {
+                Some((min, _)) if count > *min => {}
+                _ => return,
+            }
+            if let Some((_, evicted)) = self.by_count.pop_first() {
+                self.top.remove(&evicted);
+            }
+        }
+        let candidate: Arc<str> = Arc::from(value);
+        self.by_count.insert((count, candidate.clone()));
+        self.top.insert(candidate, count);
+    }
+
+    pub fn report(&self) -> SketchReport {
+        let mut top: Vec<_> = self
+            .top
+            .iter()
+            .map(|(value, count)| HeavyHitter {
+                value: value.to_string(),
+                count: *count,
+            })
+            .collect();
+        top.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        SketchReport {
+            lines: self.lines,
+            distinct: self.distinct.estimate().min(self.lines),
+            top,
+            memory_bytes: self.distinct.size_bytes() + self.counts.size_bytes(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,89 @@
+use super::*;
+
+#[test]
+fn test_distinct_estimate() {
+    // given
+    let mut sketch = Sketch::new(&SketchConfig::default());
+
+    // when
+    for i in 0..200_000 {
+        sketch.add(&format!("+3630{:07}", i % 100_000));
+    }
+
+    // then
+    let report = sketch.report();
+    assert_eq!(report.lines, 200_000);
+    let error = (report.distinct as f64 - 100_000.0).abs() / 100_000.0;
+    assert!(error < 0.03, "{}", report.distinct);
+}
+
+#[test]
+fn test_distinct_estimate_of_few_values() {
+    // given
+    let mut sketch = Sketch::new(&SketchConfig::default());
+
+    // when
+    for value in ["a", "b", "c", "a"] {
+        sketch.add(value);
+    }
+
+    // then
+    assert_eq!(sketch.report().distinct, 3);
+}
+
+#[test]
+fn test_heavy_hitters() {
+    // given
+    let mut sketch = Sketch::new(&SketchConfig {
+        top_k: 3,
+        width: 1024,
+        ..Default::default()
+    });
+
+    // when
+    for i in 0..50_000 {
+        sketch.add(&format!("+3620{:07}", i));
+        if i % 10 == 0 {
+            sketch.add("+3611111111");
+        }
+        if i % 25 == 0 {
+            sketch.add("+3622222222");
+        }
+        if i % 100 == 0 {
+            sketch.add("+3633333333");
+        }
+    }
+
+    // then
+    let report = sketch.report();
+    let top: Vec<_> = report.top.iter().map(|h| h.value.as_str()).collect();
+    assert_eq!(top, ["+3611111111", "+3622222222", "+3633333333"]);
+    // overestimated by at most e / width of the lines, with high probability
+    let bound = (std::f64::consts::E / 1024.0 * report.lines as f64) as u64;
+    assert!(report.top[0].count >= 5_000 && report.top[0].count <= 5_000 + bound);
+    assert_eq!(report.memory_bytes, 16 * 1024 + 16 * 1024);
+}
+
+#[test]
+fn test_validate() {
+    // then
+    assert!(SketchConfig::default().validate().is_ok());
+    assert!(SketchConfig {
+        precision: 20,
+        ..Default::default()
+    }
+    .validate()
+    .is_err());
+    assert!(SketchConfig {
+        top_k: 0,
+        ..Default::default()
+    }
+    .validate()
+    .is_err());
+    assert!(SketchConfig {
+        width: MAX_WIDTH + 1,
+        ..Default::default()
+    }
+    .validate()
+    .is_err());
+}
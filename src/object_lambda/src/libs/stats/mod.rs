@@ -0,0 +1,519 @@
+use std::collections::BTreeMap;
+use std::str::from_utf8;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::time::Instant;
+
+use anyhow::{anyhow, bail};
+use aws_sdk_s3::primitives::ByteStream;
+use futures::{future, stream, StreamExt};
+use futures_core::Stream;
+use percent_encoding::percent_decode_str;
+use serde::{Deserialize, Serialize};
+
+use crate::libs::deps::s3;
+use crate::libs::phone::{self, Verdict};
+use crate::libs::sketch::{Sketch, SketchConfig, SketchReport};
+use crate::libs::stream_filter::StreamItem;
+
+type BoxedSendSyncUnpinStream<I> = Box<dyn Stream<Item = I> + Send + Sync + Unpin>;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct StatsConfig {
+    /// the `REPORTS_BUCKET` if not set
+    #[serde(default)]
+    pub bucket: String,
+    /// the stats of a task are written to `<prefix><task id>.json`
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// longer input lines are counted as too long
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+    /// adds the distinct count and the most frequent of the output lines
+    #[serde(default)]
+    pub sketch: Option<SketchConfig>,
+}
+
+fn default_prefix() -> String {
+    "stats/".into()
+}
+
+fn default_max_line_bytes() -> usize {
+    4096
+}
+
+impl StatsConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.bucket.is_empty() {
+            bail!("stats need a bucket, or the REPORTS_BUCKET environment variable");
+        }
+        match &self.sketch {
+            Some(sketch) => sketch.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    bytes_in: AtomicU64,
+    bytes_out: AtomicU64,
+    lines_read: AtomicU64,
+    lines_matched: AtomicU64,
+    lines_non_utf8: AtomicU64,
+    lines_too_long: AtomicU64,
+}
+
+impl Counters {
+    fn add(counter: &AtomicU64, value: u64) {
+        counter.fetch_add(value, Ordering::Relaxed);
+    }
+
+    fn get(counter: &AtomicU64) -> u64 {
+        counter.load(Ordering::Relaxed)
+    }
+}
+
+/// A value a filter reports about the input of an invocation.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(untagged)]
+pub enum FilterStat {
+    Count(u64),
+    Ratio(f64),
+}
+
+/// Values the filters report, e.g. the records they could not parse.
+///
+/// The filters are built once and outlive the invocations, so the values are cleared by every
+/// [`StatsWriter::start`]. Lambda runs one invocation at a time per instance.
+#[derive(Default)]
+pub struct FilterStats {
+    values: Mutex<BTreeMap<&'static str, FilterStat>>,
+}
+
+impl FilterStats {
+    /// Adds `count` to the count `name`.
+    pub fn count(&self, name: &'static str, count: u64) {
+        let mut values = self.values.lock().unwrap_or_else(PoisonError::into_inner);
+        let value = values.entry(name).or_insert(FilterStat::Count(0));
+        if let FilterStat::Count(total) = value {
+            *total = total.saturating_add(count);
+        }
+    }
+
+    /// Sets the ratio `name`, e.g. an estimated error rate.
+    pub fn ratio(&self, name: &'static str, ratio: f64) {
+        self.values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(name, FilterStat::Ratio(ratio));
+    }
+
+    fn clear(&self) {
+        self.values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clear();
+    }
+
+    pub fn snapshot(&self) -> BTreeMap<&'static str, FilterStat> {
+        self.values
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Report {
+    pub task_id: String,
+    pub input_etag: Option<String>,
+    pub filter_version: &'static str,
+    pub duration_ms: u64,
+    pub bytes_in: u64,
+    pub bytes_out: u64,
+    /// the line counts are left out for binary inputs, such as archives and workbooks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub lines: Option<LineReport>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sketch: Option<SketchReport>,
+    /// what the filters report, e.g. `jsonlMalformed`
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub filters: BTreeMap<&'static str, FilterStat>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LineReport {
+    pub read: u64,
+    /// lines written to the response
+    pub matched: u64,
+    /// left out if the stages may add or reshape lines, rather than just drop them
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rejected: Option<u64>,
+    pub non_utf8: u64,
+    pub too_long: u64,
+}
+
+/// The line counts of a report.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LineCounts {
+    /// binary inputs or outputs, whose lines are not counted
+    Off,
+    Counted,
+    /// every line written is a line read, so that the difference is what the stages dropped
+    WithRejected,
+}
+
+/// Writes a JSON stats document for every task after its response is complete,
+/// as S3 Object Lambda responses cannot carry trailers.
+pub struct StatsWriter {
+    config: StatsConfig,
+    line_counts: LineCounts,
+    filters: Arc<FilterStats>,
+}
+
+impl StatsWriter {
+    pub fn new(config: StatsConfig, line_counts: LineCounts, filters: Arc<FilterStats>) -> Self {
+        Self {
+            config,
+            line_counts,
+            filters,
+        }
+    }
+
+    pub fn start(&self) -> Invocation {
+        self.filters.clear();
+        Invocation {
+            counters: Some(Arc::new(Counters::default())),
+            sketch: self
+                .config
+                .sketch
+                .as_ref()
+                .map(|sketch| Arc::new(Mutex::new(Sketch::new(sketch)))),
+            filters: Some(self.filters.clone()),
+            line_counts: self.line_counts,
+            max_line_bytes: self.config.max_line_bytes,
+            started: Instant::now(),
+        }
+    }
+
+    /// Logs failures, as the response has been sent by now.
+    pub async fn write(&self, s3: &s3::S3, report: &Report) {
+        let key = format!("{}{}.json", self.config.prefix, report.task_id);
+        let result = match serde_json::to_vec(report) {
+            Ok(json) => s3
+                .put_object(
+                    &self.config.bucket,
+                    &key,
+                    ByteStream::from(json),
+                    "application/json",
+                )
+                .await
+                .map(|_| ())
+                .map_err(anyhow::Error::from),
+            Err(error) => Err(error.into()),
+        };
+        if let Err(error) = result {
+            tracing::warn!(
+                "could not write stats to s3://{}/{key}: {error:#}",
+                self.config.bucket
+            );
+        }
+    }
+}
+
+/// The counters of a single task, updated by taps on its streams.
+pub struct Invocation {
+    /// `None` if no stats are written
+    counters: Option<Arc<Counters>>,
+    sketch: Option<Arc<Mutex<Sketch>>>,
+    filters: Option<Arc<FilterStats>>,
+    line_counts: LineCounts,
+    max_line_bytes: usize,
+    started: Instant,
+}
+
+impl Invocation {
+    pub fn disabled() -> Self {
+        Self {
+            counters: None,
+            sketch: None,
+            filters: None,
+            line_counts: LineCounts::Off,
+            max_line_bytes: 0,
+            started: Instant::now(),
+        }
+    }
+
+    /// Counts the bytes of the input as it is fetched.
+    pub fn tap_input(
+        &self,
+        s: BoxedSendSyncUnpinStream<StreamItem>,
+    ) -> BoxedSendSyncUnpinStream<StreamItem> {
+        let Some(counters) = self.counters.clone() else {
+            return s;
+        };
+        Box::new(s.map(move |item| {
+            if let Ok(chunk) = &item {
+                Counters::add(&counters.bytes_in, chunk.len() as u64);
+            }
+            item
+        }))
+    }
+
+    /// Counts the lines of the decoded input, as the filters see them.
+    pub fn tap_lines(
+        &self,
+        s: BoxedSendSyncUnpinStream<StreamItem>,
+    ) -> BoxedSendSyncUnpinStream<StreamItem> {
+        let Some(counters) = self
+            .counters
+            .clone()
+            .filter(|_| self.line_counts != LineCounts::Off)
+        else {
+            return s;
+        };
+        let mut scanner = LineScanner::new(self.max_line_bytes);
+        Box::new(
+            s.map(Some)
+                .chain(stream::once(future::ready(None)))
+                .filter_map(move |item| {
+                    let item = match item {
+                        Some(Ok(chunk)) => {
+                            scanner.scan(&chunk, &counters);
+                            Some(Ok(chunk))
+                        }
+                        Some(Err(error)) => Some(Err(error)),
+                        None => {
+                            scanner.finish(&counters);
+                            None
+                        }
+                    };
+                    future::ready(item)
+                }),
+        )
+    }
+
+    /// Counts the lines written by the filters, before any re-encoding, and sketches them.
+    pub fn tap_matched(
+        &self,
+        s: BoxedSendSyncUnpinStream<StreamItem>,
+    ) -> BoxedSendSyncUnpinStream<StreamItem> {
+        let s = self.tap_sketch(s);
+        let Some(counters) = self
+            .counters
+            .clone()
+            .filter(|_| self.line_counts != LineCounts::Off)
+        else {
+            return s;
+        };
+        Box::new(s.map(move |item| {
+            if let Ok(chunk) = &item {
+                let lines = chunk.iter().filter(|&&b| b == b'\n').count();
+                Counters::add(&counters.lines_matched, lines as u64);
+            }
+            item
+        }))
+    }
+
+    fn tap_sketch(
+        &self,
+        s: BoxedSendSyncUnpinStream<StreamItem>,
+    ) -> BoxedSendSyncUnpinStream<StreamItem> {
+        let Some(sketch) = self.sketch.clone() else {
+            return s;
+        };
+        let mut pending = Vec::new();
+        Box::new(
+            s.map(Some)
+                .chain(stream::once(future::ready(None)))
+                .filter_map(move |item| {
+                    let mut sketch = sketch.lock().unwrap_or_else(PoisonError::into_inner);
+                    let item = match item {
+                        Some(Ok(chunk)) => {
+                            let mut rest = &chunk[..];
+                            while let Some(position) = rest.iter().position(|&b| b == b'\n') {
+                                pending.extend_from_slice(&rest[..position]);
+                                sketch_line(&mut sketch, &pending);
+                                pending.clear();
+                                rest = &rest[position + 1..];
+                            }
+                            pending.extend_from_slice(rest);
+                            Some(Ok(chunk))
+                        }
+                        Some(Err(error)) => Some(Err(error)),
+                        None => {
+                            if !pending.is_empty() {
+                                sketch_line(&mut sketch, &pending);
+                            }
+                            None
+                        }
+                    };
+                    future::ready(item)
+                }),
+        )
+    }
+
+    /// Counts the bytes of the response.
+    pub fn tap_output(
+        &self,
+        s: BoxedSendSyncUnpinStream<StreamItem>,
+    ) -> BoxedSendSyncUnpinStream<StreamItem> {
+        let Some(counters) = self.counters.clone() else {
+            return s;
+        };
+        Box::new(s.map(move |item| {
+            if let Ok(chunk) = &item {
+                Counters::add(&counters.bytes_out, chunk.len() as u64);
+            }
+            item
+        }))
+    }
+
+    pub fn report(
+        &self,
+        task_id: String,
+        input_etag: Option<String>,
+        error: Option<&anyhow::Error>,
+    ) -> Report {
+        let counters = self.counters.clone().unwrap_or_default();
+        let lines = (self.line_counts != LineCounts::Off).then(|| {
+            let read = Counters::get(&counters.lines_read);
+            let matched = Counters::get(&counters.lines_matched);
+            LineReport {
+                read,
+                matched,
+                rejected: (self.line_counts == LineCounts::WithRejected)
+                    .then(|| read.saturating_sub(matched)),
+                non_utf8: Counters::get(&counters.lines_non_utf8),
+                too_long: Counters::get(&counters.lines_too_long),
+            }
+        });
+        Report {
+            task_id,
+            input_etag,
+            filter_version: env!("CARGO_PKG_VERSION"),
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            bytes_in: Counters::get(&counters.bytes_in),
+            bytes_out: Counters::get(&counters.bytes_out),
+            lines,
+            sketch: self.sketch.as_ref().map(|sketch| {
+                sketch
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+                    .report()
+            }),
+            filters: self
+                .filters
+                .as_ref()
+                .map(|filters| filters.snapshot())
+                .unwrap_or_default(),
+            error: error.map(|error| format!("{error:#}")),
+        }
+    }
+}
+
+/// Sketches numbers by their E.164 form, other lines as they are.
+fn sketch_line(sketch: &mut Sketch, line: &[u8]) {
+    let line = String::from_utf8_lossy(line.strip_suffix(b"\r").unwrap_or(line));
+    match phone::verdict(&line) {
+        Verdict::Valid(number) => sketch.add(&number),
+        Verdict::Rejected(_) => sketch.add(&line),
+    }
+}
+
+/// The task id of a request, i.e. the key of the requested object.
+pub fn task_id(user_request_url: &str) -> anyhow::Result<String> {
+    let url = reqwest::Url::parse(user_request_url)?;
+    let key = percent_decode_str(url.path().trim_start_matches('/')).decode_utf8()?;
+    if key.is_empty() {
+        return Err(anyhow!("no object key in {user_request_url}"));
+    }
+    Ok(key.into_owned())
+}
+
+/// Counts lines without buffering them, validating UTF-8 across chunk boundaries.
+struct LineScanner {
+    max_line_bytes: usize,
+    len: usize,
+    valid: bool,
+    /// the start of a UTF-8 sequence split across chunks
+    pending: Vec<u8>,
+}
+
+impl LineScanner {
+    fn new(max_line_bytes: usize) -> Self {
+        Self {
+            max_line_bytes,
+            len: 0,
+            valid: true,
+            pending: vec![],
+        }
+    }
+
+    fn scan(&mut self, mut chunk: &[u8], counters: &Counters) {
+        while let Some(position) = chunk.iter().position(|&b| b == b'\n') {
+            let line = &chunk[..position];
+            self.feed(line.strip_suffix(b"\r").unwrap_or(line));
+            self.end_line(counters);
+            chunk = &chunk[position + 1..];
+        }
+        self.feed(chunk);
+    }
+
+    fn finish(&mut self, counters: &Counters) {
+        if self.len > 0 || !self.pending.is_empty() {
+            self.end_line(counters);
+        }
+    }
+
+    fn feed(&mut self, mut bytes: &[u8]) {
+        self.len += bytes.len();
+        if !self.valid {
+            return;
+        }
+        if let Some(&lead) = self.pending.first() {
+            let width = match lead {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                _ => 4,
+            };
+            let take = (width - self.pending.len()).min(bytes.len());
+            self.pending.extend_from_slice(&bytes[..take]);
+            bytes = &bytes[take..];
+            if self.pending.len() < width {
+                return;
+            }
+            self.valid = from_utf8(&self.pending).is_ok();
+            self.pending.clear();
+        }
+        match from_utf8(bytes) {
+            Ok(_) => {}
+            Err(error) if error.error_len().is_none() => {
+                self.pending
+                    .extend_from_slice(&bytes[error.valid_up_to()..]);
+            }
+            Err(_) => self.valid = false,
+        }
+    }
+
+    fn end_line(&mut self, counters: &Counters) {
+        Counters::add(&counters.lines_read, 1);
+        if !self.valid || !self.pending.is_empty() {
+            Counters::add(&counters.lines_non_utf8, 1);
+        }
+        if self.len > self.max_line_bytes {
+            Counters::add(&counters.lines_too_long, 1);
+        }
+        self.len = 0;
+        self.valid = true;
+        self.pending.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,206 @@
+use bytes::Bytes;
+
+use crate::libs::sketch::HeavyHitter;
+
+use super::*;
+
+fn scan(chunks: &[&[u8]], max_line_bytes: usize) -> LineReport {
+    let writer = StatsWriter::new(
+        StatsConfig {
+            bucket: "bucket".into(),
+            prefix: default_prefix(),
+            max_line_bytes,
+            sketch: None,
+        },
+        LineCounts::WithRejected,
+        Arc::new(FilterStats::default()),
+    );
+    let invocation = writer.start();
+    let counters = invocation.counters.clone().unwrap();
+    let mut scanner = LineScanner::new(max_line_bytes);
+    for chunk in chunks {
+        scanner.scan(chunk, &counters);
+    }
+    scanner.finish(&counters);
+    invocation.report("task".into(), None, None).lines.unwrap()
+}
+
+#[test]
+fn test_line_counts() {
+    // given
+    // `ő` is split across the first two chunks, `\xff` is never valid UTF-8
+    let chunks: &[&[u8]] = &[
+        b"+36 1 234 5678\r\nGy\xc5",
+        b"\x91r\nbad \xff\n",
+        b"long line",
+    ];
+
+    // when
+    let lines = scan(chunks, 8);
+
+    // then
+    assert_eq!(
+        lines,
+        LineReport {
+            read: 4,
+            matched: 0,
+            rejected: Some(4),
+            non_utf8: 1,
+            too_long: 2,
+        }
+    );
+}
+
+#[test]
+fn test_truncated_sequence_at_end_of_line() {
+    // when
+    let lines = scan(&[b"abc\xc5\n", b"\x91\n"], 100);
+
+    // then
+    assert_eq!(lines.read, 2);
+    assert_eq!(lines.non_utf8, 2);
+}
+
+#[tokio::test]
+async fn test_taps() {
+    // given
+    let writer = StatsWriter::new(
+        StatsConfig {
+            bucket: "bucket".into(),
+            prefix: default_prefix(),
+            max_line_bytes: default_max_line_bytes(),
+            sketch: None,
+        },
+        LineCounts::WithRejected,
+        Arc::new(FilterStats::default()),
+    );
+    let invocation = writer.start();
+    let input = stream::iter([
+        Ok(Bytes::from_static(b"+36 1 234 5678\nfoo\n")),
+        Ok(Bytes::from_static(b"bar")),
+    ]);
+
+    // when
+    let s = invocation.tap_lines(invocation.tap_input(Box::new(input)));
+    let s = invocation.tap_matched(Box::new(s.filter(|item| {
+        future::ready(item.as_ref().is_ok_and(|chunk| chunk.starts_with(b"+")))
+    })));
+    let output: Vec<_> = invocation.tap_output(s).collect().await;
+    let report = invocation.report("task".into(), Some("\"etag\"".into()), None);
+
+    // then
+    assert_eq!(output.len(), 1);
+    assert_eq!(report.bytes_in, 22);
+    assert_eq!(report.bytes_out, 19);
+    let lines = report.lines.unwrap();
+    assert_eq!((lines.read, lines.matched, lines.rejected), (3, 2, Some(1)));
+}
+
+#[test]
+fn test_rejected_left_out() {
+    // given
+    let writer = StatsWriter::new(
+        StatsConfig {
+            bucket: "bucket".into(),
+            prefix: default_prefix(),
+            max_line_bytes: default_max_line_bytes(),
+            sketch: None,
+        },
+        LineCounts::Counted,
+        Arc::new(FilterStats::default()),
+    );
+
+    // when
+    let report = writer.start().report("task".into(), None, None);
+
+    // then
+    let report = serde_json::to_value(&report).unwrap();
+    assert_eq!(
+        report["lines"],
+        serde_json::json!({"read": 0, "matched": 0, "nonUtf8": 0, "tooLong": 0})
+    );
+}
+
+#[tokio::test]
+async fn test_sketch() {
+    // given
+    let writer = StatsWriter::new(
+        StatsConfig {
+            bucket: "bucket".into(),
+            prefix: default_prefix(),
+            max_line_bytes: default_max_line_bytes(),
+            sketch: Some(SketchConfig {
+                top_k: 2,
+                ..Default::default()
+            }),
+        },
+        LineCounts::WithRejected,
+        Arc::new(FilterStats::default()),
+    );
+    let invocation = writer.start();
+    let output = stream::iter([
+        Ok(Bytes::from_static(b"+36 1 234 5678\n0036 1 2")),
+        Ok(Bytes::from_static(b"34 5678\nfoo\r\n+36 30 123 4567\nfoo")),
+    ]);
+
+    // when
+    let _: Vec<_> = invocation.tap_matched(Box::new(output)).collect().await;
+    let report = invocation.report("task".into(), None, None);
+
+    // then
+    let sketch = report.sketch.unwrap();
+    assert_eq!((sketch.lines, sketch.distinct), (5, 3));
+    assert_eq!(
+        sketch.top,
+        [
+            HeavyHitter {
+                value: "+3612345678".into(),
+                count: 2,
+            },
+            HeavyHitter {
+                value: "foo".into(),
+                count: 2,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_filter_stats() {
+    // given
+    let filters = Arc::new(FilterStats::default());
+    let writer = StatsWriter::new(
+        StatsConfig {
+            bucket: "bucket".into(),
+            prefix: default_prefix(),
+            max_line_bytes: default_max_line_bytes(),
+            sketch: None,
+        },
+        LineCounts::WithRejected,
+        filters.clone(),
+    );
+    filters.count("jsonlMalformed", 7);
+
+    // when
+    let invocation = writer.start();
+    filters.count("jsonlMalformed", 2);
+    filters.count("jsonlMalformed", 1);
+    filters.ratio("dedupeFalsePositiveRate", 0.25);
+    let report = invocation.report("task".into(), None, None);
+
+    // then
+    assert_eq!(
+        serde_json::to_value(&report.filters).unwrap(),
+        serde_json::json!({"dedupeFalsePositiveRate": 0.25, "jsonlMalformed": 3})
+    );
+}
+
+#[test]
+fn test_task_id() {
+    assert_eq!(
+        task_id("https://ap-123.s3-object-lambda.eu-central-1.amazonaws.com/a%20b?x=1").unwrap(),
+        "a b"
+    );
+    assert!(task_id("https://ap-123.s3-object-lambda.eu-central-1.amazonaws.com/").is_err());
+    assert!(task_id("").is_err());
+}
@@ -5,7 +5,8 @@ use aws_sdk_s3::primitives::ByteStream;
 use bytes::Bytes;
 use futures_core::Stream;
 use http_body::Frame;
-use reqwest::Error;
+
+use crate::libs::stream_filter::StreamItem;
 
 type BoxedSendSyncUnpinStream<I> = Box<dyn Stream<Item = I> + Send + Sync + Unpin>;
 
@@ -24,9 +25,15 @@ impl StreamByteStreamAdapter {
     }
 }
 
+impl Default for StreamByteStreamAdapter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg_attr(test, faux::methods)]
 impl StreamToByteStream for StreamByteStreamAdapter {
-    type Item = Result<Bytes, Error>;
+    type Item = StreamItem;
 
     fn stream_to_byte_stream(
         &self,
@@ -36,8 +43,8 @@ impl StreamToByteStream for StreamByteStreamAdapter {
     }
 }
 
-impl From<StreamBodyAdapter<Result<Bytes, Error>>> for ByteStream {
-    fn from(sba: StreamBodyAdapter<Result<Bytes, Error>>) -> Self {
+impl From<StreamBodyAdapter<StreamItem>> for ByteStream {
+    fn from(sba: StreamBodyAdapter<StreamItem>) -> Self {
         ByteStream::from_body_1_x(sba)
     }
 }
@@ -52,14 +59,14 @@ impl<I> From<BoxedSendSyncUnpinStream<I>> for StreamBodyAdapter<I> {
     }
 }
 
-impl http_body::Body for StreamBodyAdapter<Result<Bytes, Error>> {
+impl http_body::Body for StreamBodyAdapter<StreamItem> {
     type Data = Bytes;
-    type Error = Error;
+    type Error = anyhow::Error;
 
     fn poll_frame(
         #[allow(unused_mut)] mut self: Pin<&mut Self>,
         cx: &mut std::task::Context<'_>,
-    ) -> Poll<Option<Result<Frame<Bytes>, Error>>> {
+    ) -> Poll<Option<Result<Frame<Bytes>, anyhow::Error>>> {
         let stream = Pin::new(&mut self.stream);
         match stream.poll_next(cx) {
             Poll::Ready(Some(Ok(bytes))) => Poll::Ready(Some(Ok(Frame::data(bytes)))),
@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::str::from_utf8;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+use crate::libs::phone::{self, NumberType, Rejection, Verdict};
+use crate::libs::sketch::{Sketch, SketchConfig, SketchReport};
+
+use super::{
+    process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter, StreamItem,
+};
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct AggregateConfig {
+    /// adds the distinct count and the most frequent of the valid numbers
+    #[serde(default)]
+    pub sketch: Option<SketchConfig>,
+}
+
+impl AggregateConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match &self.sketch {
+            Some(sketch) => sketch.validate(),
+            None => Ok(()),
+        }
+    }
+}
+
+/// The counts of an input, keyed by the few hundred possible country and area codes,
+/// so that its size does not grow with the input.
+#[derive(Serialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Histogram {
+    /// non-blank lines
+    pub total: u64,
+    pub valid: u64,
+    pub invalid: u64,
+    /// valid numbers and numbers with a wrong country code
+    pub by_country: BTreeMap<String, u64>,
+    /// valid fixed-line and special service numbers
+    pub by_area_code: BTreeMap<String, u64>,
+    /// valid mobile numbers
+    pub by_mobile_prefix: BTreeMap<String, u64>,
+    pub by_type: BTreeMap<NumberType, u64>,
+    /// invalid lines by rejection code
+    pub by_rejection: BTreeMap<&'static str, u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sketch: Option<SketchReport>,
+}
+
+impl Histogram {
+    /// Counts `line`, returning the number if it is valid.
+    fn add(&mut self, line: &[u8]) -> Option<String> {
+        let Ok(line) = from_utf8(line) else {
+            self.total += 1;
+            self.reject("invalid_utf8");
+            return None;
+        };
+        let number = match phone::verdict(line) {
+            Verdict::Rejected(Rejection::Empty) => return None,
+            Verdict::Rejected(rejection) => {
+                self.total += 1;
+                self.reject(rejection.code());
+                if rejection == Rejection::WrongCountryCode {
+                    let digits = phone::normalize(line).unwrap_or_default();
+                    if let Some(country) = phone::country_code(digits.trim_start_matches('+')) {
+                        increment(&mut self.by_country, country);
+                    }
+                }
+                return None;
+            }
+            Verdict::Valid(number) => number,
+        };
+        self.total += 1;
+        self.valid += 1;
+        increment(&mut self.by_country, "36");
+        if let Some(classification) = phone::classify(&number) {
+            let by_prefix = match classification.number_type {
+                NumberType::Mobile => &mut self.by_mobile_prefix,
+                _ => &mut self.by_area_code,
+            };
+            increment(by_prefix, classification.area_code);
+            *self.by_type.entry(classification.number_type).or_default() += 1;
+        }
+        Some(number)
+    }
+
+    fn reject(&mut self, code: &'static str) {
+        self.invalid += 1;
+        *self.by_rejection.entry(code).or_default() += 1;
+    }
+}
+
+fn increment(counts: &mut BTreeMap<String, u64>, key: &str) {
+    match counts.get_mut(key) {
+        Some(count) => *count += 1,
+        None => {
+            counts.insert(key.to_string(), 1);
+        }
+    }
+}
+
+/// Consumes the whole input and outputs only a JSON [`Histogram`] of its lines.
+pub struct AggregateStreamFilter {
+    config: AggregateConfig,
+}
+
+impl AggregateStreamFilter {
+    pub fn new(config: AggregateConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl StreamFilter for AggregateStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            AggregateProcessor {
+                lines: LineBuffer::default(),
+                aggregator: Aggregator {
+                    histogram: Histogram::default(),
+                    sketch: self.config.sketch.as_ref().map(Sketch::new),
+                },
+            },
+        )
+    }
+}
+
+struct AggregateProcessor {
+    lines: LineBuffer,
+    aggregator: Aggregator,
+}
+
+struct Aggregator {
+    histogram: Histogram,
+    sketch: Option<Sketch>,
+}
+
+impl Aggregator {
+    fn on_line(&mut self, line: &[u8]) {
+        let number = self.histogram.add(line);
+        if let (Some(sketch), Some(number)) = (&mut self.sketch, number) {
+            sketch.add(&number);
+        }
+    }
+}
+
+impl ChunkProcessor for AggregateProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        self.lines.push(&chunk, |line| {
+            self.aggregator.on_line(line);
+            Ok(())
+        })?;
+        Ok(Bytes::new())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        self.lines.finish(|line| {
+            self.aggregator.on_line(line);
+            Ok(())
+        })?;
+        let aggregator = &mut self.aggregator;
+        aggregator.histogram.sketch = aggregator.sketch.as_ref().map(Sketch::report);
+        let mut output = BytesMut::new();
+        output.put_slice(&serde_json::to_vec(&aggregator.histogram)?);
+        output.put_u8(b'\n');
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
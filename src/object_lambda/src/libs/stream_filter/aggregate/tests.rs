@@ -0,0 +1,97 @@
+use futures::{stream, StreamExt};
+
+use super::*;
+
+async fn run(config: AggregateConfig, chunks: &[&'static [u8]]) -> serde_json::Value {
+    let filter = AggregateStreamFilter::new(config);
+    let input = stream::iter(
+        chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from_static(chunk)))
+            .collect::<Vec<_>>(),
+    );
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    serde_json::from_slice(&output.concat()).unwrap()
+}
+
+#[tokio::test]
+async fn test_histogram() {
+    // given
+    let chunks: &[&[u8]] = &[
+        b"+36 1 234 5678\n0036 30 123 4567\n+36 3",
+        b"0 765 4321\n\n+36 80 123 456\xff\n+49 30 1234567\n",
+        b"+36 22 123 4567\n+36 80 123 4567\nphone",
+    ];
+
+    // when
+    let histogram = run(AggregateConfig::default(), chunks).await;
+
+    // then
+    assert_eq!(
+        histogram,
+        serde_json::json!({
+            "total": 8,
+            "valid": 5,
+            "invalid": 3,
+            "byCountry": {"36": 5, "49": 1},
+            "byAreaCode": {"1": 1, "22": 1, "80": 1},
+            "byMobilePrefix": {"30": 2},
+            "byType": {"fixed": 2, "mobile": 2, "tollFree": 1},
+            "byRejection": {"illegal_character": 1, "invalid_utf8": 1, "wrong_country_code": 1},
+        })
+    );
+}
+
+#[tokio::test]
+async fn test_empty_input() {
+    // when
+    let histogram = run(AggregateConfig::default(), &[]).await;
+
+    // then
+    assert_eq!(histogram["total"], 0);
+    assert_eq!(histogram["byCountry"], serde_json::json!({}));
+}
+
+#[tokio::test]
+async fn test_sketch() {
+    // given
+    let config = AggregateConfig {
+        sketch: Some(SketchConfig {
+            top_k: 1,
+            ..Default::default()
+        }),
+    };
+    let chunks: &[&[u8]] = &[b"+36 1 234 5678\n+36 30 123 4567\nphone\n0036 1 234 5678\n"];
+
+    // when
+    let histogram = run(config, chunks).await;
+
+    // then
+    assert_eq!(histogram["sketch"]["lines"], 3);
+    assert_eq!(histogram["sketch"]["distinct"], 2);
+    assert_eq!(
+        histogram["sketch"]["top"],
+        serde_json::json!([{"value": "+3612345678", "count": 2}])
+    );
+}
+
+#[test]
+fn test_memory_does_not_grow_with_lines() {
+    // given
+    let mut histogram = Histogram::default();
+
+    // when
+    for i in 0..100_000 {
+        histogram.add(format!("+36 30 {:07}", i).as_bytes());
+        histogram.add(format!("+49 30 {:07}", i).as_bytes());
+    }
+
+    // then
+    assert_eq!(histogram.valid, 100_000);
+    assert_eq!(histogram.by_country.len(), 2);
+    assert_eq!(histogram.by_mobile_prefix.len(), 1);
+}
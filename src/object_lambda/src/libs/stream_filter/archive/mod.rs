@@ -0,0 +1,407 @@
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
+use regex::Regex;
+use serde::Deserialize;
+
+use super::csv::write_record;
+use super::{
+    process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter, StreamItem,
+};
+
+use self::tar::{TarReader, TarWriter};
+use self::zip::{ZipReader, ZipWriter};
+
+mod tar;
+mod zip;
+
+/// Enough to see the `ustar` magic of a TAR header.
+const DETECTION_PREFIX: usize = 512;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ArchiveOutput {
+    /// the matching lines of all entries as a single text stream
+    #[default]
+    Combined,
+    /// an archive of the same format holding the filtered entries
+    Archive,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ArchiveConfig {
+    #[serde(default)]
+    pub output: ArchiveOutput,
+    /// prefix every line of the combined output with the name of its entry
+    #[serde(default)]
+    pub include_entry_name: bool,
+    /// the entries of a filtered archive are held in memory, but for unchanged TAR entries,
+    /// as are TAR metadata entries: larger ones are rejected
+    #[serde(default = "default_max_entry_bytes")]
+    pub max_entry_bytes: usize,
+}
+
+fn default_max_entry_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+impl ArchiveConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_entry_bytes == 0 {
+            bail!("archive maxEntryBytes must be positive");
+        }
+        Ok(())
+    }
+}
+
+impl Default for ArchiveConfig {
+    fn default() -> Self {
+        Self {
+            output: ArchiveOutput::default(),
+            include_entry_name: false,
+            max_entry_bytes: default_max_entry_bytes(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum EntryKind {
+    File,
+    Directory,
+    /// links, devices and metadata entries, copied as they are into a filtered archive
+    Other,
+}
+
+enum EntryHeader {
+    /// the header block, and the size of the entry which it may leave to a pax header
+    Tar {
+        header: Box<[u8; 512]>,
+        size: u64,
+    },
+    Zip {
+        time: u16,
+        date: u16,
+    },
+}
+
+struct Entry {
+    name: String,
+    kind: EntryKind,
+    header: EntryHeader,
+}
+
+enum Event<'a> {
+    Start(Entry),
+    Data(&'a [u8]),
+    End,
+}
+
+/// Moves bytes from `input` to `pending` until it holds `len` bytes, returning whether it does.
+fn fill(pending: &mut BytesMut, input: &mut &[u8], len: usize) -> bool {
+    let take = len.saturating_sub(pending.len()).min(input.len());
+    pending.put_slice(&input[..take]);
+    *input = &input[take..];
+    pending.len() >= len
+}
+
+/// Runs the phone matcher on every text entry of a ZIP or TAR archive.
+/// Other inputs are filtered line by line, as plain text.
+pub struct ArchiveStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<ArchiveConfig>,
+}
+
+impl ArchiveStreamFilter {
+    pub fn new(regex: Regex, config: ArchiveConfig) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl StreamFilter for ArchiveStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            ArchiveProcessor {
+                format: Format::Undetected(BytesMut::new()),
+                entries: Entries {
+                    regex: self.regex.clone(),
+                    config: self.config.clone(),
+                    current: None,
+                },
+            },
+        )
+    }
+}
+
+enum Format {
+    Undetected(BytesMut),
+    Text(LineBuffer),
+    Tar(TarReader, TarWriter),
+    Zip(ZipReader, ZipWriter),
+}
+
+struct ArchiveProcessor {
+    format: Format,
+    entries: Entries,
+}
+
+impl ArchiveProcessor {
+    fn detect(prefix: &[u8], config: &ArchiveConfig) -> Format {
+        if zip::is_zip(prefix) {
+            Format::Zip(ZipReader::new(), ZipWriter::default())
+        } else if tar::is_tar(prefix) {
+            Format::Tar(TarReader::new(config.max_entry_bytes), TarWriter)
+        } else {
+            Format::Text(LineBuffer::default())
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        let entries = &mut self.entries;
+        match &mut self.format {
+            Format::Undetected(prefix) => {
+                prefix.put_slice(chunk);
+                if prefix.len() >= DETECTION_PREFIX {
+                    let prefix = prefix.split().freeze();
+                    self.format = Self::detect(&prefix, &self.entries.config);
+                    self.push(&prefix, output)?;
+                }
+            }
+            Format::Text(lines) => {
+                lines.push(chunk, |line| {
+                    entries.on_line(None, line, output);
+                    Ok(())
+                })?;
+            }
+            Format::Tar(reader, writer) => reader.push(chunk, &mut |event| {
+                entries.on_tar_event(event, writer, output)
+            })?,
+            Format::Zip(reader, writer) => reader.push(chunk, &mut |event| {
+                entries.on_zip_event(event, writer, output)
+            })?,
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut BytesMut) -> anyhow::Result<()> {
+        if let Format::Undetected(prefix) = &mut self.format {
+            let prefix = prefix.split().freeze();
+            self.format = Self::detect(&prefix, &self.entries.config);
+            self.push(&prefix, output)?;
+        }
+        let archive = self.entries.config.output == ArchiveOutput::Archive;
+        match &mut self.format {
+            Format::Undetected(_) => unreachable!("detected above"),
+            Format::Text(lines) => lines.finish(|line| {
+                self.entries.on_line(None, line, output);
+                Ok(())
+            })?,
+            Format::Tar(reader, writer) => {
+                reader.finish()?;
+                if archive {
+                    writer.finish(output);
+                }
+            }
+            Format::Zip(reader, writer) => {
+                reader.finish()?;
+                if archive {
+                    writer.finish(output)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChunkProcessor for ArchiveProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.push(&chunk, &mut output)?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        ArchiveProcessor::finish(self, &mut output)?;
+        Ok(output.freeze())
+    }
+}
+
+struct CurrentEntry {
+    entry: Entry,
+    lines: LineBuffer,
+    /// undecided until the first data of the entry is seen
+    text: Option<bool>,
+    /// the filtered content, if a filtered archive is written
+    content: BytesMut,
+    /// whether the entry is unchanged, and copied to the filtered TAR archive as it is read
+    streamed: bool,
+}
+
+/// Filters the entries of an archive, whatever its format.
+struct Entries {
+    regex: Arc<Regex>,
+    config: Arc<ArchiveConfig>,
+    current: Option<CurrentEntry>,
+}
+
+impl Entries {
+    fn on_tar_event(
+        &mut self,
+        event: Event,
+        writer: &mut TarWriter,
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        if let Some(current) = self.on_event(event, output)? {
+            if let EntryHeader::Tar { header, .. } = &current.entry.header {
+                writer.write_entry(header, &current.content, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_zip_event(
+        &mut self,
+        event: Event,
+        writer: &mut ZipWriter,
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        if let Some(current) = self.on_event(event, output)? {
+            let CurrentEntry { entry, content, .. } = current;
+            // entries of unsupported compression methods cannot be rewritten
+            if let (EntryHeader::Zip { time, date }, false) =
+                (entry.header, entry.kind == EntryKind::Other)
+            {
+                writer.write_entry(&entry.name, time, date, &content, output)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Returns the completed entry if it is to be written to a filtered archive.
+    fn on_event(
+        &mut self,
+        event: Event,
+        output: &mut BytesMut,
+    ) -> anyhow::Result<Option<CurrentEntry>> {
+        let archive = self.config.output == ArchiveOutput::Archive;
+        match event {
+            Event::Start(entry) => {
+                self.current = Some(CurrentEntry {
+                    entry,
+                    lines: LineBuffer::default(),
+                    text: None,
+                    content: BytesMut::new(),
+                    streamed: false,
+                });
+                Ok(None)
+            }
+            Event::Data(data) => {
+                let Some(mut current) = self.current.take() else {
+                    return Ok(None);
+                };
+                let text = *current.text.get_or_insert_with(|| {
+                    // the same heuristic as git's: binary files contain NUL bytes
+                    current.entry.kind == EntryKind::File && !data.contains(&0)
+                });
+                if text {
+                    let CurrentEntry {
+                        entry,
+                        lines,
+                        content,
+                        ..
+                    } = &mut current;
+                    let target = if archive { content } else { &mut *output };
+                    lines.push(data, |line| {
+                        self.on_line(Some(&entry.name), line, target);
+                        Ok(())
+                    })?;
+                } else if archive {
+                    match &current.entry.header {
+                        // a pax header may hold the size of the next entry, which is rewritten
+                        EntryHeader::Tar { header, size } if header[156] != b'x' => {
+                            if !current.streamed {
+                                current.streamed = true;
+                                tar::write_header(header, *size, output)?;
+                            }
+                            output.put_slice(data);
+                        }
+                        _ => current.content.put_slice(data),
+                    }
+                }
+                self.check_size(&current)?;
+                self.current = Some(current);
+                Ok(None)
+            }
+            Event::End => {
+                let Some(mut current) = self.current.take() else {
+                    return Ok(None);
+                };
+                if let (true, EntryHeader::Tar { size, .. }) =
+                    (current.streamed, &current.entry.header)
+                {
+                    output.put_bytes(0, tar::padding(*size));
+                    return Ok(None);
+                }
+                let CurrentEntry {
+                    entry,
+                    lines,
+                    content,
+                    ..
+                } = &mut current;
+                let target = if archive { content } else { &mut *output };
+                lines.finish(|line| {
+                    self.on_line(Some(&entry.name), line, target);
+                    Ok(())
+                })?;
+                self.check_size(&current)?;
+                Ok(archive.then_some(current))
+            }
+        }
+    }
+
+    fn check_size(&self, current: &CurrentEntry) -> anyhow::Result<()> {
+        if current.content.len() > self.config.max_entry_bytes {
+            bail!(
+                "filtered archive entry `{}` exceeds the limit of {} bytes",
+                current.entry.name,
+                self.config.max_entry_bytes
+            );
+        }
+        Ok(())
+    }
+
+    fn on_line(&self, entry_name: Option<&str>, line: &[u8], output: &mut BytesMut) {
+        let Ok(line) = from_utf8(line) else {
+            return;
+        };
+        if !self.regex.is_match(line) {
+            return;
+        }
+        match entry_name {
+            Some(name)
+                if self.config.include_entry_name
+                    && self.config.output == ArchiveOutput::Combined =>
+            {
+                write_record(output, &[name.as_bytes(), line.as_bytes()], b',');
+            }
+            _ => {
+                output.put_slice(line.as_bytes());
+                output.put_u8(b'\n');
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,326 @@
+use anyhow::{anyhow, bail};
+use bytes::{BufMut, BytesMut};
+
+use super::{fill, Entry, EntryHeader, EntryKind, Event};
+
+const BLOCK: usize = 512;
+
+/// `ustar` magic of POSIX and GNU archives, at offset 257 of the first header
+pub(super) fn is_tar(prefix: &[u8]) -> bool {
+    prefix.get(257..262) == Some(b"ustar")
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Header,
+    Data { remaining: u64, padding: usize },
+    Padding { remaining: usize },
+    End,
+}
+
+/// Incremental TAR reader, accepting the archive in chunks of any size.
+pub(super) struct TarReader {
+    state: State,
+    pending: BytesMut,
+    /// data of a GNU long name (`L`) or pax (`x`) entry, naming the next entry
+    meta: Option<(u8, Vec<u8>)>,
+    next_name: Option<String>,
+    /// the size of the next entry from a pax header, overriding its header field
+    next_size: Option<u64>,
+    max_meta_bytes: usize,
+}
+
+impl TarReader {
+    pub fn new(max_meta_bytes: usize) -> Self {
+        Self {
+            state: State::Header,
+            pending: BytesMut::new(),
+            meta: None,
+            next_name: None,
+            next_size: None,
+            max_meta_bytes,
+        }
+    }
+
+    pub fn push<F>(&mut self, mut input: &[u8], f: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(Event) -> anyhow::Result<()>,
+    {
+        while !input.is_empty() {
+            match self.state {
+                State::Header => {
+                    if !fill(&mut self.pending, &mut input, BLOCK) {
+                        return Ok(());
+                    }
+                    let header = self.pending.split();
+                    self.on_header(&header, f)?;
+                }
+                State::Data { remaining, padding } => {
+                    let take = input
+                        .len()
+                        .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                    let (data, rest) = input.split_at(take);
+                    input = rest;
+                    if let Some((_, meta)) = &mut self.meta {
+                        meta.extend_from_slice(data);
+                    }
+                    f(Event::Data(data))?;
+                    self.on_data_consumed(remaining - take as u64, padding, f)?;
+                }
+                State::Padding { remaining } => {
+                    let take = input.len().min(remaining);
+                    input = &input[take..];
+                    self.state = match remaining - take {
+                        0 => State::Header,
+                        remaining => State::Padding { remaining },
+                    };
+                }
+                // anything after the end-of-archive blocks is ignored
+                State::End => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match self.state {
+            State::Header if !self.pending.is_empty() => bail!("truncated TAR header"),
+            State::Data { .. } | State::Padding { .. } => bail!("truncated TAR entry"),
+            _ => Ok(()),
+        }
+    }
+
+    fn on_header<F>(&mut self, header: &[u8], f: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(Event) -> anyhow::Result<()>,
+    {
+        if header.iter().all(|&b| b == 0) {
+            self.state = State::End;
+            return Ok(());
+        }
+        if parse_octal(&header[148..156])? != checksum(header) {
+            bail!("invalid TAR header checksum");
+        }
+        let typeflag = header[156];
+        let size = match self.next_size {
+            Some(size) if !is_meta(typeflag) => {
+                self.next_size = None;
+                size
+            }
+            _ => parse_size(&header[124..136])?,
+        };
+        let kind = match typeflag {
+            0 | b'0' | b'7' => EntryKind::File,
+            b'5' => EntryKind::Directory,
+            _ => EntryKind::Other,
+        };
+        if matches!(typeflag, b'L' | b'x') {
+            if size > self.max_meta_bytes as u64 {
+                bail!(
+                    "TAR metadata entry of {size} bytes exceeds the limit of {} bytes",
+                    self.max_meta_bytes
+                );
+            }
+            self.meta = Some((typeflag, vec![]));
+        }
+        let name = match self.next_name.take() {
+            Some(name) if kind != EntryKind::Other => name,
+            _ => ustar_name(header),
+        };
+        let header = Box::new(<[u8; BLOCK]>::try_from(header)?);
+        f(Event::Start(Entry {
+            name,
+            kind,
+            header: EntryHeader::Tar { header, size },
+        }))?;
+        self.on_data_consumed(size, padding(size), f)
+    }
+
+    fn on_data_consumed<F>(
+        &mut self,
+        remaining: u64,
+        padding: usize,
+        f: &mut F,
+    ) -> anyhow::Result<()>
+    where
+        F: FnMut(Event) -> anyhow::Result<()>,
+    {
+        if remaining > 0 {
+            self.state = State::Data { remaining, padding };
+            return Ok(());
+        }
+        f(Event::End)?;
+        if let Some((typeflag, meta)) = self.meta.take() {
+            if typeflag == b'L' {
+                self.next_name = Some(String::from_utf8_lossy(until_nul(&meta)).into_owned());
+            } else {
+                let records = pax_records(&meta)?;
+                self.next_name = pax_value(&records, "path").map(str::to_string);
+                self.next_size = pax_value(&records, "size")
+                    .map(|size| {
+                        size.parse()
+                            .map_err(|_| anyhow!("invalid pax size `{size}`"))
+                    })
+                    .transpose()?;
+            }
+        }
+        self.state = match padding {
+            0 => State::Header,
+            remaining => State::Padding { remaining },
+        };
+        Ok(())
+    }
+}
+
+/// Rewrites filtered entries into a TAR archive, reusing their original headers.
+pub(super) struct TarWriter;
+
+impl TarWriter {
+    pub fn write_entry(
+        &mut self,
+        header: &[u8; BLOCK],
+        data: &[u8],
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        // the size of the filtered entry is in its own header, not in a pax record
+        let stripped;
+        let data = if header[156] == b'x' {
+            stripped = strip_pax_size(data)?;
+            &stripped[..]
+        } else {
+            data
+        };
+        write_header(header, data.len() as u64, output)?;
+        output.put_slice(data);
+        output.put_bytes(0, padding(data.len() as u64));
+        Ok(())
+    }
+
+    pub fn finish(&mut self, output: &mut BytesMut) {
+        output.put_bytes(0, 2 * BLOCK);
+    }
+}
+
+/// Writes `header` with the size of the entry following it, which is to be padded to a block.
+pub(super) fn write_header(
+    header: &[u8; BLOCK],
+    size: u64,
+    output: &mut BytesMut,
+) -> anyhow::Result<()> {
+    if size >= 1 << 33 {
+        bail!("TAR entry of {size} bytes does not fit a header");
+    }
+    let mut header = *header;
+    header[124..136].copy_from_slice(format!("{size:011o}\0").as_bytes());
+    let checksum = checksum(&header);
+    header[148..156].copy_from_slice(format!("{checksum:06o}\0 ").as_bytes());
+    output.put_slice(&header);
+    Ok(())
+}
+
+pub(super) fn padding(size: u64) -> usize {
+    (BLOCK - (size % BLOCK as u64) as usize) % BLOCK
+}
+
+/// Sum of the header bytes, with the checksum field itself taken as spaces.
+fn checksum(header: &[u8]) -> u64 {
+    header
+        .iter()
+        .enumerate()
+        .map(|(i, &b)| if (148..156).contains(&i) { b' ' } else { b } as u64)
+        .sum()
+}
+
+fn parse_octal(field: &[u8]) -> anyhow::Result<u64> {
+    let digits = std::str::from_utf8(until_nul(field))?.trim();
+    if digits.is_empty() {
+        return Ok(0);
+    }
+    u64::from_str_radix(digits, 8).map_err(|_| anyhow!("invalid TAR octal field `{digits}`"))
+}
+
+/// Sizes over 8 GiB are stored base-256, flagged by the high bit of the first byte.
+fn parse_size(field: &[u8]) -> anyhow::Result<u64> {
+    if field[0] & 0x80 == 0 {
+        return parse_octal(field);
+    }
+    Ok(field[1..]
+        .iter()
+        .fold((field[0] & 0x7f) as u64, |size, &b| (size << 8) | b as u64))
+}
+
+fn ustar_name(header: &[u8]) -> String {
+    let name = String::from_utf8_lossy(until_nul(&header[0..100]));
+    // GNU archives (`ustar  `) use the prefix field for timestamps
+    if &header[257..263] != b"ustar\0" {
+        return name.into_owned();
+    }
+    let prefix = String::from_utf8_lossy(until_nul(&header[345..500]));
+    if prefix.is_empty() {
+        name.into_owned()
+    } else {
+        format!("{prefix}/{name}")
+    }
+}
+
+/// Entries describing the next entry rather than being one: GNU long names and pax headers.
+fn is_meta(typeflag: u8) -> bool {
+    matches!(typeflag, b'L' | b'K' | b'x' | b'g')
+}
+
+/// The records of a pax extended header, each being `<length> <key>=<value>\n` with the length
+/// counting the whole record.
+fn pax_records(mut data: &[u8]) -> anyhow::Result<Vec<(String, String)>> {
+    let mut records = vec![];
+    while !data.is_empty() {
+        let space = data
+            .iter()
+            .position(|&b| b == b' ')
+            .ok_or_else(|| anyhow!("malformed pax record"))?;
+        let len: usize = std::str::from_utf8(&data[..space])?
+            .parse()
+            .map_err(|_| anyhow!("malformed pax record length"))?;
+        if len <= space + 1 || len > data.len() || data[len - 1] != b'\n' {
+            bail!("malformed pax record");
+        }
+        let record = String::from_utf8_lossy(&data[space + 1..len - 1]);
+        let (key, value) = record
+            .split_once('=')
+            .ok_or_else(|| anyhow!("malformed pax record `{record}`"))?;
+        records.push((key.to_string(), value.to_string()));
+        data = &data[len..];
+    }
+    Ok(records)
+}
+
+/// The value of the last record of `key`, as later records override earlier ones.
+fn pax_value<'a>(records: &'a [(String, String)], key: &str) -> Option<&'a str> {
+    records
+        .iter()
+        .rev()
+        .find(|(k, _)| k == key)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Re-encodes the records of a pax extended header without its `size` record.
+pub(super) fn strip_pax_size(data: &[u8]) -> anyhow::Result<Vec<u8>> {
+    let mut output = vec![];
+    for (key, value) in pax_records(data)? {
+        if key == "size" {
+            continue;
+        }
+        // the length counts its own digits, so grow it until it does
+        let body = key.len() + value.len() + 3;
+        let mut len = body + 1;
+        while len != body + len.to_string().len() {
+            len = body + len.to_string().len();
+        }
+        output.extend_from_slice(format!("{len} {key}={value}\n").as_bytes());
+    }
+    Ok(output)
+}
+
+fn until_nul(field: &[u8]) -> &[u8] {
+    let end = field.iter().position(|&b| b == 0).unwrap_or(field.len());
+    &field[..end]
+}
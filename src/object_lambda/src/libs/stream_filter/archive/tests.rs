@@ -0,0 +1,441 @@
+use std::io::{Cursor, Read, Write};
+
+use ::zip::write::SimpleFileOptions;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use futures::{stream, StreamExt};
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::tar::strip_pax_size;
+use super::*;
+
+const REGION_1: &str = "+36 1 234 5678\nnot a number\n0036 30 123 4567";
+const REGION_2: &str = "+49 30 1234567\r\n+36 20 987 6543\r\n";
+
+async fn run(config: ArchiveConfig, input: Vec<u8>, chunk_size: usize) -> anyhow::Result<Vec<u8>> {
+    let filter = ArchiveStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), config);
+    let chunks: Vec<StreamItem> = input
+        .chunks(chunk_size)
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+        .collect();
+    let mut output = vec![];
+    let mut stream = filter.filter_stream(Box::new(stream::iter(chunks)));
+    while let Some(chunk) = stream.next().await {
+        output.extend_from_slice(&chunk?);
+    }
+    Ok(output)
+}
+
+fn tar_archive() -> Vec<u8> {
+    let mut builder = ::tar::Builder::new(vec![]);
+    for (name, content) in [
+        ("regions/", &b""[..]),
+        ("regions/central.txt", REGION_1.as_bytes()),
+        ("regions/logo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+        (
+            "regions/a-very-long-directory-name-which-does-not-fit-into-the-hundred-bytes-of-a-plain-tar-header/west.txt",
+            REGION_2.as_bytes(),
+        ),
+    ] {
+        let mut header = ::tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        header.set_entry_type(if name.ends_with('/') {
+            ::tar::EntryType::Directory
+        } else {
+            ::tar::EntryType::Regular
+        });
+        builder.append_data(&mut header, name, content).unwrap();
+    }
+    builder.into_inner().unwrap()
+}
+
+const ZIP_ENTRIES: [(&str, &[u8]); 3] = [
+    ("central.txt", REGION_1.as_bytes()),
+    ("logo.png", b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"),
+    ("west.txt", REGION_2.as_bytes()),
+];
+
+fn zip_archive() -> Vec<u8> {
+    let mut writer = ::zip::ZipWriter::new(Cursor::new(vec![]));
+    for (name, content) in ZIP_ENTRIES {
+        writer
+            .start_file(name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(content).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+/// As written by streaming zippers: sizes and CRC follow the data in a data descriptor.
+fn streamed_zip_archive() -> Vec<u8> {
+    streamed_zip(&ZIP_ENTRIES.map(|(name, content)| (name.as_bytes(), content)))
+}
+
+/// A streamed archive of `entries`, whose names are read as CP437 as the UTF-8 flag is not set.
+fn streamed_zip(entries: &[(&[u8], &[u8])]) -> Vec<u8> {
+    let mut archive = vec![];
+    for &(name, content) in entries {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(content).unwrap();
+        let compressed = encoder.finish().unwrap();
+        archive.put_u32_le(0x04034b50);
+        archive.put_u16_le(20);
+        archive.put_u16_le(1 << 3);
+        archive.put_u16_le(8);
+        archive.put_u32_le(0);
+        archive.put_bytes(0, 12);
+        archive.put_u16_le(name.len() as u16);
+        archive.put_u16_le(0);
+        archive.put_slice(name);
+        archive.put_slice(&compressed);
+        archive.put_u32_le(0x08074b50);
+        archive.put_u32_le(crc32fast::hash(content));
+        archive.put_u32_le(compressed.len() as u32);
+        archive.put_u32_le(content.len() as u32);
+    }
+    // the reader stops at the central directory, so an empty one will do
+    archive.put_u32_le(0x06054b50);
+    archive.put_bytes(0, 18);
+    archive
+}
+
+/// A pax header holding the size of the next entry, whose own size field says 0.
+fn pax_sized_tar(records: &[u8]) -> Vec<u8> {
+    let mut archive = vec![];
+    for (name, entry_type, data) in [
+        ("PaxHeaders/central.txt", ::tar::EntryType::XHeader, records),
+        (
+            "central.txt",
+            ::tar::EntryType::Regular,
+            REGION_1.as_bytes(),
+        ),
+    ] {
+        let mut header = ::tar::Header::new_ustar();
+        header.set_path(name).unwrap();
+        header.set_entry_type(entry_type);
+        header.set_mode(0o644);
+        header.set_size(if entry_type == ::tar::EntryType::XHeader {
+            data.len() as u64
+        } else {
+            0
+        });
+        header.set_cksum();
+        archive.extend_from_slice(header.as_bytes());
+        archive.extend_from_slice(data);
+        archive.put_bytes(0, (512 - data.len() % 512) % 512);
+    }
+    archive.put_bytes(0, 1024);
+    archive
+}
+
+#[tokio::test]
+async fn test_tar_combined_with_entry_names() {
+    // given
+    let config = ArchiveConfig {
+        output: ArchiveOutput::Combined,
+        include_entry_name: true,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, tar_archive(), 7).await.unwrap();
+
+    // then
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "regions/central.txt,+36 1 234 5678\n\
+         regions/central.txt,0036 30 123 4567\n\
+         regions/a-very-long-directory-name-which-does-not-fit-into-the-hundred-bytes-of-a-plain-tar-header/west.txt,+36 20 987 6543\n"
+    );
+}
+
+#[tokio::test]
+async fn test_tar_archive_output() {
+    // given
+    let config = ArchiveConfig {
+        output: ArchiveOutput::Archive,
+        include_entry_name: false,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, tar_archive(), 100).await.unwrap();
+
+    // then
+    let mut archive = ::tar::Archive::new(Cursor::new(output));
+    let entries: Vec<(String, String)> = archive
+        .entries()
+        .unwrap()
+        .map(|entry| {
+            let mut entry = entry.unwrap();
+            let mut content = vec![];
+            entry.read_to_end(&mut content).unwrap();
+            (
+                entry.path().unwrap().to_string_lossy().into_owned(),
+                String::from_utf8_lossy(&content).into_owned(),
+            )
+        })
+        .collect();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0].0, "regions/");
+    assert_eq!(
+        entries[1],
+        (
+            "regions/central.txt".to_string(),
+            "+36 1 234 5678\n0036 30 123 4567\n".to_string()
+        )
+    );
+    assert!(entries[2].1.starts_with("\u{fffd}PNG"));
+    assert_eq!(entries[3].1, "+36 20 987 6543\n");
+}
+
+#[tokio::test]
+async fn test_tar_archive_output_streams_unchanged_entries() {
+    // given
+    let logo: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+    let mut builder = ::tar::Builder::new(vec![]);
+    for (name, content) in [
+        ("logo.png", &logo[..]),
+        ("central.txt", REGION_1.as_bytes()),
+    ] {
+        let mut header = ::tar::Header::new_gnu();
+        header.set_size(content.len() as u64);
+        header.set_mode(0o644);
+        builder.append_data(&mut header, name, content).unwrap();
+    }
+    let config = ArchiveConfig {
+        output: ArchiveOutput::Archive,
+        max_entry_bytes: 100,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, builder.into_inner().unwrap(), 512)
+        .await
+        .unwrap();
+
+    // then
+    let mut archive = ::tar::Archive::new(Cursor::new(output));
+    let mut entries = archive.entries().unwrap();
+    let mut content = vec![];
+    let mut entry = entries.next().unwrap().unwrap();
+    entry.read_to_end(&mut content).unwrap();
+    assert_eq!(content, logo);
+    let mut content = String::new();
+    let mut entry = entries.next().unwrap().unwrap();
+    entry.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "+36 1 234 5678\n0036 30 123 4567\n");
+}
+
+#[tokio::test]
+async fn test_entry_limit() {
+    for (archive, max_entry_bytes) in [
+        // the filtered text entry
+        (pax_sized_tar(b"11 size=44\n"), 20),
+        (zip_archive(), 20),
+        // the pax header
+        (pax_sized_tar(b"11 size=44\n"), 10),
+    ] {
+        // given
+        let config = ArchiveConfig {
+            output: ArchiveOutput::Archive,
+            max_entry_bytes,
+            ..Default::default()
+        };
+
+        // when
+        let output = run(config, archive, 100).await;
+
+        // then
+        assert!(output.is_err());
+    }
+}
+
+#[tokio::test]
+async fn test_tar_pax_size() {
+    // given
+    let config = ArchiveConfig {
+        output: ArchiveOutput::Combined,
+        include_entry_name: true,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, pax_sized_tar(b"11 size=44\n"), 100)
+        .await
+        .unwrap();
+
+    // then
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "central.txt,+36 1 234 5678\ncentral.txt,0036 30 123 4567\n"
+    );
+}
+
+#[tokio::test]
+async fn test_tar_archive_output_with_pax_size() {
+    // given
+    let config = ArchiveConfig {
+        output: ArchiveOutput::Archive,
+        include_entry_name: false,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, pax_sized_tar(b"11 size=44\n"), 100)
+        .await
+        .unwrap();
+
+    // then
+    let mut archive = ::tar::Archive::new(Cursor::new(output));
+    let mut entries = archive.entries().unwrap();
+    let mut entry = entries.next().unwrap().unwrap();
+    let mut content = String::new();
+    entry.read_to_string(&mut content).unwrap();
+    assert_eq!(content, "+36 1 234 5678\n0036 30 123 4567\n");
+}
+
+#[tokio::test]
+async fn test_malformed_pax_header() {
+    for records in [&b"12 size=44\n"[..], b"11 size=4x\n", b"size=44\n"] {
+        // when
+        let output = run(ArchiveConfig::default(), pax_sized_tar(records), 100).await;
+
+        // then
+        assert!(output.is_err(), "{records:?}");
+    }
+}
+
+#[test]
+fn test_strip_pax_size() {
+    // given
+    // records of 98 and 101 bytes, on either side of a three-digit length
+    let path_98 = format!("98 path={}\n", "a".repeat(89));
+    let path_101 = format!("101 path={}\n", "a".repeat(91));
+    let records = format!("11 size=44\n{path_98}16 path=abc.txt\n{path_101}");
+
+    // when
+    let stripped = strip_pax_size(records.as_bytes()).unwrap();
+
+    // then
+    assert_eq!(
+        String::from_utf8(stripped).unwrap(),
+        format!("{path_98}16 path=abc.txt\n{path_101}")
+    );
+}
+
+#[test]
+fn test_zip_entry_name_too_long() {
+    // given
+    let mut writer = ZipWriter::default();
+    let mut output = BytesMut::new();
+
+    // when
+    let result = writer.write_entry(&"a".repeat(70_000), 0, 0, b"", &mut output);
+
+    // then
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn test_zip_combined() {
+    for archive in [zip_archive(), streamed_zip_archive()] {
+        // when
+        let output = run(ArchiveConfig::default(), archive, 5).await.unwrap();
+
+        // then
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "+36 1 234 5678\n0036 30 123 4567\n+36 20 987 6543\n"
+        );
+    }
+}
+
+#[tokio::test]
+async fn test_zip_cp437_names() {
+    // given
+    let archive = streamed_zip(&[(b"k\x94rzet.txt", REGION_2.as_bytes())]);
+    let config = ArchiveConfig {
+        output: ArchiveOutput::Combined,
+        include_entry_name: true,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, archive.clone(), 64).await.unwrap();
+    let filtered = run(
+        ArchiveConfig {
+            output: ArchiveOutput::Archive,
+            ..Default::default()
+        },
+        archive,
+        64,
+    )
+    .await
+    .unwrap();
+
+    // then
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "körzet.txt,+36 20 987 6543\n"
+    );
+    let mut filtered = ::zip::ZipArchive::new(Cursor::new(filtered)).unwrap();
+    assert_eq!(filtered.by_index(0).unwrap().name(), "körzet.txt");
+}
+
+#[tokio::test]
+async fn test_zip_archive_output() {
+    // given
+    let config = ArchiveConfig {
+        output: ArchiveOutput::Archive,
+        include_entry_name: false,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, streamed_zip_archive(), 64).await.unwrap();
+
+    // then
+    let mut archive = ::zip::ZipArchive::new(Cursor::new(output)).unwrap();
+    assert_eq!(archive.len(), 3);
+    let mut content = String::new();
+    archive
+        .by_name("central.txt")
+        .unwrap()
+        .read_to_string(&mut content)
+        .unwrap();
+    assert_eq!(content, "+36 1 234 5678\n0036 30 123 4567\n");
+    let mut logo = vec![];
+    archive
+        .by_name("logo.png")
+        .unwrap()
+        .read_to_end(&mut logo)
+        .unwrap();
+    assert_eq!(logo, b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR");
+}
+
+#[tokio::test]
+async fn test_plain_text_input() {
+    // when
+    let output = run(ArchiveConfig::default(), REGION_2.as_bytes().to_vec(), 3)
+        .await
+        .unwrap();
+
+    // then
+    assert_eq!(String::from_utf8(output).unwrap(), "+36 20 987 6543\n");
+}
+
+#[tokio::test]
+async fn test_truncated_tar() {
+    // given
+    let mut archive = tar_archive();
+    archive.truncate(1050);
+
+    // when
+    let output = run(ArchiveConfig::default(), archive, 512).await;
+
+    // then
+    assert!(output.is_err());
+}
@@ -0,0 +1,408 @@
+use std::io::Write;
+
+use anyhow::bail;
+use bytes::{Buf, BufMut, BytesMut};
+use flate2::write::DeflateEncoder;
+use flate2::{Compression, Decompress, FlushDecompress, Status};
+
+use super::{fill, Entry, EntryHeader, EntryKind, Event};
+
+const LOCAL_FILE_HEADER: u32 = 0x04034b50;
+const DATA_DESCRIPTOR: u32 = 0x08074b50;
+const CENTRAL_DIRECTORY_HEADER: u32 = 0x02014b50;
+const END_OF_CENTRAL_DIRECTORY: u32 = 0x06054b50;
+
+const FLAG_ENCRYPTED: u16 = 1;
+const FLAG_DATA_DESCRIPTOR: u16 = 1 << 3;
+const FLAG_UTF8: u16 = 1 << 11;
+
+const METHOD_STORED: u16 = 0;
+const METHOD_DEFLATED: u16 = 8;
+
+/// The characters of the CP437 bytes from 0x80, the encoding of ZIP names without the UTF-8 flag.
+const CP437_HIGH: [char; 128] = [
+    'Ç', 'ü', 'é', 'â', 'ä', 'à', 'å', 'ç', 'ê', 'ë', 'è', 'ï', 'î', 'ì', 'Ä', 'Å', 'É', 'æ', 'Æ',
+    'ô', 'ö', 'ò', 'û', 'ù', 'ÿ', 'Ö', 'Ü', '¢', '£', '¥', '₧', 'ƒ', 'á', 'í', 'ó', 'ú', 'ñ', 'Ñ',
+    'ª', 'º', '¿', '⌐', '¬', '½', '¼', '¡', '«', '»', '░', '▒', '▓', '│', '┤', '╡', '╢', '╖', '╕',
+    '╣', '║', '╗', '╝', '╜', '╛', '┐', '└', '┴', '┬', '├', '─', '┼', '╞', '╟', '╚', '╔', '╩', '╦',
+    '╠', '═', '╬', '╧', '╨', '╤', '╥', '╙', '╘', '╒', '╓', '╫', '╪', '┘', '┌', '█', '▄', '▌', '▐',
+    '▀', 'α', 'ß', 'Γ', 'π', 'Σ', 'σ', 'µ', 'τ', 'Φ', 'Θ', 'Ω', 'δ', '∞', 'φ', 'ε', '∩', '≡', '±',
+    '≥', '≤', '⌠', '⌡', '÷', '≈', '°', '∙', '·', '√', 'ⁿ', '²', '■', '\u{a0}',
+];
+
+pub(super) fn is_zip(prefix: &[u8]) -> bool {
+    prefix.starts_with(&LOCAL_FILE_HEADER.to_le_bytes())
+}
+
+/// Entry names are UTF-8 if the flag says so, CP437 otherwise.
+fn decode_name(name: &[u8], flags: u16) -> String {
+    if flags & FLAG_UTF8 != 0 {
+        return String::from_utf8_lossy(name).into_owned();
+    }
+    name.iter()
+        .map(|&b| match b {
+            0..=0x7f => b as char,
+            _ => CP437_HIGH[b as usize - 0x80],
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy)]
+enum State {
+    Header,
+    Stored {
+        remaining: u64,
+        descriptor: bool,
+    },
+    /// `remaining` is unknown if the sizes follow in a data descriptor
+    Deflated {
+        remaining: Option<u64>,
+        descriptor: bool,
+    },
+    Skipped {
+        remaining: u64,
+        descriptor: bool,
+    },
+    Descriptor,
+    /// the central directory repeats what was already read from the local headers
+    Done,
+}
+
+/// Incremental ZIP reader, going through the local file headers instead of the
+/// central directory at the end of the archive.
+pub(super) struct ZipReader {
+    state: State,
+    pending: BytesMut,
+    inflater: Decompress,
+    buffer: Vec<u8>,
+}
+
+impl ZipReader {
+    pub fn new() -> Self {
+        Self {
+            state: State::Header,
+            pending: BytesMut::new(),
+            inflater: Decompress::new(false),
+            buffer: vec![0; 32 * 1024],
+        }
+    }
+
+    pub fn push<F>(&mut self, mut input: &[u8], f: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(Event) -> anyhow::Result<()>,
+    {
+        while !input.is_empty() {
+            match self.state {
+                State::Header => {
+                    if !fill(&mut self.pending, &mut input, 4) {
+                        return Ok(());
+                    }
+                    match (&self.pending[..4]).get_u32_le() {
+                        LOCAL_FILE_HEADER => {}
+                        CENTRAL_DIRECTORY_HEADER | END_OF_CENTRAL_DIRECTORY => {
+                            self.state = State::Done;
+                            continue;
+                        }
+                        _ => bail!("invalid ZIP local file header"),
+                    }
+                    if !fill(&mut self.pending, &mut input, 30) {
+                        return Ok(());
+                    }
+                    let name_len = (&self.pending[26..]).get_u16_le() as usize;
+                    let extra_len = (&self.pending[28..]).get_u16_le() as usize;
+                    if !fill(&mut self.pending, &mut input, 30 + name_len + extra_len) {
+                        return Ok(());
+                    }
+                    let header = self.pending.split();
+                    self.on_header(&header, name_len, f)?;
+                }
+                State::Stored {
+                    remaining,
+                    descriptor,
+                } => {
+                    let take = input
+                        .len()
+                        .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                    f(Event::Data(&input[..take]))?;
+                    input = &input[take..];
+                    self.state = match remaining - take as u64 {
+                        0 => Self::end_of_entry(descriptor, f)?,
+                        remaining => State::Stored {
+                            remaining,
+                            descriptor,
+                        },
+                    };
+                }
+                State::Deflated {
+                    remaining,
+                    descriptor,
+                } => {
+                    let available = match remaining {
+                        Some(remaining) => input
+                            .len()
+                            .min(usize::try_from(remaining).unwrap_or(usize::MAX)),
+                        None => input.len(),
+                    };
+                    let (consumed, status) = self.inflate(&input[..available], f)?;
+                    input = &input[consumed..];
+                    let remaining = remaining.map(|remaining| remaining - consumed as u64);
+                    self.state = match (status, remaining) {
+                        (Status::StreamEnd, Some(remaining)) if remaining > 0 => {
+                            f(Event::End)?;
+                            State::Skipped {
+                                remaining,
+                                descriptor,
+                            }
+                        }
+                        (Status::StreamEnd, _) => Self::end_of_entry(descriptor, f)?,
+                        (_, Some(0)) => bail!("truncated ZIP deflate stream"),
+                        (_, remaining) => State::Deflated {
+                            remaining,
+                            descriptor,
+                        },
+                    };
+                }
+                State::Skipped {
+                    remaining,
+                    descriptor,
+                } => {
+                    let take = input
+                        .len()
+                        .min(usize::try_from(remaining).unwrap_or(usize::MAX));
+                    input = &input[take..];
+                    self.state = match remaining - take as u64 {
+                        0 if descriptor => State::Descriptor,
+                        0 => State::Header,
+                        remaining => State::Skipped {
+                            remaining,
+                            descriptor,
+                        },
+                    };
+                }
+                State::Descriptor => {
+                    if !fill(&mut self.pending, &mut input, 4) {
+                        return Ok(());
+                    }
+                    // the descriptor signature is optional
+                    let len = match (&self.pending[..4]).get_u32_le() {
+                        DATA_DESCRIPTOR => 16,
+                        _ => 12,
+                    };
+                    if !fill(&mut self.pending, &mut input, len) {
+                        return Ok(());
+                    }
+                    self.pending.clear();
+                    self.state = State::Header;
+                }
+                State::Done => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(&self) -> anyhow::Result<()> {
+        match self.state {
+            State::Done => Ok(()),
+            _ => bail!("truncated ZIP archive"),
+        }
+    }
+
+    fn on_header<F>(&mut self, header: &[u8], name_len: usize, f: &mut F) -> anyhow::Result<()>
+    where
+        F: FnMut(Event) -> anyhow::Result<()>,
+    {
+        let mut fields = &header[6..];
+        let flags = fields.get_u16_le();
+        let method = fields.get_u16_le();
+        let time = fields.get_u16_le();
+        let date = fields.get_u16_le();
+        let _crc = fields.get_u32_le();
+        let compressed_size = fields.get_u32_le();
+        if flags & FLAG_ENCRYPTED != 0 {
+            bail!("encrypted ZIP entries are not supported");
+        }
+        if compressed_size == u32::MAX {
+            bail!("ZIP64 entries are not supported");
+        }
+        let descriptor = flags & FLAG_DATA_DESCRIPTOR != 0;
+        let name = decode_name(&header[30..30 + name_len], flags);
+        let kind = match method {
+            _ if name.ends_with('/') => EntryKind::Directory,
+            METHOD_STORED | METHOD_DEFLATED => EntryKind::File,
+            _ => EntryKind::Other,
+        };
+        f(Event::Start(Entry {
+            name,
+            kind,
+            header: EntryHeader::Zip { time, date },
+        }))?;
+        let compressed_size = compressed_size as u64;
+        self.state = match method {
+            METHOD_DEFLATED => State::Deflated {
+                remaining: (!descriptor || compressed_size > 0).then_some(compressed_size),
+                descriptor,
+            },
+            _ if descriptor && compressed_size == 0 && kind != EntryKind::Directory => {
+                bail!("ZIP entries with a data descriptor must be deflated")
+            }
+            METHOD_STORED if compressed_size > 0 => State::Stored {
+                remaining: compressed_size,
+                descriptor,
+            },
+            METHOD_STORED => Self::end_of_entry(descriptor, f)?,
+            _ => {
+                f(Event::End)?;
+                State::Skipped {
+                    remaining: compressed_size,
+                    descriptor,
+                }
+            }
+        };
+        Ok(())
+    }
+
+    /// Inflates `input` up to the end of the deflate stream, returning the number of bytes consumed.
+    fn inflate<F>(&mut self, input: &[u8], f: &mut F) -> anyhow::Result<(usize, Status)>
+    where
+        F: FnMut(Event) -> anyhow::Result<()>,
+    {
+        let mut consumed = 0;
+        loop {
+            let total_in = self.inflater.total_in();
+            let total_out = self.inflater.total_out();
+            let status = self.inflater.decompress(
+                &input[consumed..],
+                &mut self.buffer,
+                FlushDecompress::None,
+            )?;
+            consumed += (self.inflater.total_in() - total_in) as usize;
+            let produced = (self.inflater.total_out() - total_out) as usize;
+            if produced > 0 {
+                f(Event::Data(&self.buffer[..produced]))?;
+            }
+            if status == Status::StreamEnd {
+                self.inflater.reset(false);
+                return Ok((consumed, status));
+            }
+            if consumed == input.len() && produced < self.buffer.len() {
+                return Ok((consumed, status));
+            }
+        }
+    }
+
+    fn end_of_entry<F>(descriptor: bool, f: &mut F) -> anyhow::Result<State>
+    where
+        F: FnMut(Event) -> anyhow::Result<()>,
+    {
+        f(Event::End)?;
+        Ok(if descriptor {
+            State::Descriptor
+        } else {
+            State::Header
+        })
+    }
+}
+
+struct CentralDirectoryEntry {
+    name: String,
+    name_len: u16,
+    time: u16,
+    date: u16,
+    method: u16,
+    crc: u32,
+    compressed_size: u32,
+    size: u32,
+    offset: u32,
+}
+
+/// Writes filtered entries as a new ZIP archive, deflating every file.
+#[derive(Default)]
+pub(super) struct ZipWriter {
+    offset: u32,
+    entries: Vec<CentralDirectoryEntry>,
+}
+
+impl ZipWriter {
+    pub fn write_entry(
+        &mut self,
+        name: &str,
+        time: u16,
+        date: u16,
+        data: &[u8],
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        let (method, compressed) = if name.ends_with('/') {
+            (METHOD_STORED, vec![])
+        } else {
+            let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+            encoder.write_all(data)?;
+            (METHOD_DEFLATED, encoder.finish()?)
+        };
+        let name_len = u16::try_from(name.len())
+            .map_err(|_| anyhow::anyhow!("ZIP entry name of {} bytes is too long", name.len()))?;
+        let entry = CentralDirectoryEntry {
+            name: name.to_string(),
+            name_len,
+            time,
+            date,
+            method,
+            crc: crc32fast::hash(data),
+            compressed_size: u32::try_from(compressed.len())?,
+            size: u32::try_from(data.len())?,
+            offset: self.offset,
+        };
+        let start = output.len();
+        output.put_u32_le(LOCAL_FILE_HEADER);
+        output.put_u16_le(20);
+        Self::put_common_fields(&entry, output);
+        output.put_u16_le(0);
+        output.put_slice(entry.name.as_bytes());
+        output.put_slice(&compressed);
+        self.offset = self
+            .offset
+            .checked_add(u32::try_from(output.len() - start)?)
+            .ok_or_else(|| anyhow::anyhow!("filtered ZIP archive exceeds 4 GiB"))?;
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    pub fn finish(&mut self, output: &mut BytesMut) -> anyhow::Result<()> {
+        let start = output.len();
+        for entry in &self.entries {
+            output.put_u32_le(CENTRAL_DIRECTORY_HEADER);
+            output.put_u16_le(20);
+            output.put_u16_le(20);
+            Self::put_common_fields(entry, output);
+            output.put_u16_le(0); // extra field length
+            output.put_u16_le(0); // comment length
+            output.put_u16_le(0); // disk number
+            output.put_u16_le(0); // internal attributes
+            output.put_u32_le(if entry.name.ends_with('/') { 0x10 } else { 0 });
+            output.put_u32_le(entry.offset);
+            output.put_slice(entry.name.as_bytes());
+        }
+        let entries = u16::try_from(self.entries.len())?;
+        let size = u32::try_from(output.len() - start)?;
+        output.put_u32_le(END_OF_CENTRAL_DIRECTORY);
+        output.put_u16_le(0);
+        output.put_u16_le(0);
+        output.put_u16_le(entries);
+        output.put_u16_le(entries);
+        output.put_u32_le(size);
+        output.put_u32_le(self.offset);
+        output.put_u16_le(0);
+        Ok(())
+    }
+
+    /// Fields shared by the local and the central directory header, up to the name length.
+    fn put_common_fields(entry: &CentralDirectoryEntry, output: &mut BytesMut) {
+        output.put_u16_le(FLAG_UTF8);
+        output.put_u16_le(entry.method);
+        output.put_u16_le(entry.time);
+        output.put_u16_le(entry.date);
+        output.put_u32_le(entry.crc);
+        output.put_u32_le(entry.compressed_size);
+        output.put_u32_le(entry.size);
+        output.put_u16_le(entry.name_len);
+    }
+}
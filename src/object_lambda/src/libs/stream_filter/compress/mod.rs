@@ -0,0 +1,86 @@
+use std::io::Write;
+use std::mem;
+
+use anyhow::bail;
+use bytes::Bytes;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::Deserialize;
+
+use super::{process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, StreamFilter, StreamItem};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CompressFormat {
+    #[default]
+    Gzip,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CompressConfig {
+    #[serde(default)]
+    pub format: CompressFormat,
+    /// 0 to 9, 6 by default
+    #[serde(default)]
+    pub level: Option<u32>,
+}
+
+impl CompressConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.level.is_some_and(|level| level > 9) {
+            bail!("compress level must be between 0 and 9");
+        }
+        Ok(())
+    }
+}
+
+/// Compresses the output as it streams.
+pub struct CompressStreamFilter {
+    config: CompressConfig,
+}
+
+impl CompressStreamFilter {
+    pub fn new(config: CompressConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl StreamFilter for CompressStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        let level = self
+            .config
+            .level
+            .map_or_else(Compression::default, Compression::new);
+        let CompressFormat::Gzip = self.config.format;
+        process_stream(
+            s,
+            GzipProcessor {
+                encoder: GzEncoder::new(Vec::new(), level),
+            },
+        )
+    }
+}
+
+struct GzipProcessor {
+    encoder: GzEncoder<Vec<u8>>,
+}
+
+impl ChunkProcessor for GzipProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        self.encoder.write_all(&chunk)?;
+        Ok(Bytes::from(mem::take(self.encoder.get_mut())))
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        self.encoder.try_finish()?;
+        Ok(Bytes::from(mem::take(self.encoder.get_mut())))
+    }
+}
+
+#[cfg(test)]
+mod tests;
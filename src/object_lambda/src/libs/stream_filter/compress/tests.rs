@@ -0,0 +1,30 @@
+use std::io::Read;
+
+use flate2::read::GzDecoder;
+use futures::{stream, StreamExt};
+
+use super::*;
+
+#[tokio::test]
+async fn test_gzip() {
+    // given
+    let filter = CompressStreamFilter::new(CompressConfig::default());
+    let input = stream::iter([
+        Ok(Bytes::from_static(b"+3612345678\n")),
+        Ok(Bytes::from_static(b"+36301234567\n")),
+    ]);
+
+    // when
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    // then
+    let mut text = String::new();
+    GzDecoder::new(&output.concat()[..])
+        .read_to_string(&mut text)
+        .unwrap();
+    assert_eq!(text, "+3612345678\n+36301234567\n");
+}
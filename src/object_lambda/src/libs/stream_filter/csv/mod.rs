@@ -0,0 +1,306 @@
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail};
+use bytes::{BufMut, Bytes, BytesMut};
+use csv_core::{ReadRecordResult, Reader, ReaderBuilder};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::libs::phone;
+
+use super::{process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, StreamFilter, StreamItem};
+
+/// The column holding the phone number, either by header name or by zero-based index.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(untagged)]
+pub enum CsvColumn {
+    Index(usize),
+    Name(String),
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CsvOutput {
+    /// the whole row, with its fields quoted where needed and the configured delimiter
+    #[default]
+    Row,
+    /// only the normalized number
+    Number,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct CsvConfig {
+    pub column: CsvColumn,
+    #[serde(default = "default_delimiter")]
+    pub delimiter: char,
+    #[serde(default = "default_has_header")]
+    pub has_header: bool,
+    #[serde(default)]
+    pub output: CsvOutput,
+    /// a record is held in memory until it is complete, larger ones fail the request, e.g. on
+    /// a quote which is never closed
+    #[serde(default = "default_max_record_bytes")]
+    pub max_record_bytes: usize,
+}
+
+fn default_delimiter() -> char {
+    ','
+}
+
+fn default_max_record_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_has_header() -> bool {
+    true
+}
+
+impl CsvConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !self.delimiter.is_ascii() {
+            bail!("CSV delimiter must be an ASCII character");
+        }
+        if matches!(self.delimiter, '"' | '\r' | '\n') {
+            bail!("CSV delimiter must not be a quote or a line break");
+        }
+        if matches!(self.column, CsvColumn::Name(_)) && !self.has_header {
+            bail!("CSV column can only be selected by name if the input has a header");
+        }
+        if self.max_record_bytes == 0 {
+            bail!("CSV maxRecordBytes must be positive");
+        }
+        Ok(())
+    }
+}
+
+pub struct CsvStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<CsvConfig>,
+}
+
+impl CsvStreamFilter {
+    pub fn new(regex: Regex, config: CsvConfig) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl StreamFilter for CsvStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            CsvProcessor::new(self.regex.clone(), self.config.clone()),
+        )
+    }
+}
+
+/// Incremental CSV parser state; a record may span any number of chunks.
+struct CsvProcessor {
+    regex: Arc<Regex>,
+    config: Arc<CsvConfig>,
+    reader: Reader,
+    record: Vec<u8>,
+    record_len: usize,
+    ends: Vec<usize>,
+    ends_len: usize,
+    column: usize,
+    header_pending: bool,
+}
+
+impl CsvProcessor {
+    fn new(regex: Arc<Regex>, config: Arc<CsvConfig>) -> Self {
+        let reader = ReaderBuilder::new()
+            .delimiter(config.delimiter as u8)
+            .build();
+        // columns selected by name are resolved once the header is read
+        let column = match config.column {
+            CsvColumn::Index(index) => index,
+            CsvColumn::Name(_) => 0,
+        };
+        let header_pending = config.has_header;
+        let record = vec![0; config.max_record_bytes.min(1024)];
+        Self {
+            regex,
+            config,
+            reader,
+            record,
+            record_len: 0,
+            ends: vec![0; 32],
+            ends_len: 0,
+            column,
+            header_pending,
+        }
+    }
+
+    /// Feeds `input` to the parser; an empty `input` signals the end of the file.
+    fn read(&mut self, mut input: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        let eof = input.is_empty();
+        loop {
+            let (result, nin, nout, nend) = self.reader.read_record(
+                input,
+                &mut self.record[self.record_len..],
+                &mut self.ends[self.ends_len..],
+            );
+            input = &input[nin..];
+            self.record_len += nout;
+            self.ends_len += nend;
+            match result {
+                ReadRecordResult::InputEmpty => return Ok(()),
+                ReadRecordResult::OutputFull => {
+                    let len = self.grown_len(self.record.len())?;
+                    self.record.resize(len, 0);
+                }
+                ReadRecordResult::OutputEndsFull => {
+                    let len = self.grown_len(self.ends.len())?;
+                    self.ends.resize(len, 0);
+                }
+                ReadRecordResult::Record => {
+                    self.on_record(output)?;
+                    self.record_len = 0;
+                    self.ends_len = 0;
+                    // an empty slice would be taken as the end of the file
+                    if input.is_empty() && !eof {
+                        return Ok(());
+                    }
+                }
+                ReadRecordResult::End => return Ok(()),
+            }
+        }
+    }
+
+    /// The doubled length of a full record buffer, up to `max_record_bytes`; a record has at most
+    /// as many fields as bytes.
+    fn grown_len(&self, len: usize) -> anyhow::Result<usize> {
+        let max = self.config.max_record_bytes;
+        if len >= max {
+            bail!("CSV record exceeds the limit of {max} bytes");
+        }
+        Ok((len * 2).min(max))
+    }
+
+    fn on_record(&mut self, output: &mut BytesMut) -> anyhow::Result<()> {
+        let fields = split_fields(&self.record, &self.ends[..self.ends_len]);
+        if self.header_pending {
+            self.header_pending = false;
+            if let CsvColumn::Name(name) = &self.config.column {
+                let column = fields
+                    .iter()
+                    .position(|field| field.trim_ascii() == name.as_bytes())
+                    .ok_or_else(|| anyhow!("column `{name}` not found in CSV header"))?;
+                self.column = column;
+            }
+            let header = match self.config.output {
+                CsvOutput::Row => fields,
+                CsvOutput::Number => fields.get(self.column).copied().into_iter().collect(),
+            };
+            write_record(output, &header, self.config.delimiter as u8);
+            return Ok(());
+        }
+        let Some(value) = fields
+            .get(self.column)
+            .and_then(|field| from_utf8(field).ok())
+        else {
+            return Ok(());
+        };
+        if !self.regex.is_match(value) {
+            return Ok(());
+        }
+        match self.config.output {
+            CsvOutput::Row => write_record(output, &fields, self.config.delimiter as u8),
+            CsvOutput::Number => {
+                if let Some(number) = phone::normalize(value) {
+                    output.put_slice(number.as_bytes());
+                    output.put_u8(b'\n');
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChunkProcessor for CsvProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        if !chunk.is_empty() {
+            self.read(&chunk, &mut output)?;
+        }
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.read(&[], &mut output)?;
+        Ok(output.freeze())
+    }
+}
+
+fn split_fields<'a>(record: &'a [u8], ends: &[usize]) -> Vec<&'a [u8]> {
+    let mut start = 0;
+    ends.iter()
+        .map(|&end| {
+            let field = &record[start..end];
+            start = end;
+            field
+        })
+        .collect()
+}
+
+/// The fields of a single record, e.g. a row written by `write_record`.
+pub(super) fn read_fields(line: &[u8], delimiter: u8) -> Vec<Vec<u8>> {
+    let mut reader = ReaderBuilder::new().delimiter(delimiter).build();
+    // unquoting never grows a record
+    let mut record = vec![0; line.len()];
+    let mut ends = vec![0; line.len() + 1];
+    let (mut input, mut record_len, mut ends_len) = (line, 0, 0);
+    loop {
+        let (result, nin, nout, nend) =
+            reader.read_record(input, &mut record[record_len..], &mut ends[ends_len..]);
+        input = &input[nin..];
+        record_len += nout;
+        ends_len += nend;
+        match result {
+            // the next, empty input ends the record
+            ReadRecordResult::InputEmpty => continue,
+            _ => break,
+        }
+    }
+    split_fields(&record, &ends[..ends_len])
+        .into_iter()
+        .map(<[u8]>::to_vec)
+        .collect()
+}
+
+pub(super) fn write_record(output: &mut BytesMut, fields: &[&[u8]], delimiter: u8) {
+    for (i, field) in fields.iter().enumerate() {
+        if i > 0 {
+            output.put_u8(delimiter);
+        }
+        let needs_quotes = field
+            .iter()
+            .any(|&b| b == delimiter || matches!(b, b'"' | b'\r' | b'\n'));
+        if needs_quotes {
+            output.put_u8(b'"');
+            for &b in field.iter() {
+                if b == b'"' {
+                    output.put_u8(b'"');
+                }
+                output.put_u8(b);
+            }
+            output.put_u8(b'"');
+        } else {
+            output.put_slice(field);
+        }
+    }
+    output.put_u8(b'\n');
+}
+
+#[cfg(test)]
+mod tests;
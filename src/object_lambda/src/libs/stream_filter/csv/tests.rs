@@ -0,0 +1,119 @@
+use futures::{stream, StreamExt};
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::*;
+
+async fn run(config: CsvConfig, chunks: &[&str]) -> anyhow::Result<String> {
+    let filter = CsvStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), config);
+    let input = stream::iter(
+        chunks
+            .iter()
+            .map(|&chunk| Ok(Bytes::copy_from_slice(chunk.as_bytes())))
+            .collect::<Vec<StreamItem>>(),
+    );
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk?)?);
+    }
+    Ok(output)
+}
+
+fn config(column: CsvColumn, output: CsvOutput) -> CsvConfig {
+    CsvConfig {
+        column,
+        delimiter: ',',
+        has_header: true,
+        output,
+        max_record_bytes: default_max_record_bytes(),
+    }
+}
+
+#[tokio::test]
+async fn test_row_output_by_name() {
+    // given
+    let config = config(CsvColumn::Name("phone".into()), CsvOutput::Row);
+
+    // when
+    let output = run(
+        config,
+        &[
+            "name,phone,city\nAnna,+36 1 234 5678,Buda",
+            "pest\nBéla,n/a,Pécs\n\"Kovács, Cecília\",0036 30 123 45",
+            "67,\"Győr\nMoson\"",
+        ],
+    )
+    .await;
+
+    // then
+    assert_eq!(
+        output.unwrap(),
+        "name,phone,city\nAnna,+36 1 234 5678,Budapest\n\"Kovács, Cecília\",0036 30 123 4567,\"Győr\nMoson\"\n"
+    );
+}
+
+#[tokio::test]
+async fn test_number_output_by_index() {
+    // given
+    let config = config(CsvColumn::Index(1), CsvOutput::Number);
+
+    // when
+    let output = run(
+        config,
+        &["name,tel\r\nAnna,+36 1 234 5678\r\nBéla,+49 30 1234567\r\n"],
+    )
+    .await;
+
+    // then
+    assert_eq!(output.unwrap(), "tel\n+3612345678\n");
+}
+
+#[tokio::test]
+async fn test_without_header() {
+    // given
+    let config = CsvConfig {
+        delimiter: ';',
+        has_header: false,
+        ..config(CsvColumn::Index(0), CsvOutput::Row)
+    };
+
+    // when
+    let output = run(config, &["+36 1 234 5678;a\n", "12;b\n"]).await;
+
+    // then
+    assert_eq!(output.unwrap(), "+36 1 234 5678;a\n");
+}
+
+#[tokio::test]
+async fn test_record_limit() {
+    // given
+    let config = CsvConfig {
+        max_record_bytes: 64,
+        ..config(CsvColumn::Index(0), CsvOutput::Row)
+    };
+    let unterminated = format!("phone,name\n+36 1 234 5678,\"{}", "a\n".repeat(100));
+    let many_fields = format!("phone,name\n+36 1 234 5678{}\n", ",".repeat(100));
+
+    // when
+    let unterminated = run(config.clone(), &[&unterminated]).await;
+    let many_fields = run(config.clone(), &[&many_fields]).await;
+    let within = run(config, &["phone,name\n+36 1 234 5678,\"a\nb\"\n"]).await;
+
+    // then
+    assert!(unterminated.is_err());
+    assert!(many_fields.is_err());
+    assert_eq!(within.unwrap(), "phone,name\n+36 1 234 5678,\"a\nb\"\n");
+}
+
+#[tokio::test]
+async fn test_missing_column() {
+    // given
+    let config = config(CsvColumn::Name("phone".into()), CsvOutput::Row);
+
+    // when
+    let output = run(config, &["name,mobile\n"]).await;
+
+    // then
+    assert!(output.is_err());
+}
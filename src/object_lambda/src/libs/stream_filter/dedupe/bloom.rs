@@ -0,0 +1,66 @@
+use std::collections::hash_map::DefaultHasher;
+use std::f64::consts::LN_2;
+use std::hash::{Hash, Hasher};
+
+/// Fixed-size set answering "probably seen" or "certainly not seen".
+pub(super) struct BloomFilter {
+    bits: Vec<u64>,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// Sizes the filter for `expected_items` keys at the given false-positive rate.
+    pub fn new(expected_items: u64, false_positive_rate: f64) -> Self {
+        let items = expected_items as f64;
+        let bits = Self::bits(expected_items, false_positive_rate);
+        let hashes = (bits / items * LN_2).round().max(1.0) as u32;
+        Self {
+            bits: vec![0; Self::words(bits) as usize],
+            hashes,
+        }
+    }
+
+    /// The size of the filter `new` would allocate, without allocating it.
+    pub fn size_bytes_for(expected_items: u64, false_positive_rate: f64) -> u64 {
+        Self::words(Self::bits(expected_items, false_positive_rate)).saturating_mul(8)
+    }
+
+    fn bits(expected_items: u64, false_positive_rate: f64) -> f64 {
+        (-(expected_items as f64) * false_positive_rate.ln() / (LN_2 * LN_2)).ceil()
+    }
+
+    fn words(bits: f64) -> u64 {
+        (bits as u64).div_ceil(64).max(1)
+    }
+
+    /// Inserts `key`, returning whether it was absent.
+    pub fn insert(&mut self, key: &str) -> bool {
+        // double hashing: the k bit positions are h1 + i * h2
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        let h1 = hasher.finish();
+        h1.hash(&mut hasher);
+        let h2 = hasher.finish() | 1;
+        let len = self.bits.len() as u64 * 64;
+        let mut absent = false;
+        for i in 0..self.hashes as u64 {
+            let bit = h1.wrapping_add(i.wrapping_mul(h2)) % len;
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            if self.bits[word] & mask == 0 {
+                self.bits[word] |= mask;
+                absent = true;
+            }
+        }
+        absent
+    }
+
+    /// Probability that a new key is taken for a seen one, given the bits set so far.
+    pub fn false_positive_rate(&self) -> f64 {
+        let set: u64 = self.bits.iter().map(|word| word.count_ones() as u64).sum();
+        (set as f64 / (self.bits.len() as f64 * 64.0)).powi(self.hashes as i32)
+    }
+
+    pub fn size_bytes(&self) -> usize {
+        self.bits.len() * 8
+    }
+}
@@ -0,0 +1,257 @@
+use std::collections::HashSet;
+use std::mem::size_of;
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::libs::stats::FilterStats;
+
+use super::{
+    number_key, process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, LineFormat,
+    StreamFilter, StreamItem,
+};
+
+use self::bloom::BloomFilter;
+
+mod bloom;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "mode", rename_all = "camelCase")]
+pub enum DedupeConfig {
+    /// remembers every number seen, never dropping a new one
+    Exact(ExactConfig),
+    /// remembers numbers in a fixed-size Bloom filter, dropping a few new ones as duplicates
+    Bloom(BloomConfig),
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ExactConfig {
+    /// the invocation fails once the set of seen numbers grows over this estimate
+    #[serde(default = "default_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BloomConfig {
+    #[serde(default = "default_expected_items")]
+    pub expected_items: u64,
+    /// the false-positive rate once `expected_items` distinct numbers are seen
+    #[serde(default = "default_false_positive_rate")]
+    pub false_positive_rate: f64,
+    /// the configuration is rejected if the filter sized for the above would be larger
+    #[serde(default = "default_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+}
+
+fn default_max_memory_bytes() -> usize {
+    256 * 1024 * 1024
+}
+
+fn default_expected_items() -> u64 {
+    10_000_000
+}
+
+fn default_false_positive_rate() -> f64 {
+    0.001
+}
+
+impl DedupeConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match self {
+            DedupeConfig::Exact(exact) if exact.max_memory_bytes == 0 => {
+                bail!("dedupe maxMemoryBytes must be positive")
+            }
+            DedupeConfig::Bloom(bloom) if bloom.expected_items == 0 => {
+                bail!("dedupe expectedItems must be positive")
+            }
+            DedupeConfig::Bloom(bloom)
+                if !(bloom.false_positive_rate > 0.0 && bloom.false_positive_rate < 1.0) =>
+            {
+                bail!("dedupe falsePositiveRate must be between 0 and 1")
+            }
+            DedupeConfig::Bloom(bloom)
+                if BloomFilter::size_bytes_for(bloom.expected_items, bloom.false_positive_rate)
+                    > bloom.max_memory_bytes as u64 =>
+            {
+                bail!(
+                    "dedupe Bloom filter of {} bytes for {} expectedItems at falsePositiveRate {} \
+                     exceeds maxMemoryBytes {}",
+                    BloomFilter::size_bytes_for(bloom.expected_items, bloom.false_positive_rate),
+                    bloom.expected_items,
+                    bloom.false_positive_rate,
+                    bloom.max_memory_bytes
+                )
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Drops the repeats of output lines, keeping the first one seen.
+/// Lines holding just a number are keyed on the normalized number, other lines on their content,
+/// CSV records as a whole.
+pub struct DedupeStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<DedupeConfig>,
+    stats: Arc<FilterStats>,
+    format: LineFormat,
+}
+
+impl DedupeStreamFilter {
+    pub fn new(
+        regex: Regex,
+        config: DedupeConfig,
+        stats: Arc<FilterStats>,
+        format: LineFormat,
+    ) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+            stats,
+            format,
+        }
+    }
+}
+
+impl StreamFilter for DedupeStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        let seen = match self.config.as_ref() {
+            DedupeConfig::Exact(exact) => Seen::Exact {
+                keys: HashSet::new(),
+                memory_bytes: 0,
+                max_memory_bytes: exact.max_memory_bytes,
+            },
+            DedupeConfig::Bloom(bloom) => Seen::Bloom(BloomFilter::new(
+                bloom.expected_items,
+                bloom.false_positive_rate,
+            )),
+        };
+        process_stream(
+            s,
+            DedupeProcessor {
+                lines: self.format.line_buffer(),
+                stats: self.stats.clone(),
+                dedupe: Dedupe {
+                    regex: self.regex.clone(),
+                    seen,
+                    duplicates: 0,
+                },
+            },
+        )
+    }
+}
+
+enum Seen {
+    Exact {
+        keys: HashSet<String>,
+        /// estimated, from the key lengths and a per-entry overhead
+        memory_bytes: usize,
+        max_memory_bytes: usize,
+    },
+    Bloom(BloomFilter),
+}
+
+impl Seen {
+    /// Remembers `key`, returning whether it is new.
+    fn insert(&mut self, key: &str) -> anyhow::Result<bool> {
+        match self {
+            Seen::Exact {
+                keys,
+                memory_bytes,
+                max_memory_bytes,
+            } => {
+                if keys.contains(key) {
+                    return Ok(false);
+                }
+                // the String itself plus a control byte and slack of the hash table
+                *memory_bytes += key.len() + size_of::<String>() + 8;
+                if *memory_bytes > *max_memory_bytes {
+                    bail!(
+                        "exact dedupe exceeds its memory ceiling of {max_memory_bytes} bytes \
+                         after {} distinct lines, consider the bloom mode",
+                        keys.len()
+                    );
+                }
+                keys.insert(key.to_string());
+                Ok(true)
+            }
+            Seen::Bloom(bloom) => Ok(bloom.insert(key)),
+        }
+    }
+}
+
+struct DedupeProcessor {
+    lines: LineBuffer,
+    stats: Arc<FilterStats>,
+    dedupe: Dedupe,
+}
+
+struct Dedupe {
+    regex: Arc<Regex>,
+    seen: Seen,
+    duplicates: u64,
+}
+
+impl Dedupe {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        let key = match from_utf8(line) {
+            Ok(line) => number_key(&self.regex, line),
+            Err(_) => String::from_utf8_lossy(line),
+        };
+        if self.seen.insert(&key)? {
+            output.put_slice(line);
+            output.put_u8(b'\n');
+        } else {
+            self.duplicates += 1;
+        }
+        Ok(())
+    }
+}
+
+impl ChunkProcessor for DedupeProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .push(&chunk, |line| self.dedupe.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .finish(|line| self.dedupe.on_line(line, &mut output))?;
+        self.stats.count("dedupeDuplicates", self.dedupe.duplicates);
+        match &self.dedupe.seen {
+            Seen::Exact { keys, .. } => tracing::info!(
+                "dropped {} duplicates of {} distinct lines",
+                self.dedupe.duplicates,
+                keys.len()
+            ),
+            Seen::Bloom(bloom) => {
+                self.stats
+                    .ratio("dedupeFalsePositiveRate", bloom.false_positive_rate());
+                tracing::info!(
+                    "dropped {} duplicates with a {} byte Bloom filter, \
+                     estimated false-positive rate {:.6}",
+                    self.dedupe.duplicates,
+                    bloom.size_bytes(),
+                    bloom.false_positive_rate()
+                )
+            }
+        }
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
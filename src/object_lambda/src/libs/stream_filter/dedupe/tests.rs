@@ -0,0 +1,168 @@
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+
+use crate::libs::phone::HU_PATTERN;
+use crate::libs::stats::FilterStat;
+
+use super::*;
+
+async fn run(config: DedupeConfig, chunks: &[&'static str]) -> anyhow::Result<String> {
+    run_with_stats(config, chunks, Arc::new(FilterStats::default())).await
+}
+
+async fn run_with_stats(
+    config: DedupeConfig,
+    chunks: &[&'static str],
+    stats: Arc<FilterStats>,
+) -> anyhow::Result<String> {
+    run_with_format(config, chunks, stats, LineFormat::Text).await
+}
+
+async fn run_with_format(
+    config: DedupeConfig,
+    chunks: &[&'static str],
+    stats: Arc<FilterStats>,
+    format: LineFormat,
+) -> anyhow::Result<String> {
+    let filter = DedupeStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), config, stats, format);
+    let input = stream::iter(
+        chunks
+            .iter()
+            .map(|&chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+            .collect::<Vec<StreamItem>>(),
+    );
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk?)?);
+    }
+    Ok(output)
+}
+
+const INPUT: [&str; 2] = [
+    "+36 1 234 5678\n0036 30 123 4567\n+36 1 2",
+    "34 5678\nname,phone\n00 36 1 234 56 78\nname,phone\n+36301234567",
+];
+
+#[tokio::test]
+async fn test_exact() {
+    // given
+    let config = DedupeConfig::Exact(ExactConfig {
+        max_memory_bytes: default_max_memory_bytes(),
+    });
+
+    // when
+    let output = run(config, &INPUT).await;
+
+    // then
+    assert_eq!(
+        output.unwrap(),
+        "+36 1 234 5678\n0036 30 123 4567\nname,phone\n"
+    );
+}
+
+#[tokio::test]
+async fn test_csv_records_with_multi_line_fields() {
+    // given
+    let config = DedupeConfig::Exact(ExactConfig {
+        max_memory_bytes: default_max_memory_bytes(),
+    });
+    let chunks = [
+        "name;phone\n\"Anna\nthe \"\"first\"\"\";+36 1 234 5678\n\"Anna",
+        "\nthe \"\"first\"\"\";+36 1 234 5678\n\"Anna\";+36 1 234 5678\n",
+    ];
+
+    // when
+    let output = run_with_format(
+        config,
+        &chunks,
+        Arc::new(FilterStats::default()),
+        LineFormat::Csv { has_header: true },
+    )
+    .await;
+
+    // then
+    assert_eq!(
+        output.unwrap(),
+        "name;phone\n\"Anna\nthe \"\"first\"\"\";+36 1 234 5678\n\"Anna\";+36 1 234 5678\n"
+    );
+}
+
+#[tokio::test]
+async fn test_exact_memory_ceiling() {
+    // given
+    let config = DedupeConfig::Exact(ExactConfig {
+        max_memory_bytes: 100,
+    });
+
+    // when
+    let output = run(config, &INPUT).await;
+
+    // then
+    assert!(output.is_err());
+}
+
+#[tokio::test]
+async fn test_bloom() {
+    // given
+    let config = DedupeConfig::Bloom(BloomConfig {
+        expected_items: 1000,
+        false_positive_rate: 0.0001,
+        max_memory_bytes: default_max_memory_bytes(),
+    });
+    let stats = Arc::new(FilterStats::default());
+
+    // when
+    let output = run_with_stats(config, &INPUT, stats.clone()).await;
+
+    // then
+    assert_eq!(
+        output.unwrap(),
+        "+36 1 234 5678\n0036 30 123 4567\nname,phone\n"
+    );
+    let stats = stats.snapshot();
+    assert_eq!(stats.get("dedupeDuplicates"), Some(&FilterStat::Count(4)));
+    assert!(matches!(
+        stats.get("dedupeFalsePositiveRate"),
+        Some(FilterStat::Ratio(rate)) if *rate > 0.0 && *rate < 0.0001
+    ));
+}
+
+#[test]
+fn test_bloom_false_positive_rate() {
+    // given
+    let mut bloom = BloomFilter::new(10_000, 0.01);
+
+    // when
+    let inserted = (0..10_000)
+        .filter(|i| bloom.insert(&format!("+3630{i:07}")))
+        .count();
+    let false_positives = (10_000..20_000)
+        .filter(|i| !bloom.insert(&format!("+3630{i:07}")))
+        .count();
+
+    // then
+    assert!(inserted > 9_950);
+    // the second half doubles the load, and the rate with it
+    assert!(false_positives < 1_000, "{false_positives} false positives");
+    assert!(bloom.false_positive_rate() > 0.01);
+}
+
+#[test]
+fn test_validate() {
+    let bloom = |expected_items, false_positive_rate| {
+        DedupeConfig::Bloom(BloomConfig {
+            expected_items,
+            false_positive_rate,
+            max_memory_bytes: default_max_memory_bytes(),
+        })
+    };
+    assert!(bloom(1, 0.5).validate().is_ok());
+    assert!(bloom(1, 0.0).validate().is_err());
+    assert!(bloom(1, 1.0).validate().is_err());
+    // the README example, a filter of 120 MB
+    assert!(bloom(50_000_000, 0.0001).validate().is_ok());
+    assert!(bloom(u64::MAX, 0.001).validate().is_err());
+    assert!(bloom(100_000_000, 1e-300).validate().is_err());
+}
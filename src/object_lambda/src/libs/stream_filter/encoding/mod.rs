@@ -0,0 +1,245 @@
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use bytes::{BufMut, Bytes, BytesMut};
+use encoding_rs::{CoderResult, Decoder, Encoder, Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use futures::{stream, StreamExt};
+use serde::{Deserialize, Deserializer};
+
+use super::{process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, StreamItem};
+
+/// The longest byte order mark, UTF-8's.
+const BOM_LEN: usize = 3;
+
+const BUFFER_LEN: usize = 8 * 1024;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum OutputEncoding {
+    #[default]
+    Utf8,
+    /// re-encode the output to the encoding of the input
+    Source,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct EncodingConfig {
+    /// encoding label, e.g. `windows-1250`, `latin2` or `utf-16le`, used when the input
+    /// has neither a byte order mark nor a charset in its Content-Type
+    #[serde(default, deserialize_with = "deserialize_encoding")]
+    pub source: Option<&'static Encoding>,
+    #[serde(default)]
+    pub output: OutputEncoding,
+}
+
+fn deserialize_encoding<'de, D>(deserializer: D) -> Result<Option<&'static Encoding>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let label = String::deserialize(deserializer)?;
+    Encoding::for_label(label.as_bytes())
+        .map(Some)
+        .ok_or_else(|| serde::de::Error::custom(format!("unknown encoding `{label}`")))
+}
+
+/// The charset parameter of a Content-Type header, e.g. `text/csv; charset=windows-1250`.
+fn charset(content_type: &str) -> Option<&'static Encoding> {
+    content_type
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches('"').as_bytes()))
+}
+
+/// Transcodes text inputs to UTF-8 for the filters, and their output back if requested.
+/// Binary inputs such as archives and workbooks are passed through untouched.
+pub struct Transcoder {
+    config: Option<Arc<EncodingConfig>>,
+}
+
+impl Transcoder {
+    pub fn new(config: EncodingConfig) -> Self {
+        Self {
+            config: Some(Arc::new(config)),
+        }
+    }
+
+    pub fn binary() -> Self {
+        Self { config: None }
+    }
+
+    /// Detects the encoding of `s` from its byte order mark, then `content_type`, then the
+    /// configuration, and decodes it to UTF-8 without the byte order mark.
+    pub async fn decode(
+        &self,
+        mut s: BoxedSendSyncUnpinStream<StreamItem>,
+        content_type: Option<&str>,
+    ) -> (BoxedSendSyncUnpinStream<StreamItem>, &'static Encoding) {
+        let Some(config) = &self.config else {
+            return (s, UTF_8);
+        };
+        let mut prefix = BytesMut::new();
+        let mut error = None;
+        while prefix.len() < BOM_LEN {
+            match s.next().await {
+                Some(Ok(chunk)) => prefix.put(chunk),
+                Some(Err(e)) => {
+                    error = Some(e);
+                    break;
+                }
+                None => break,
+            }
+        }
+        let (encoding, bom_len) = Encoding::for_bom(&prefix).unwrap_or_else(|| {
+            let encoding = content_type
+                .and_then(charset)
+                .or(config.source)
+                .unwrap_or(UTF_8);
+            (encoding, 0)
+        });
+        let prefix = prefix.freeze().slice(bom_len..);
+        let s = Box::new(
+            stream::iter((!prefix.is_empty()).then_some(Ok(prefix)))
+                .chain(stream::iter(error.map(Err)))
+                .chain(s),
+        );
+        if encoding == UTF_8 {
+            return (s, encoding);
+        }
+        let processor = DecodeProcessor {
+            decoder: encoding.new_decoder_without_bom_handling(),
+        };
+        (process_stream(s, processor), encoding)
+    }
+
+    /// Re-encodes the UTF-8 output of the filters to `source` if the configuration asks for it.
+    pub fn encode(
+        &self,
+        s: BoxedSendSyncUnpinStream<StreamItem>,
+        source: &'static Encoding,
+    ) -> BoxedSendSyncUnpinStream<StreamItem> {
+        match &self.config {
+            Some(config) if config.output == OutputEncoding::Source && source != UTF_8 => {
+                process_stream(s, EncodeProcessor::new(source))
+            }
+            _ => s,
+        }
+    }
+}
+
+struct DecodeProcessor {
+    decoder: Decoder,
+}
+
+impl DecodeProcessor {
+    fn decode(&mut self, mut input: &[u8], last: bool) -> Bytes {
+        let mut output = BytesMut::new();
+        let mut buffer = [0; BUFFER_LEN];
+        loop {
+            // malformed sequences are replaced with U+FFFD, which the matchers never accept
+            let (result, read, written, _) = self.decoder.decode_to_utf8(input, &mut buffer, last);
+            output.put_slice(&buffer[..written]);
+            input = &input[read..];
+            if result == CoderResult::InputEmpty {
+                return output.freeze();
+            }
+        }
+    }
+}
+
+impl ChunkProcessor for DecodeProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        Ok(self.decode(&chunk, false))
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        Ok(self.decode(&[], true))
+    }
+}
+
+struct EncodeProcessor {
+    encoding: &'static Encoding,
+    /// `None` for UTF-16, which `encoding_rs` only decodes
+    encoder: Option<Encoder>,
+    /// a UTF-8 sequence split across chunks
+    pending: BytesMut,
+    started: bool,
+}
+
+impl EncodeProcessor {
+    fn new(encoding: &'static Encoding) -> Self {
+        let utf16 = encoding == UTF_16LE || encoding == UTF_16BE;
+        Self {
+            encoding,
+            encoder: (!utf16).then(|| encoding.new_encoder()),
+            pending: BytesMut::new(),
+            started: false,
+        }
+    }
+
+    fn encode(&mut self, text: &str, last: bool, output: &mut BytesMut) {
+        if !self.started {
+            self.started = true;
+            // Windows tools rely on the byte order mark to recognize UTF-16
+            match self.encoding {
+                encoding if encoding == UTF_16LE => output.put_u16_le(0xfeff),
+                encoding if encoding == UTF_16BE => output.put_u16(0xfeff),
+                _ => {}
+            }
+        }
+        let Some(encoder) = &mut self.encoder else {
+            let little_endian = self.encoding == UTF_16LE;
+            for unit in text.encode_utf16() {
+                if little_endian {
+                    output.put_u16_le(unit);
+                } else {
+                    output.put_u16(unit);
+                }
+            }
+            return;
+        };
+        let mut input = text;
+        let mut buffer = [0; BUFFER_LEN];
+        loop {
+            // unmappable characters are written as HTML numeric character references
+            let (result, read, written, _) = encoder.encode_from_utf8(input, &mut buffer, last);
+            output.put_slice(&buffer[..written]);
+            input = &input[read..];
+            if result == CoderResult::InputEmpty {
+                return;
+            }
+        }
+    }
+}
+
+impl ChunkProcessor for EncodeProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        self.pending.put(chunk);
+        let valid = match from_utf8(&self.pending) {
+            Ok(text) => text.len(),
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            Err(_) => return Err(anyhow!("filter output is not valid UTF-8")),
+        };
+        let pending = self.pending.split_to(valid);
+        let mut output = BytesMut::new();
+        self.encode(from_utf8(&pending)?, false, &mut output);
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        if !self.pending.is_empty() {
+            return Err(anyhow!(
+                "filter output ends with a truncated UTF-8 sequence"
+            ));
+        }
+        let mut output = BytesMut::new();
+        self.encode("", true, &mut output);
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,144 @@
+use std::str::from_utf8;
+
+use encoding_rs::{ISO_8859_2, WINDOWS_1250};
+use futures::stream;
+
+use super::*;
+
+async fn decode(
+    transcoder: &Transcoder,
+    chunks: &[&[u8]],
+    content_type: Option<&str>,
+) -> (Vec<u8>, &'static Encoding) {
+    let chunks: Vec<StreamItem> = chunks
+        .iter()
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+        .collect();
+    let (s, encoding) = transcoder
+        .decode(Box::new(stream::iter(chunks)), content_type)
+        .await;
+    (collect(s).await, encoding)
+}
+
+async fn collect(mut s: BoxedSendSyncUnpinStream<StreamItem>) -> Vec<u8> {
+    let mut output = vec![];
+    while let Some(chunk) = s.next().await {
+        output.extend_from_slice(&chunk.unwrap());
+    }
+    output
+}
+
+fn utf16le(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xff, 0xfe];
+    bytes.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+    bytes
+}
+
+#[tokio::test]
+async fn test_utf16_bom() {
+    // given
+    let input = utf16le("Árvíztűrő,+36 1 234 5678\n");
+    // split inside the BOM and inside a code unit
+    let chunks: Vec<&[u8]> = vec![&input[..1], &input[1..6], &input[6..]];
+
+    // when
+    let (output, encoding) = decode(&Transcoder::new(Default::default()), &chunks, None).await;
+
+    // then
+    assert_eq!(encoding, UTF_16LE);
+    assert_eq!(from_utf8(&output).unwrap(), "Árvíztűrő,+36 1 234 5678\n");
+}
+
+#[tokio::test]
+async fn test_utf8_bom_removed() {
+    // when
+    let (output, encoding) = decode(
+        &Transcoder::new(Default::default()),
+        &[b"\xef\xbb", b"\xbf+36 1 234 5678\n"],
+        None,
+    )
+    .await;
+
+    // then
+    assert_eq!(encoding, UTF_8);
+    assert_eq!(output, b"+36 1 234 5678\n");
+}
+
+#[tokio::test]
+async fn test_content_type_charset() {
+    // given
+    let (input, _, _) = WINDOWS_1250.encode("Győr,+36 96 123 456\n");
+
+    // when
+    let (output, encoding) = decode(
+        &Transcoder::new(Default::default()),
+        &[&input],
+        Some("text/csv; charset=\"windows-1250\""),
+    )
+    .await;
+
+    // then
+    assert_eq!(encoding, WINDOWS_1250);
+    assert_eq!(from_utf8(&output).unwrap(), "Győr,+36 96 123 456\n");
+}
+
+#[tokio::test]
+async fn test_configured_source() {
+    // given
+    let config = EncodingConfig {
+        source: Some(ISO_8859_2),
+        ..Default::default()
+    };
+    let (input, _, _) = ISO_8859_2.encode("Pécs\n");
+
+    // when
+    let (output, encoding) = decode(&Transcoder::new(config), &[&input], Some("text/plain")).await;
+
+    // then
+    assert_eq!(encoding, ISO_8859_2);
+    assert_eq!(from_utf8(&output).unwrap(), "Pécs\n");
+}
+
+#[tokio::test]
+async fn test_binary_passthrough() {
+    // when
+    let (output, encoding) = decode(&Transcoder::binary(), &[b"\xff\xfePK"], None).await;
+
+    // then
+    assert_eq!(encoding, UTF_8);
+    assert_eq!(output, b"\xff\xfePK");
+}
+
+#[tokio::test]
+async fn test_encode_to_source() {
+    // given
+    let config = EncodingConfig {
+        output: OutputEncoding::Source,
+        ..Default::default()
+    };
+    let text = "Győr\n".as_bytes();
+    // split inside the two-byte `ő`
+    let chunks = || -> BoxedSendSyncUnpinStream<StreamItem> {
+        Box::new(stream::iter([
+            Ok(Bytes::copy_from_slice(&text[..3])),
+            Ok(Bytes::copy_from_slice(&text[3..])),
+        ]))
+    };
+
+    // when
+    let transcoder = Transcoder::new(config);
+    let windows_1250 = collect(transcoder.encode(chunks(), WINDOWS_1250)).await;
+    let utf16 = collect(transcoder.encode(chunks(), UTF_16LE)).await;
+
+    // then
+    assert_eq!(windows_1250, WINDOWS_1250.encode("Győr\n").0.as_ref());
+    assert_eq!(utf16, utf16le("Győr\n"));
+}
+
+#[test]
+fn test_charset() {
+    assert_eq!(charset("text/plain; charset=ISO-8859-2"), Some(ISO_8859_2));
+    assert_eq!(charset("text/plain;Charset=utf-16"), Some(UTF_16LE));
+    assert_eq!(charset("text/plain"), None);
+    assert_eq!(charset("text/plain; charset=unknown"), None);
+}
@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use futures::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+
+use crate::libs::deps::reqwest;
+
+/// An asynchronous source of data about numbers, e.g. their ported operator.
+pub trait Lookup: Send + Sync {
+    /// The data of every number of the batch, in the order of `numbers`, `None` for the
+    /// numbers it knows nothing about.
+    fn lookup<'a>(
+        &'a self,
+        numbers: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Option<String>>>>;
+}
+
+/// Looks the numbers up in a fixed map, standing in for a lookup service.
+pub struct MemoryLookup {
+    entries: HashMap<String, String>,
+}
+
+impl MemoryLookup {
+    pub fn new(entries: HashMap<String, String>) -> Self {
+        Self { entries }
+    }
+}
+
+impl Lookup for MemoryLookup {
+    fn lookup<'a>(
+        &'a self,
+        numbers: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Option<String>>>> {
+        Box::pin(async move {
+            Ok(numbers
+                .iter()
+                .map(|number| self.entries.get(number).cloned())
+                .collect())
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct HttpRequest<'a> {
+    numbers: &'a [String],
+}
+
+#[derive(Deserialize)]
+struct HttpResponse {
+    results: Vec<Option<String>>,
+}
+
+/// Posts every batch as `{"numbers": [...]}` to a lookup service, which answers with
+/// `{"results": [...]}` in the same order.
+pub struct HttpLookup {
+    reqwest: Arc<reqwest::Reqwest>,
+    url: String,
+    timeout: Duration,
+}
+
+impl HttpLookup {
+    pub fn new(reqwest: Arc<reqwest::Reqwest>, url: String, timeout: Duration) -> Self {
+        Self {
+            reqwest,
+            url,
+            timeout,
+        }
+    }
+}
+
+impl Lookup for HttpLookup {
+    fn lookup<'a>(
+        &'a self,
+        numbers: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Option<String>>>> {
+        Box::pin(async move {
+            let body = serde_json::to_string(&HttpRequest { numbers })?;
+            let response = self
+                .reqwest
+                .post_json(&self.url, body, self.timeout)
+                .await
+                .with_context(|| format!("lookup {} failed", self.url))?;
+            let status = response.status();
+            if !status.is_success() {
+                bail!("lookup {} answered {status}", self.url);
+            }
+            let response: HttpResponse = serde_json::from_slice(&response.bytes().await?)
+                .with_context(|| format!("lookup {} answered an invalid response", self.url))?;
+            Ok(response.results)
+        })
+    }
+}
@@ -0,0 +1,282 @@
+use std::collections::HashMap;
+use std::mem;
+use std::str::from_utf8;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{future, stream, StreamExt};
+use serde::Deserialize;
+use tokio::task::JoinHandle;
+
+use crate::libs::deps::reqwest;
+use crate::libs::phone::{self, Verdict};
+
+use self::lookup::{HttpLookup, Lookup, MemoryLookup};
+use super::{BoxedSendSyncUnpinStream, LineBuffer, StreamFilter, StreamItem};
+
+/// The most attempts of a batch, so that a failing lookup fails the request in bounded time.
+const MAX_ATTEMPTS: u32 = 10;
+
+/// The longest delay between the attempts of a batch.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+mod lookup;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum LookupConfig {
+    /// a fixed map of E.164 numbers to their data, e.g. for local testing
+    Memory { entries: HashMap<String, String> },
+    /// a lookup service, see [`HttpLookup`]
+    #[serde(rename_all = "camelCase")]
+    Http {
+        url: String,
+        #[serde(default = "default_timeout_millis")]
+        timeout_millis: u64,
+    },
+}
+
+fn default_timeout_millis() -> u64 {
+    2_000
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct EnrichConfig {
+    pub lookup: LookupConfig,
+    /// lines looked up together
+    #[serde(default = "default_batch_size")]
+    pub batch_size: usize,
+    /// batches looked up at the same time
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+    /// a batch failing this many times fails the request
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// the delay before the first retry of a batch, doubled for every further retry up to 30 s
+    #[serde(default = "default_retry_delay_millis")]
+    pub retry_delay_millis: u64,
+}
+
+fn default_batch_size() -> usize {
+    100
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+fn default_max_attempts() -> u32 {
+    3
+}
+
+fn default_retry_delay_millis() -> u64 {
+    100
+}
+
+impl EnrichConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.batch_size == 0 || self.concurrency == 0 || self.max_attempts == 0 {
+            bail!("enrich batchSize, concurrency and maxAttempts must be positive");
+        }
+        if self.max_attempts > MAX_ATTEMPTS {
+            bail!("enrich maxAttempts must be at most {MAX_ATTEMPTS}");
+        }
+        if let LookupConfig::Http { url, .. } = &self.lookup {
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                bail!("enrich lookup url must be http or https");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Appends the data of a lookup to every line holding just a valid number, as
+/// `<line>\t<data>`, the data being empty for unknown numbers and other lines. Backslashes and
+/// control characters of the data are escaped, so that it stays in its column.
+///
+/// Lines are looked up in batches, up to `concurrency` of them at the same time, and are
+/// output in the order of the input.
+pub struct EnrichStreamFilter {
+    lookup: Arc<dyn Lookup>,
+    config: Arc<EnrichConfig>,
+}
+
+impl EnrichStreamFilter {
+    pub fn new(config: EnrichConfig, reqwest: Arc<reqwest::Reqwest>) -> Self {
+        let lookup: Arc<dyn Lookup> = match &config.lookup {
+            LookupConfig::Memory { entries } => Arc::new(MemoryLookup::new(entries.clone())),
+            LookupConfig::Http {
+                url,
+                timeout_millis,
+            } => Arc::new(HttpLookup::new(
+                reqwest,
+                url.clone(),
+                Duration::from_millis(*timeout_millis),
+            )),
+        };
+        Self::with_lookup(lookup, config)
+    }
+
+    pub fn with_lookup(lookup: Arc<dyn Lookup>, config: EnrichConfig) -> Self {
+        Self {
+            lookup,
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl StreamFilter for EnrichStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        let mut batcher = Batcher {
+            lines: LineBuffer::default(),
+            batch: vec![],
+            batch_size: self.config.batch_size,
+        };
+        let batches = s
+            .map(Some)
+            .chain(stream::once(future::ready(None)))
+            .flat_map(move |item| stream::iter(batcher.push(item)));
+        let lookup = self.lookup.clone();
+        let config = self.config.clone();
+        Box::new(
+            batches
+                .map(move |batch| {
+                    // spawned, as a response stream is shared across threads but a lookup
+                    // future need not be
+                    let mut task =
+                        LookupTask(tokio::spawn(enrich(batch, lookup.clone(), config.clone())));
+                    async move { (&mut task.0).await.context("lookup task failed")? }
+                })
+                .buffered(self.config.concurrency),
+        )
+    }
+}
+
+/// A spawned lookup, aborted once the response stream drops it, e.g. as the request failed.
+struct LookupTask(JoinHandle<anyhow::Result<Bytes>>);
+
+impl Drop for LookupTask {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Collects the lines of the input into batches.
+struct Batcher {
+    lines: LineBuffer,
+    batch: Vec<Vec<u8>>,
+    batch_size: usize,
+}
+
+impl Batcher {
+    /// The batches completed by `item`, all remaining lines once the input ends.
+    fn push(&mut self, item: Option<StreamItem>) -> Vec<anyhow::Result<Vec<Vec<u8>>>> {
+        let mut batches = vec![];
+        let batch = &mut self.batch;
+        let batch_size = self.batch_size;
+        let mut on_line = |line: &[u8]| {
+            batch.push(line.to_vec());
+            if batch.len() == batch_size {
+                batches.push(Ok(mem::take(batch)));
+            }
+            Ok(())
+        };
+        let end = item.is_none();
+        let result = match item {
+            Some(Ok(chunk)) => self.lines.push(&chunk, &mut on_line),
+            Some(Err(error)) => Err(error),
+            None => self.lines.finish(&mut on_line),
+        };
+        if let Err(error) = result {
+            batches.push(Err(error));
+        }
+        if end && !self.batch.is_empty() {
+            batches.push(Ok(mem::take(&mut self.batch)));
+        }
+        batches
+    }
+}
+
+async fn enrich(
+    batch: anyhow::Result<Vec<Vec<u8>>>,
+    lookup: Arc<dyn Lookup>,
+    config: Arc<EnrichConfig>,
+) -> anyhow::Result<Bytes> {
+    let batch = batch?;
+    let numbers: Vec<_> = batch
+        .iter()
+        .map(|line| match from_utf8(line).map(phone::verdict) {
+            Ok(Verdict::Valid(number)) => Some(number),
+            _ => None,
+        })
+        .collect();
+    let queried: Vec<_> = numbers.iter().flatten().cloned().collect();
+    let mut results = if queried.is_empty() {
+        vec![]
+    } else {
+        lookup_with_retries(&*lookup, &queried, &config).await?
+    }
+    .into_iter();
+    let mut output = BytesMut::new();
+    for (line, number) in batch.iter().zip(&numbers) {
+        output.put_slice(line);
+        output.put_u8(b'\t');
+        if let Some(data) = number.as_ref().and_then(|_| results.next().flatten()) {
+            escape(&data, &mut output);
+        }
+        output.put_u8(b'\n');
+    }
+    Ok(output.freeze())
+}
+
+/// Writes `data` with `\\`, `\t`, `\n` and `\r` escaped as such, and other control characters
+/// as `\u{..}`.
+fn escape(data: &str, output: &mut BytesMut) {
+    for c in data.chars() {
+        match c {
+            '\\' => output.put_slice(b"\\\\"),
+            '\t' => output.put_slice(b"\\t"),
+            '\n' => output.put_slice(b"\\n"),
+            '\r' => output.put_slice(b"\\r"),
+            c if c.is_control() => output.put_slice(c.escape_unicode().to_string().as_bytes()),
+            c => output.put_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+}
+
+async fn lookup_with_retries(
+    lookup: &dyn Lookup,
+    numbers: &[String],
+    config: &EnrichConfig,
+) -> anyhow::Result<Vec<Option<String>>> {
+    let mut delay = Duration::from_millis(config.retry_delay_millis).min(MAX_RETRY_DELAY);
+    let mut attempt = 1;
+    loop {
+        let error = match lookup.lookup(numbers).await {
+            Ok(results) if results.len() == numbers.len() => return Ok(results),
+            Ok(results) => anyhow::anyhow!(
+                "lookup returned {} results for {} numbers",
+                results.len(),
+                numbers.len()
+            ),
+            Err(error) => error,
+        };
+        if attempt == config.max_attempts {
+            return Err(error.context(format!("lookup failed {attempt} times")));
+        }
+        tracing::warn!("lookup failed, retrying in {delay:?}: {error:#}");
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).min(MAX_RETRY_DELAY);
+        attempt += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests;
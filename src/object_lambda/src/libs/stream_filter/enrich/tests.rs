@@ -0,0 +1,298 @@
+use std::str::from_utf8;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use aws_lambda_events::http;
+use futures::future::BoxFuture;
+
+use super::*;
+
+fn config(lookup: LookupConfig, batch_size: usize) -> EnrichConfig {
+    EnrichConfig {
+        lookup,
+        batch_size,
+        concurrency: default_concurrency(),
+        max_attempts: default_max_attempts(),
+        retry_delay_millis: 1,
+    }
+}
+
+fn memory(entries: &[(&str, &str)]) -> LookupConfig {
+    LookupConfig::Memory {
+        entries: entries
+            .iter()
+            .map(|(number, data)| (number.to_string(), data.to_string()))
+            .collect(),
+    }
+}
+
+async fn output(filter: &EnrichStreamFilter, chunks: &[&'static [u8]]) -> anyhow::Result<String> {
+    let input = stream::iter(
+        chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from_static(chunk)))
+            .collect::<Vec<_>>(),
+    );
+    let output: anyhow::Result<Vec<_>> = filter
+        .filter_stream(Box::new(input))
+        .collect::<Vec<_>>()
+        .await
+        .into_iter()
+        .collect();
+    Ok(from_utf8(&output?.concat())?.to_string())
+}
+
+/// Answers the later batches first, and fails the first attempt of every batch `failures`
+/// times.
+struct SlowLookup {
+    calls: AtomicUsize,
+    failures: usize,
+}
+
+impl Lookup for SlowLookup {
+    fn lookup<'a>(
+        &'a self,
+        numbers: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Option<String>>>> {
+        Box::pin(async move {
+            let call = self.calls.fetch_add(1, Ordering::SeqCst);
+            if call < self.failures {
+                bail!("unavailable");
+            }
+            let delay = 20u64.saturating_sub(call as u64 * 5);
+            tokio::time::sleep(Duration::from_millis(delay)).await;
+            Ok(numbers
+                .iter()
+                .map(|number| Some(format!("{}", number.len())))
+                .collect())
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_memory_lookup() {
+    // given
+    let filter = EnrichStreamFilter::new(
+        config(memory(&[("+3612345678", "telekom")]), 2),
+        Arc::new(reqwest::Reqwest::faux()),
+    );
+
+    // when
+    let output = output(
+        &filter,
+        &[
+            b"+36 1 234 5678\nhello\n+36 30 123 ",
+            b"4567\n+36 1 234 5678",
+        ],
+    )
+    .await
+    .unwrap();
+
+    // then
+    assert_eq!(
+        output,
+        "+36 1 234 5678\ttelekom\nhello\t\n+36 30 123 4567\t\n+36 1 234 5678\ttelekom\n"
+    );
+}
+
+#[tokio::test]
+async fn test_data_is_escaped() {
+    // given
+    let filter = EnrichStreamFilter::new(
+        config(memory(&[("+3612345678", "a\tb\r\nc\\d\u{1b}é")]), 2),
+        Arc::new(reqwest::Reqwest::faux()),
+    );
+
+    // when
+    let output = output(&filter, &[b"+36 1 234 5678\n"]).await.unwrap();
+
+    // then
+    assert_eq!(output, "+36 1 234 5678\ta\\tb\\r\\nc\\\\d\\u{1b}é\n");
+}
+
+/// Never answers, and tells how many of its lookups are still running.
+struct PendingLookup {
+    running: Arc<()>,
+}
+
+impl Lookup for PendingLookup {
+    fn lookup<'a>(
+        &'a self,
+        _numbers: &'a [String],
+    ) -> BoxFuture<'a, anyhow::Result<Vec<Option<String>>>> {
+        let running = self.running.clone();
+        Box::pin(async move {
+            let _running = running;
+            future::pending().await
+        })
+    }
+}
+
+#[tokio::test]
+async fn test_lookups_are_aborted_with_the_stream() {
+    // given
+    let lookup = Arc::new(PendingLookup {
+        running: Arc::new(()),
+    });
+    let filter = EnrichStreamFilter::with_lookup(lookup.clone(), config(memory(&[]), 1));
+    let input = stream::iter([Ok(Bytes::from_static(b"+36 1 234 5678\n+36 30 123 4567\n"))]);
+    let mut output = filter.filter_stream(Box::new(input));
+    let pending = tokio::time::timeout(Duration::from_millis(20), output.next()).await;
+    let started = Arc::strong_count(&lookup.running) - 1;
+
+    // when
+    drop(output);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+
+    // then
+    assert!(pending.is_err());
+    assert_eq!(started, 2);
+    assert_eq!(Arc::strong_count(&lookup.running), 1);
+}
+
+#[tokio::test]
+async fn test_order_is_kept_across_concurrent_batches() {
+    // given
+    let lookup = Arc::new(SlowLookup {
+        calls: AtomicUsize::new(0),
+        failures: 0,
+    });
+    let filter = EnrichStreamFilter::with_lookup(lookup.clone(), config(memory(&[]), 1));
+
+    // when
+    let output = output(
+        &filter,
+        &[b"+36 1 234 5678\n+36 30 123 4567\n+36 1 234 567\n+36 70 123 4567\n"],
+    )
+    .await
+    .unwrap();
+
+    // then
+    assert_eq!(
+        output,
+        "+36 1 234 5678\t11\n+36 30 123 4567\t12\n+36 1 234 567\t\n+36 70 123 4567\t12\n"
+    );
+    assert_eq!(lookup.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_failed_lookup_is_retried() {
+    // given
+    let lookup = Arc::new(SlowLookup {
+        calls: AtomicUsize::new(0),
+        failures: 2,
+    });
+    let filter = EnrichStreamFilter::with_lookup(lookup.clone(), config(memory(&[]), 10));
+
+    // when
+    let output = output(&filter, &[b"+36 1 234 5678\n"]).await.unwrap();
+
+    // then
+    assert_eq!(output, "+36 1 234 5678\t11\n");
+    assert_eq!(lookup.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_lookup_fails_after_max_attempts() {
+    // given
+    let lookup = Arc::new(SlowLookup {
+        calls: AtomicUsize::new(0),
+        failures: 3,
+    });
+    let filter = EnrichStreamFilter::with_lookup(lookup.clone(), config(memory(&[]), 10));
+
+    // when
+    let output = output(&filter, &[b"+36 1 234 5678\n"]).await;
+
+    // then
+    assert!(format!("{:#}", output.unwrap_err()).contains("lookup failed 3 times"));
+    assert_eq!(lookup.calls.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn test_http_lookup() {
+    // given
+    let mut mock_reqwest = reqwest::Reqwest::faux();
+    faux::when!(mock_reqwest.post_json("https://hlr.example.com/lookup", _, _)).then(
+        |(_, body, _)| {
+            assert_eq!(body, r#"{"numbers":["+3612345678","+36301234567"]}"#);
+            Ok(http::Response::builder()
+                .status(200)
+                .body(r#"{"results":["telekom",null]}"#)
+                .unwrap()
+                .into())
+        },
+    );
+    let filter = EnrichStreamFilter::new(
+        config(
+            LookupConfig::Http {
+                url: "https://hlr.example.com/lookup".into(),
+                timeout_millis: default_timeout_millis(),
+            },
+            10,
+        ),
+        Arc::new(mock_reqwest),
+    );
+
+    // when
+    let output = output(&filter, &[b"+36 1 234 5678\nhello\n+36 30 123 4567\n"])
+        .await
+        .unwrap();
+
+    // then
+    assert_eq!(
+        output,
+        "+36 1 234 5678\ttelekom\nhello\t\n+36 30 123 4567\t\n"
+    );
+}
+
+#[tokio::test]
+async fn test_http_lookup_error_status() {
+    // given
+    let mut mock_reqwest = reqwest::Reqwest::faux();
+    faux::when!(mock_reqwest.post_json).then(|_| {
+        Ok(http::Response::builder()
+            .status(503)
+            .body("")
+            .unwrap()
+            .into())
+    });
+    let mut config = config(
+        LookupConfig::Http {
+            url: "https://hlr.example.com/lookup".into(),
+            timeout_millis: default_timeout_millis(),
+        },
+        10,
+    );
+    config.max_attempts = 1;
+    let filter = EnrichStreamFilter::new(config, Arc::new(mock_reqwest));
+
+    // when
+    let output = output(&filter, &[b"+36 1 234 5678\n"]).await;
+
+    // then
+    assert!(format!("{:#}", output.unwrap_err()).contains("503"));
+}
+
+#[test]
+fn test_config() {
+    // given
+    let json = r#"{"lookup": {"kind": "http", "url": "https://hlr.example.com/lookup"}, "concurrency": 8}"#;
+
+    // when
+    let config: EnrichConfig = serde_json::from_str(json).unwrap();
+
+    // then
+    assert_eq!(config.concurrency, 8);
+    assert_eq!(config.batch_size, 100);
+    config.validate().unwrap();
+    let invalid = EnrichConfig {
+        batch_size: 0,
+        ..config.clone()
+    };
+    assert!(invalid.validate().is_err());
+    let invalid = EnrichConfig {
+        max_attempts: MAX_ATTEMPTS + 1,
+        ..config
+    };
+    assert!(invalid.validate().is_err());
+}
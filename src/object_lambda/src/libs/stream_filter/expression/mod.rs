@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+use std::str::from_utf8;
+
+use anyhow::{bail, Context};
+use regex::{RegexSet, SetMatches};
+use serde::Deserialize;
+
+use crate::libs::phone::{self, Verdict};
+
+use self::parser::{Expr, KEYWORDS};
+use super::line::{LineAction, LineFilter};
+
+mod parser;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ExpressionConfig {
+    /// regexes referenced by name in `keep`, matched against the whole line
+    #[serde(default)]
+    pub patterns: BTreeMap<String, String>,
+    /// the lines for which this holds are kept, e.g. `valid and not type(premiumRate)`
+    pub keep: String,
+}
+
+impl ExpressionConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        Expression::compile(self).map(drop)
+    }
+}
+
+/// A parsed `keep` expression, with its patterns compiled into one [`RegexSet`] so that a line
+/// is scanned once for all of them.
+struct Expression {
+    expr: Expr,
+    patterns: RegexSet,
+}
+
+impl Expression {
+    fn compile(config: &ExpressionConfig) -> anyhow::Result<Self> {
+        for name in config.patterns.keys() {
+            let is_identifier = name.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_')
+                && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+            if !is_identifier || KEYWORDS.contains(&name.to_ascii_lowercase().as_str()) {
+                bail!("pattern name `{name}` must be an identifier other than a keyword");
+            }
+        }
+        let patterns = RegexSet::new(config.patterns.values())
+            .context("filter patterns must be valid regexes")?;
+        let indices = config
+            .patterns
+            .keys()
+            .enumerate()
+            .map(|(index, name)| (name.as_str(), index))
+            .collect();
+        let expr = parser::parse(&config.keep, &indices)
+            .with_context(|| format!("invalid filter expression `{}`", config.keep))?;
+        Ok(Self { expr, patterns })
+    }
+
+    fn eval(&self, expr: &Expr, line: &mut Line) -> bool {
+        match expr {
+            Expr::Pattern(index) => line
+                .matches
+                .get_or_insert_with(|| self.patterns.matches(line.text))
+                .matched(*index),
+            Expr::Valid => line.number().is_some(),
+            Expr::Type(types) => line
+                .number()
+                .and_then(phone::classify)
+                .is_some_and(|classification| types.contains(&classification.number_type)),
+            Expr::Length(comparison, value) => comparison.holds(line.text.chars().count(), *value),
+            Expr::Not(operand) => !self.eval(operand, line),
+            Expr::And(operands) => operands.iter().all(|operand| self.eval(operand, line)),
+            Expr::Or(operands) => operands.iter().any(|operand| self.eval(operand, line)),
+        }
+    }
+}
+
+/// A line being evaluated, with the pattern matches and the number computed on first use.
+struct Line<'a> {
+    text: &'a str,
+    matches: Option<SetMatches>,
+    number: Option<Option<String>>,
+}
+
+impl Line<'_> {
+    fn number(&mut self) -> Option<&str> {
+        self.number
+            .get_or_insert_with(|| match phone::verdict(self.text) {
+                Verdict::Valid(number) => Some(number),
+                Verdict::Rejected(_) => None,
+            })
+            .as_deref()
+    }
+}
+
+/// Keeps the lines for which the expression holds, dropping the lines which are not UTF-8.
+pub struct ExpressionFilter {
+    expression: Expression,
+}
+
+impl ExpressionFilter {
+    pub fn new(config: &ExpressionConfig) -> anyhow::Result<Self> {
+        Ok(Self {
+            expression: Expression::compile(config)?,
+        })
+    }
+}
+
+impl LineFilter for ExpressionFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        let Ok(text) = from_utf8(line) else {
+            return LineAction::Drop;
+        };
+        let mut line = Line {
+            text,
+            matches: None,
+            number: None,
+        };
+        if self.expression.eval(&self.expression.expr, &mut line) {
+            LineAction::Keep
+        } else {
+            LineAction::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,240 @@
+use std::collections::BTreeMap;
+
+use anyhow::{anyhow, bail};
+use serde::de::value::{Error as ValueError, StrDeserializer};
+use serde::Deserialize;
+
+use crate::libs::phone::NumberType;
+
+/// A comparison of the line length.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl Comparison {
+    pub fn holds(self, left: usize, right: usize) -> bool {
+        match self {
+            Comparison::Lt => left < right,
+            Comparison::Le => left <= right,
+            Comparison::Gt => left > right,
+            Comparison::Ge => left >= right,
+            Comparison::Eq => left == right,
+            Comparison::Ne => left != right,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    /// the named pattern with this index matches the line
+    Pattern(usize),
+    /// the line holds just a valid number
+    Valid,
+    /// the line holds just a valid number of one of these types
+    Type(Vec<NumberType>),
+    /// the number of characters of the line compares to the value
+    Length(Comparison, usize),
+    Not(Box<Expr>),
+    And(Vec<Expr>),
+    Or(Vec<Expr>),
+}
+
+pub const KEYWORDS: [&str; 6] = ["and", "or", "not", "valid", "type", "length"];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Integer(usize),
+    Comparison(Comparison),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    Comma,
+}
+
+fn tokenize(source: &str) -> anyhow::Result<Vec<(usize, Token)>> {
+    let mut tokens = vec![];
+    let mut chars = source.char_indices().peekable();
+    while let Some((position, c)) = chars.next() {
+        let mut next_is = |expected: char| chars.next_if(|&(_, c)| c == expected).is_some();
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            ',' => Token::Comma,
+            '&' if next_is('&') => Token::And,
+            '|' if next_is('|') => Token::Or,
+            '!' if next_is('=') => Token::Comparison(Comparison::Ne),
+            '!' => Token::Not,
+            '=' if next_is('=') => Token::Comparison(Comparison::Eq),
+            '<' if next_is('=') => Token::Comparison(Comparison::Le),
+            '<' => Token::Comparison(Comparison::Lt),
+            '>' if next_is('=') => Token::Comparison(Comparison::Ge),
+            '>' => Token::Comparison(Comparison::Gt),
+            c if c.is_ascii_digit() => {
+                let mut end = position + 1;
+                while let Some((index, _)) = chars.next_if(|(_, c)| c.is_ascii_digit()) {
+                    end = index + 1;
+                }
+                Token::Integer(
+                    source[position..end]
+                        .parse()
+                        .map_err(|_| anyhow!("integer at {position} is too large"))?,
+                )
+            }
+            c if c.is_ascii_alphabetic() || c == '_' => {
+                let mut end = position + 1;
+                while let Some((index, _)) =
+                    chars.next_if(|(_, c)| c.is_ascii_alphanumeric() || *c == '_')
+                {
+                    end = index + 1;
+                }
+                let word = &source[position..end];
+                match word.to_ascii_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Ident(word.into()),
+                }
+            }
+            c => bail!("unexpected `{c}` at {position}"),
+        };
+        tokens.push((position, token));
+    }
+    Ok(tokens)
+}
+
+/// Parses `source`, resolving the pattern names to their index in `patterns`.
+pub fn parse(source: &str, patterns: &BTreeMap<&str, usize>) -> anyhow::Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        position: 0,
+        end: source.len(),
+        patterns,
+    };
+    let expr = parser.or()?;
+    if let Some((position, token)) = parser.tokens.get(parser.position) {
+        bail!("unexpected {token:?} at {position}");
+    }
+    Ok(expr)
+}
+
+struct Parser<'a> {
+    tokens: Vec<(usize, Token)>,
+    position: usize,
+    end: usize,
+    patterns: &'a BTreeMap<&'a str, usize>,
+}
+
+impl Parser<'_> {
+    fn next(&mut self) -> anyhow::Result<(usize, Token)> {
+        let token = self
+            .tokens
+            .get(self.position)
+            .cloned()
+            .ok_or_else(|| anyhow!("unexpected end at {}", self.end))?;
+        self.position += 1;
+        Ok(token)
+    }
+
+    fn next_if(&mut self, expected: &Token) -> bool {
+        let found = self
+            .tokens
+            .get(self.position)
+            .is_some_and(|(_, token)| token == expected);
+        if found {
+            self.position += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, expected: Token) -> anyhow::Result<()> {
+        match self.next()? {
+            (_, token) if token == expected => Ok(()),
+            (position, token) => bail!("expected {expected:?} at {position}, found {token:?}"),
+        }
+    }
+
+    fn or(&mut self) -> anyhow::Result<Expr> {
+        let mut operands = vec![self.and()?];
+        while self.next_if(&Token::Or) {
+            operands.push(self.and()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Expr::Or(operands)
+        })
+    }
+
+    fn and(&mut self) -> anyhow::Result<Expr> {
+        let mut operands = vec![self.not()?];
+        while self.next_if(&Token::And) {
+            operands.push(self.not()?);
+        }
+        Ok(if operands.len() == 1 {
+            operands.remove(0)
+        } else {
+            Expr::And(operands)
+        })
+    }
+
+    fn not(&mut self) -> anyhow::Result<Expr> {
+        if self.next_if(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.atom()
+    }
+
+    fn atom(&mut self) -> anyhow::Result<Expr> {
+        let (position, token) = self.next()?;
+        let Token::Ident(name) = token else {
+            if token == Token::Open {
+                let expr = self.or()?;
+                self.expect(Token::Close)?;
+                return Ok(expr);
+            }
+            bail!("unexpected {token:?} at {position}");
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "valid" => Ok(Expr::Valid),
+            "type" => {
+                self.expect(Token::Open)?;
+                let mut types = vec![self.number_type()?];
+                while self.next_if(&Token::Comma) {
+                    types.push(self.number_type()?);
+                }
+                self.expect(Token::Close)?;
+                Ok(Expr::Type(types))
+            }
+            "length" => match (self.next()?, self.next()?) {
+                ((_, Token::Comparison(comparison)), (_, Token::Integer(value))) => {
+                    Ok(Expr::Length(comparison, value))
+                }
+                _ => bail!("expected a comparison with an integer after `length` at {position}"),
+            },
+            _ => match self.patterns.get(name.as_str()) {
+                Some(&index) => Ok(Expr::Pattern(index)),
+                None => bail!("unknown pattern `{name}` at {position}"),
+            },
+        }
+    }
+
+    fn number_type(&mut self) -> anyhow::Result<NumberType> {
+        match self.next()? {
+            (position, Token::Ident(name)) => {
+                NumberType::deserialize(StrDeserializer::<ValueError>::new(&name))
+                    .map_err(|_| anyhow!("unknown number type `{name}` at {position}"))
+            }
+            (position, token) => bail!("expected a number type at {position}, found {token:?}"),
+        }
+    }
+}
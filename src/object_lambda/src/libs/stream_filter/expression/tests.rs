@@ -0,0 +1,137 @@
+use super::parser::Comparison;
+use super::*;
+
+fn config(patterns: &[(&str, &str)], keep: &str) -> ExpressionConfig {
+    ExpressionConfig {
+        patterns: patterns
+            .iter()
+            .map(|(name, pattern)| (name.to_string(), pattern.to_string()))
+            .collect(),
+        keep: keep.into(),
+    }
+}
+
+fn kept(config: &ExpressionConfig, lines: &[&str]) -> Vec<String> {
+    let filter = ExpressionFilter::new(config).unwrap();
+    lines
+        .iter()
+        .filter(|line| filter.filter_line(line.as_bytes()) == LineAction::Keep)
+        .map(|line| line.to_string())
+        .collect()
+}
+
+#[test]
+fn test_precedence() {
+    // given
+    let config = config(
+        &[("a", "a"), ("b", "b"), ("c", "c")],
+        "a or not b and (c || length>=3)",
+    );
+
+    // when
+    let expression = Expression::compile(&config).unwrap();
+
+    // then
+    assert_eq!(
+        expression.expr,
+        Expr::Or(vec![
+            Expr::Pattern(0),
+            Expr::And(vec![
+                Expr::Not(Box::new(Expr::Pattern(1))),
+                Expr::Or(vec![Expr::Pattern(2), Expr::Length(Comparison::Ge, 3)]),
+            ]),
+        ])
+    );
+}
+
+#[test]
+fn test_valid_not_premium_not_test_range() {
+    // given
+    let config = config(
+        &[("testRange", r"^\+36 ?1 ?555")],
+        "valid AND NOT type(premiumRate) AND NOT testRange",
+    );
+
+    // when
+    let kept = kept(
+        &config,
+        &[
+            "+36 1 234 5678",
+            "+36 90 123 4567",
+            "+36 1 555 1234",
+            "+36 30 123 4567",
+            "phone",
+        ],
+    );
+
+    // then
+    assert_eq!(kept, ["+36 1 234 5678", "+36 30 123 4567"]);
+    assert_eq!(
+        self::kept(
+            &self::config(&[], "type(premiumRate)"),
+            &["+36 90 123 4567"]
+        ),
+        ["+36 90 123 4567"]
+    );
+}
+
+#[test]
+fn test_either_pattern() {
+    // given
+    let config = config(&[("a", "^0036"), ("b", "^06")], "a or b");
+
+    // when
+    let kept = kept(
+        &config,
+        &["0036 1 234 5678", "06 1 234 5678", "+36 1 234 5678"],
+    );
+
+    // then
+    assert_eq!(kept, ["0036 1 234 5678", "06 1 234 5678"]);
+}
+
+#[test]
+fn test_length_and_types() {
+    // given
+    let config = config(&[], "!valid && length < 6 || type(mobile, tollFree)");
+
+    // when
+    let kept = kept(
+        &config,
+        &[
+            "+36 30 123 4567",
+            "+36 80 123 4567",
+            "+36 1 234 5678",
+            "abc",
+            "abcdef",
+        ],
+    );
+
+    // then
+    assert_eq!(kept, ["+36 30 123 4567", "+36 80 123 4567", "abc"]);
+}
+
+#[test]
+fn test_rejected_configs() {
+    for (patterns, keep) in [
+        (vec![], "unknown"),
+        (vec![], "type(premium)"),
+        (vec![], "valid and"),
+        (vec![], "(valid"),
+        (vec![], "valid valid"),
+        (vec![], "length > x"),
+        (vec![], "valid # comment"),
+        (vec![("bad", "(")], "bad"),
+        (vec![("valid", "x")], "valid"),
+        (vec![("has space", "x")], "valid"),
+    ] {
+        // given
+        let config = config(&patterns, keep);
+
+        // when
+        let result = config.validate();
+
+        // then
+        assert!(result.is_err(), "{keep}");
+    }
+}
@@ -0,0 +1,166 @@
+use std::sync::Arc;
+
+use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::libs::phone;
+use crate::libs::stats::FilterStats;
+
+use super::{
+    process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter, StreamItem,
+};
+
+pub use path::JsonPath;
+
+mod path;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum JsonlOutput {
+    /// every record with at least one matching number, as it was read
+    #[default]
+    Records,
+    /// the normalized matching numbers, one per line
+    Numbers,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct JsonlConfig {
+    #[serde(deserialize_with = "deserialize_paths")]
+    pub fields: Vec<JsonPath>,
+    #[serde(default)]
+    pub output: JsonlOutput,
+}
+
+fn deserialize_paths<'de, D>(deserializer: D) -> Result<Vec<JsonPath>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .into_iter()
+        .map(JsonPath::try_from)
+        .collect::<anyhow::Result<_>>()
+        .map_err(serde::de::Error::custom)
+}
+
+impl JsonlConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.fields.is_empty() {
+            bail!("JSONL input needs at least one field");
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the records with a matching number in one of the fields, reporting the lines which
+/// are not JSON as `jsonlMalformed`.
+pub struct JsonlStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<JsonlConfig>,
+    stats: Arc<FilterStats>,
+}
+
+impl JsonlStreamFilter {
+    pub fn new(regex: Regex, config: JsonlConfig, stats: Arc<FilterStats>) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+            stats,
+        }
+    }
+}
+
+impl StreamFilter for JsonlStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            JsonlProcessor {
+                lines: LineBuffer::default(),
+                matcher: JsonlMatcher {
+                    regex: self.regex.clone(),
+                    config: self.config.clone(),
+                    malformed: 0,
+                },
+                stats: self.stats.clone(),
+            },
+        )
+    }
+}
+
+struct JsonlProcessor {
+    lines: LineBuffer,
+    matcher: JsonlMatcher,
+    stats: Arc<FilterStats>,
+}
+
+struct JsonlMatcher {
+    regex: Arc<Regex>,
+    config: Arc<JsonlConfig>,
+    malformed: usize,
+}
+
+impl JsonlMatcher {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        if line.trim_ascii().is_empty() {
+            return Ok(());
+        }
+        let Ok(record) = serde_json::from_slice::<Value>(line) else {
+            self.malformed += 1;
+            return Ok(());
+        };
+        let mut matches = self
+            .config
+            .fields
+            .iter()
+            .flat_map(|path| path.select(&record))
+            .filter_map(Value::as_str)
+            .filter(|value| self.regex.is_match(value));
+        match self.config.output {
+            JsonlOutput::Records => {
+                if matches.next().is_some() {
+                    output.put_slice(line);
+                    output.put_u8(b'\n');
+                }
+            }
+            JsonlOutput::Numbers => {
+                for number in matches.filter_map(phone::normalize) {
+                    output.put_slice(number.as_bytes());
+                    output.put_u8(b'\n');
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ChunkProcessor for JsonlProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .push(&chunk, |line| self.matcher.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .finish(|line| self.matcher.on_line(line, &mut output))?;
+        if self.matcher.malformed > 0 {
+            tracing::warn!("skipped {} malformed JSON lines", self.matcher.malformed);
+        }
+        self.stats
+            .count("jsonlMalformed", self.matcher.malformed as u64);
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
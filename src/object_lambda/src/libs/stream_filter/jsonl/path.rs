@@ -0,0 +1,88 @@
+use std::fmt;
+
+use anyhow::{anyhow, bail};
+use serde_json::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Field(String),
+    Index(usize),
+    Wildcard,
+}
+
+/// A simple path into a JSON document, e.g. `contact.phones[*]` or `phones[0].number`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonPath {
+    source: String,
+    segments: Vec<Segment>,
+}
+
+impl JsonPath {
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let mut segments = vec![];
+        for part in source.split('.') {
+            let (name, mut subscripts) = match part.find('[') {
+                Some(position) => part.split_at(position),
+                None => (part, ""),
+            };
+            if name.is_empty() || name.contains(']') {
+                bail!("invalid path `{source}`: expected a field name in `{part}`");
+            }
+            segments.push(Segment::Field(name.to_string()));
+            while !subscripts.is_empty() {
+                let end = subscripts
+                    .find(']')
+                    .filter(|_| subscripts.starts_with('['))
+                    .ok_or_else(|| anyhow!("invalid path `{source}`: malformed `{part}`"))?;
+                segments.push(match &subscripts[1..end] {
+                    "*" => Segment::Wildcard,
+                    index => Segment::Index(index.parse().map_err(|_| {
+                        anyhow!("invalid path `{source}`: `{index}` is not an index")
+                    })?),
+                });
+                subscripts = &subscripts[end + 1..];
+            }
+        }
+        Ok(Self {
+            source: source.to_string(),
+            segments,
+        })
+    }
+
+    /// All values the path resolves to; missing fields resolve to nothing.
+    pub fn select<'a>(&self, value: &'a Value) -> Vec<&'a Value> {
+        let mut values = vec![value];
+        for segment in &self.segments {
+            values = values
+                .into_iter()
+                .flat_map(|value| -> Vec<&'a Value> {
+                    match (segment, value) {
+                        (Segment::Field(name), Value::Object(map)) => {
+                            map.get(name).into_iter().collect()
+                        }
+                        (Segment::Index(index), Value::Array(array)) => {
+                            array.get(*index).into_iter().collect()
+                        }
+                        (Segment::Wildcard, Value::Array(array)) => array.iter().collect(),
+                        _ => vec![],
+                    }
+                })
+                .collect();
+        }
+        values
+    }
+}
+
+impl fmt::Display for JsonPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+impl TryFrom<String> for JsonPath {
+    type Error = anyhow::Error;
+
+    fn try_from(source: String) -> Result<Self, Self::Error> {
+        Self::parse(&source)
+    }
+}
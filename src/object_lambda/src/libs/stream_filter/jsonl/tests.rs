@@ -0,0 +1,118 @@
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+use serde_json::json;
+
+use crate::libs::phone::HU_PATTERN;
+use crate::libs::stats::FilterStat;
+
+use super::*;
+
+async fn run(config: JsonlConfig, chunks: &[&'static str]) -> String {
+    run_with_stats(config, chunks, Arc::new(FilterStats::default())).await
+}
+
+async fn run_with_stats(
+    config: JsonlConfig,
+    chunks: &[&'static str],
+    stats: Arc<FilterStats>,
+) -> String {
+    let filter = JsonlStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), config, stats);
+    let input = stream::iter(
+        chunks
+            .iter()
+            .map(|&chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+            .collect::<Vec<StreamItem>>(),
+    );
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk.unwrap()).unwrap());
+    }
+    output
+}
+
+fn config(fields: &[&str], output: JsonlOutput) -> JsonlConfig {
+    JsonlConfig {
+        fields: fields.iter().map(|f| JsonPath::parse(f).unwrap()).collect(),
+        output,
+    }
+}
+
+const INPUT: [&str; 3] = [
+    "{\"name\": \"Anna\", \"contact\": {\"phones\": [\"+36 1 234 5678\", \"n/a\"]}}\n{\"na",
+    "me\": \"Béla\", \"contact\": {\"phones\": [\"12345\"]}}\n{broken\n",
+    "{\"name\": \"Cili\", \"mobile\": \"0036301234567\", \"contact\": {\"phones\": []}}",
+];
+
+#[tokio::test]
+async fn test_records_output() {
+    // given
+    let config = config(&["contact.phones[*]", "mobile"], JsonlOutput::Records);
+
+    // when
+    let output = run(config, &INPUT).await;
+
+    // then
+    let records: Vec<_> = output.lines().collect();
+    assert_eq!(records.len(), 2);
+    assert!(records[0].contains("Anna"));
+    assert!(records[1].contains("Cili"));
+}
+
+#[tokio::test]
+async fn test_numbers_output() {
+    // given
+    let config = config(&["contact.phones[*]", "mobile"], JsonlOutput::Numbers);
+
+    // when
+    let output = run(config, &INPUT).await;
+
+    // then
+    assert_eq!(output, "+3612345678\n+36301234567\n");
+}
+
+#[tokio::test]
+async fn test_malformed_records_are_reported() {
+    // given
+    let config = config(&["mobile"], JsonlOutput::Numbers);
+    let stats = Arc::new(FilterStats::default());
+
+    // when
+    run_with_stats(config, &INPUT, stats.clone()).await;
+
+    // then
+    assert_eq!(
+        stats.snapshot().get("jsonlMalformed"),
+        Some(&FilterStat::Count(1))
+    );
+}
+
+#[test]
+fn test_path_select() {
+    // given
+    let value = json!({"a": {"b": [{"c": "x"}, {"c": "y"}, {"d": "z"}]}});
+
+    // then
+    assert_eq!(
+        JsonPath::parse("a.b[*].c").unwrap().select(&value),
+        vec![&json!("x"), &json!("y")]
+    );
+    assert_eq!(
+        JsonPath::parse("a.b[1].c").unwrap().select(&value),
+        vec![&json!("y")]
+    );
+    assert!(JsonPath::parse("a.missing[*]")
+        .unwrap()
+        .select(&value)
+        .is_empty());
+}
+
+#[test]
+fn test_path_parse_errors() {
+    assert!(JsonPath::parse("").is_err());
+    assert!(JsonPath::parse("a..b").is_err());
+    assert!(JsonPath::parse("a[1").is_err());
+    assert!(JsonPath::parse("a[-1]").is_err());
+    assert!(JsonPath::parse("a]").is_err());
+}
@@ -0,0 +1,115 @@
+use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{future, stream, StreamExt};
+use serde::Deserialize;
+
+use super::{
+    BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, LineFormat, StreamFilter, StreamItem,
+};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct LimitConfig {
+    pub lines: u64,
+}
+
+impl LimitConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.lines == 0 {
+            bail!("limit lines must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Keeps the first lines, then ends the stream, so that the rest of the input is not read. CSV
+/// records count as one line whole, and a header row does not count.
+pub struct LimitStreamFilter {
+    config: LimitConfig,
+    format: LineFormat,
+}
+
+impl LimitStreamFilter {
+    pub fn new(config: LimitConfig, format: LineFormat) -> Self {
+        Self { config, format }
+    }
+}
+
+impl StreamFilter for LimitStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        let processor = LimitProcessor {
+            lines: self.format.line_buffer(),
+            limit: Limit {
+                header: matches!(self.format, LineFormat::Csv { has_header: true }),
+                remaining: self.config.lines,
+            },
+        };
+        let s = s.map(Some).chain(stream::once(future::ready(None)));
+        // the input is not polled once the limit is reached
+        Box::new(Box::pin(stream::unfold(
+            Some((s, processor)),
+            |state| async move {
+                let (mut s, mut processor) = state?;
+                let output = match s.next().await? {
+                    Some(Ok(chunk)) => processor.process(chunk),
+                    Some(Err(error)) => Err(error),
+                    None => processor.finish(),
+                };
+                let done = processor.limit.remaining == 0 || output.is_err();
+                Some((output, (!done).then_some((s, processor))))
+            },
+        )))
+    }
+}
+
+struct LimitProcessor {
+    lines: LineBuffer,
+    limit: Limit,
+}
+
+struct Limit {
+    /// whether the next line is a header row, kept without counting
+    header: bool,
+    remaining: u64,
+}
+
+impl Limit {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) {
+        if self.header {
+            self.header = false;
+            output.put_slice(line);
+            output.put_u8(b'\n');
+        } else if self.remaining > 0 {
+            self.remaining -= 1;
+            output.put_slice(line);
+            output.put_u8(b'\n');
+        }
+    }
+}
+
+impl ChunkProcessor for LimitProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.push(&chunk, |line| {
+            self.limit.on_line(line, &mut output);
+            Ok(())
+        })?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.finish(|line| {
+            self.limit.on_line(line, &mut output);
+            Ok(())
+        })?;
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,80 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use super::*;
+
+#[tokio::test]
+async fn test_limit_stops_reading() {
+    // given
+    let read = Arc::new(AtomicUsize::new(0));
+    let input = stream::iter([
+        Ok(Bytes::from_static(b"+36 1 234 5671\n+36 1 ")),
+        Ok(Bytes::from_static(b"234 5672\n+36 1 234 5673\n")),
+        Ok(Bytes::from_static(b"+36 1 234 5674\n")),
+    ])
+    .inspect({
+        let read = read.clone();
+        move |_| {
+            read.fetch_add(1, Ordering::SeqCst);
+        }
+    });
+    let filter = LimitStreamFilter::new(LimitConfig { lines: 2 }, LineFormat::Text);
+
+    // when
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    // then
+    assert_eq!(output.concat(), b"+36 1 234 5671\n+36 1 234 5672\n");
+    assert_eq!(read.load(Ordering::SeqCst), 2);
+}
+
+#[tokio::test]
+async fn test_short_input() {
+    // given
+    let input = stream::iter([Ok(Bytes::from_static(b"a\nb"))]);
+    let filter = LimitStreamFilter::new(LimitConfig { lines: 5 }, LineFormat::Text);
+
+    // when
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    // then
+    assert_eq!(output.concat(), b"a\nb\n");
+}
+
+#[tokio::test]
+async fn test_csv_records_with_multi_line_fields() {
+    // given
+    let input = stream::iter([
+        Ok(Bytes::from_static(
+            b"name;phone\n\"Anna\nthe first\";+36 1 ",
+        )),
+        Ok(Bytes::from_static(
+            b"234 5671\n\"B\r\n\";+36 1 234 5672\nC;+36 1 234 5673\n",
+        )),
+    ]);
+    let filter = LimitStreamFilter::new(
+        LimitConfig { lines: 2 },
+        LineFormat::Csv { has_header: true },
+    );
+
+    // when
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+
+    // then
+    assert_eq!(
+        output.concat(),
+        b"name;phone\n\"Anna\nthe first\";+36 1 234 5671\n\"B\r\n\";+36 1 234 5672\n"
+    );
+}
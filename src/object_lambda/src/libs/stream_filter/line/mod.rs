@@ -0,0 +1,177 @@
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{future, stream, StreamExt};
+use regex::Regex;
+
+use self::parallel::{filter_block, offload, BlockSplitter};
+use super::{
+    process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter, StreamItem,
+};
+
+mod parallel;
+
+/// What becomes of a line.
+#[derive(Debug, PartialEq)]
+pub enum LineAction {
+    Keep,
+    Drop,
+    /// outputs these bytes instead of the line, without a line terminator
+    Replace(Vec<u8>),
+}
+
+/// Per-line logic which [`LineStreamFilter`] turns into a [`StreamFilter`].
+pub trait LineFilter {
+    /// Decides on one complete line, given without its `\n` or `\r\n` terminator.
+    fn filter_line(&self, line: &[u8]) -> LineAction;
+}
+
+/// Keeps the valid UTF-8 lines matching the regex.
+impl LineFilter for Regex {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        match from_utf8(line) {
+            Ok(line) if self.is_match(line) => LineAction::Keep,
+            _ => LineAction::Drop,
+        }
+    }
+}
+
+/// How [`filter_lines`] spreads the work over the worker pool.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Parallelism {
+    /// chunks filtered at the same time, in addition to the one being output
+    pub chunks_in_flight: usize,
+    /// chunks of at least this many bytes are split into pieces filtered in parallel
+    pub min_split_bytes: usize,
+}
+
+impl Default for Parallelism {
+    fn default() -> Self {
+        Self {
+            chunks_in_flight: 4,
+            min_split_bytes: 64 * 1024,
+        }
+    }
+}
+
+/// Applies a [`LineFilter`] to every line of the stream, including a trailing line without a
+/// terminator. Every output line is terminated by `\n`.
+pub struct LineStreamFilter<L> {
+    filter: Arc<L>,
+    parallelism: Option<Parallelism>,
+}
+
+impl<L> LineStreamFilter<L>
+where
+    L: LineFilter + Send + Sync + 'static,
+{
+    pub fn new(filter: L) -> Self {
+        Self::with_parallelism(filter, Some(Parallelism::default()))
+    }
+
+    /// Filters on the worker pool as `parallelism` says, or on the thread polling the stream
+    /// if it is `None`.
+    pub fn with_parallelism(filter: L, parallelism: Option<Parallelism>) -> Self {
+        Self {
+            filter: Arc::new(filter),
+            parallelism,
+        }
+    }
+}
+
+impl<L> StreamFilter for LineStreamFilter<L>
+where
+    L: LineFilter + Send + Sync + 'static,
+{
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        filter_lines(s, self.filter.clone(), self.parallelism)
+    }
+}
+
+/// The adapter of [`LineStreamFilter`], for filters which share their line filter.
+///
+/// With `parallelism`, the complete lines of every chunk are filtered on the rayon pool, so the
+/// runtime is free to write the output meanwhile, and the output keeps the order of the input.
+pub fn filter_lines<L>(
+    s: BoxedSendSyncUnpinStream<StreamItem>,
+    filter: Arc<L>,
+    parallelism: Option<Parallelism>,
+) -> BoxedSendSyncUnpinStream<StreamItem>
+where
+    L: LineFilter + Send + Sync + 'static,
+{
+    let Some(parallelism) = parallelism else {
+        return process_stream(
+            s,
+            LineProcessor {
+                lines: LineBuffer::default(),
+                filter,
+            },
+        );
+    };
+    let mut blocks = BlockSplitter::default();
+    Box::new(
+        s.map(Some)
+            .chain(stream::once(future::ready(None)))
+            .map(move |item| {
+                let block = match item {
+                    Some(Ok(chunk)) => Ok(blocks.push(chunk)),
+                    Some(Err(error)) => Err(error),
+                    None => Ok(blocks.finish()),
+                };
+                let filter = filter.clone();
+                async move {
+                    let block = block?;
+                    if block.is_empty() {
+                        return Ok(block);
+                    }
+                    offload(move || filter_block(&block, &*filter, parallelism.min_split_bytes))
+                        .await?
+                }
+            })
+            .buffered(parallelism.chunks_in_flight.max(1)),
+    )
+}
+
+struct LineProcessor<L> {
+    lines: LineBuffer,
+    filter: Arc<L>,
+}
+
+/// Writes what `action` makes of `line` to `output`, terminated by `\n`.
+pub fn write_line(line: &[u8], action: LineAction, output: &mut BytesMut) {
+    match action {
+        LineAction::Keep => output.put_slice(line),
+        LineAction::Drop => return,
+        LineAction::Replace(replacement) => output.put_slice(&replacement),
+    }
+    output.put_u8(b'\n');
+}
+
+impl<L: LineFilter> ChunkProcessor for LineProcessor<L> {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.push(&chunk, |line| {
+            write_line(line, self.filter.filter_line(line), &mut output);
+            Ok(())
+        })?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.finish(|line| {
+            write_line(line, self.filter.filter_line(line), &mut output);
+            Ok(())
+        })?;
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,123 @@
+use std::any::Any;
+use std::mem;
+use std::panic::{self, AssertUnwindSafe};
+
+use anyhow::anyhow;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::channel::oneshot;
+use rayon::prelude::*;
+
+use super::{write_line, LineBuffer, LineFilter};
+
+/// Cuts the input into blocks of complete lines, carrying a partial line over to the next
+/// block.
+#[derive(Default)]
+pub(super) struct BlockSplitter {
+    leftover: BytesMut,
+}
+
+impl BlockSplitter {
+    /// The complete lines of `chunk`, with the partial line of the previous chunk in front.
+    pub(super) fn push(&mut self, chunk: Bytes) -> Bytes {
+        let Some(last) = chunk.iter().rposition(|&b| b == b'\n') else {
+            self.leftover.put_slice(&chunk);
+            return Bytes::new();
+        };
+        let block = if self.leftover.is_empty() {
+            chunk.slice(..=last)
+        } else {
+            self.leftover.put_slice(&chunk[..=last]);
+            mem::take(&mut self.leftover).freeze()
+        };
+        self.leftover.put_slice(&chunk[last + 1..]);
+        block
+    }
+
+    /// The trailing line if the input did not end with a line terminator.
+    pub(super) fn finish(&mut self) -> Bytes {
+        mem::take(&mut self.leftover).freeze()
+    }
+}
+
+/// Runs `f` on the worker pool, so that it does not hold up the tasks of the runtime.
+pub(super) async fn offload<T, F>(f: F) -> anyhow::Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> T + Send + 'static,
+{
+    let (sender, receiver) = oneshot::channel();
+    rayon::spawn(move || {
+        // a panic would abort the process on the pool, so it is returned instead
+        let _ = sender.send(panic::catch_unwind(AssertUnwindSafe(f)));
+    });
+    receiver
+        .await
+        .map_err(|_| anyhow!("line filter worker is gone"))?
+        .map_err(|panic| anyhow!("line filter panicked: {}", panic_message(&*panic)))
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown cause"
+    }
+}
+
+/// Filters a block of lines, splitting one of at least `min_split_bytes` into a piece per
+/// worker which are filtered in parallel.
+pub(super) fn filter_block<L>(
+    block: &[u8],
+    filter: &L,
+    min_split_bytes: usize,
+) -> anyhow::Result<Bytes>
+where
+    L: LineFilter + Sync,
+{
+    if block.len() < min_split_bytes {
+        return filter_piece(block, filter);
+    }
+    let outputs = split_at_lines(block, rayon::current_num_threads())
+        .into_par_iter()
+        .map(|piece| filter_piece(piece, filter))
+        .collect::<anyhow::Result<Vec<_>>>()?;
+    let mut output = BytesMut::with_capacity(outputs.iter().map(Bytes::len).sum());
+    for piece in outputs {
+        output.put_slice(&piece);
+    }
+    Ok(output.freeze())
+}
+
+fn filter_piece<L: LineFilter>(piece: &[u8], filter: &L) -> anyhow::Result<Bytes> {
+    let mut lines = LineBuffer::default();
+    let mut output = BytesMut::new();
+    let mut on_line = |line: &[u8]| {
+        write_line(line, filter.filter_line(line), &mut output);
+        Ok(())
+    };
+    lines.push(piece, &mut on_line)?;
+    lines.finish(&mut on_line)?;
+    Ok(output.freeze())
+}
+
+/// Splits `block` into about `count` pieces of similar size, each ending after a line
+/// terminator except for the last one.
+pub(super) fn split_at_lines(block: &[u8], count: usize) -> Vec<&[u8]> {
+    let size = block.len().div_ceil(count.max(1)).max(1);
+    let mut pieces = vec![];
+    let mut rest = block;
+    while rest.len() > size {
+        let Some(position) = rest[size - 1..].iter().position(|&b| b == b'\n') else {
+            break;
+        };
+        let (piece, tail) = rest.split_at(size + position);
+        pieces.push(piece);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        pieces.push(rest);
+    }
+    pieces
+}
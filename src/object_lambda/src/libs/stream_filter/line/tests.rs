@@ -0,0 +1,191 @@
+use std::str::from_utf8;
+
+use anyhow::anyhow;
+use futures::{stream, StreamExt};
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::parallel::split_at_lines;
+use super::*;
+
+/// Drops `drop`, upper-cases `upper` and keeps anything else.
+struct TestFilter;
+
+impl LineFilter for TestFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        match line {
+            b"drop" => LineAction::Drop,
+            b"upper" => LineAction::Replace(b"UPPER".to_vec()),
+            _ => LineAction::Keep,
+        }
+    }
+}
+
+async fn run<L>(filter: L, chunks: &[&'static str]) -> String
+where
+    L: LineFilter + Send + Sync + 'static,
+{
+    let input = stream::iter(
+        chunks
+            .iter()
+            .map(|chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+            .collect::<Vec<_>>(),
+    );
+    let output: Vec<_> = LineStreamFilter::new(filter)
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    from_utf8(&output.concat()).unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_actions() {
+    // when
+    let output = run(TestFilter, &["keep\ndrop\nupper\n"]).await;
+
+    // then
+    assert_eq!(output, "keep\nUPPER\n");
+}
+
+#[tokio::test]
+async fn test_lines_split_across_chunks() {
+    // when
+    let output = run(TestFilter, &["ke", "", "ep\ndr", "op\nup", "per\n"]).await;
+
+    // then
+    assert_eq!(output, "keep\nUPPER\n");
+}
+
+#[tokio::test]
+async fn test_crlf_and_trailing_line() {
+    // when
+    let output = run(TestFilter, &["drop\r\nupper\r\n\r\nkeep\r", "\nup", "per"]).await;
+
+    // then
+    assert_eq!(output, "UPPER\n\nkeep\nUPPER\n");
+}
+
+#[tokio::test]
+async fn test_error_is_passed_on() {
+    // given
+    let input = stream::iter([
+        Ok(Bytes::from_static(b"keep\n")),
+        Err(anyhow!("read failed")),
+    ]);
+
+    // when
+    let output: Vec<_> = LineStreamFilter::new(TestFilter)
+        .filter_stream(Box::new(input))
+        .collect()
+        .await;
+
+    // then
+    assert_eq!(output[0].as_ref().unwrap(), "keep\n");
+    assert_eq!(output[1].as_ref().unwrap_err().to_string(), "read failed");
+}
+
+#[tokio::test]
+async fn test_regex_on_number_split_across_chunks() {
+    // given
+    let regex = Regex::new(HU_PATTERN).unwrap();
+
+    // when
+    let output = run(
+        regex,
+        &[
+            "+36 1 234 567",
+            "8\nphone\n+36 1 234",
+            " 5679\n+36 30 123 4567",
+        ],
+    )
+    .await;
+
+    // then
+    assert_eq!(output, "+36 1 234 5678\n+36 1 234 5679\n+36 30 123 4567\n");
+}
+
+/// Panics on `panic`, keeping anything else.
+struct PanickingFilter;
+
+impl LineFilter for PanickingFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        assert_ne!(line, b"panic", "bad line");
+        LineAction::Keep
+    }
+}
+
+async fn collect<L>(filter: &LineStreamFilter<L>, input: &[u8], chunk_size: usize) -> Vec<u8>
+where
+    L: LineFilter + Send + Sync + 'static,
+{
+    let chunks: Vec<_> = input
+        .chunks(chunk_size)
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+        .collect();
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(stream::iter(chunks)))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    output.concat()
+}
+
+#[tokio::test]
+async fn test_parallel_output_matches_inline_output() {
+    // given
+    let input: String = (0..5000)
+        .map(|i| match i % 4 {
+            0 => format!("+36 1 {:03} {:04}\n", i % 1000, i),
+            1 => "drop\r\n".to_string(),
+            2 => "upper\n".to_string(),
+            _ => format!("line {i}\n"),
+        })
+        .chain(["upper".to_string()])
+        .collect();
+    let inline = LineStreamFilter::with_parallelism(TestFilter, None);
+    let parallel = LineStreamFilter::with_parallelism(
+        TestFilter,
+        Some(Parallelism {
+            chunks_in_flight: 3,
+            min_split_bytes: 1024,
+        }),
+    );
+
+    // when
+    let expected = collect(&inline, input.as_bytes(), 7000).await;
+    let small_chunks = collect(&parallel, input.as_bytes(), 333).await;
+    let large_chunks = collect(&parallel, input.as_bytes(), 40_000).await;
+
+    // then
+    assert!(expected.ends_with(b"line 4999\nUPPER\n"));
+    assert_eq!(small_chunks, expected);
+    assert_eq!(large_chunks, expected);
+}
+
+#[test]
+fn test_split_at_lines() {
+    // when
+    let pieces = split_at_lines(b"aa\nbbbb\nc\ndd\ne", 3);
+
+    // then
+    assert_eq!(pieces, vec![&b"aa\nbbbb\n"[..], b"c\ndd\n", b"e"]);
+    assert_eq!(split_at_lines(b"abcdef", 4), vec![&b"abcdef"[..]]);
+    assert!(split_at_lines(b"", 4).is_empty());
+}
+
+#[tokio::test]
+async fn test_panicking_filter_fails_the_stream() {
+    // given
+    let input = stream::iter([Ok(Bytes::from_static(b"keep\npanic\n"))]);
+
+    // when
+    let output: Vec<_> = LineStreamFilter::new(PanickingFilter)
+        .filter_stream(Box::new(input))
+        .collect()
+        .await;
+
+    // then
+    let error = output[0].as_ref().unwrap_err().to_string();
+    assert!(error.contains("bad line"), "{error}");
+}
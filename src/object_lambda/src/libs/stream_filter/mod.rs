@@ -1,15 +1,45 @@
+use std::borrow::Cow;
 use std::mem;
-use std::str::from_utf8;
 use std::sync::Arc;
 
 use bytes::{BufMut, Bytes, BytesMut};
-use futures::StreamExt;
+use futures::{future, stream, StreamExt};
 use futures_core::Stream;
 use regex::Regex;
-use reqwest::Error;
+
+use crate::libs::phone;
+
+pub mod aggregate;
+pub mod archive;
+pub mod compress;
+pub mod csv;
+pub mod dedupe;
+pub mod encoding;
+pub mod enrich;
+pub mod expression;
+pub mod jsonl;
+pub mod limit;
+pub mod line;
+pub mod numbers;
+pub mod pii;
+pub mod pseudonymize;
+pub mod quarantine;
+pub mod rewrite;
+pub mod sample;
+pub mod script;
+pub mod serialize;
+pub mod sort;
+pub mod spreadsheet;
+pub mod suppression;
+pub mod vcard;
+pub mod wasm;
 
 type BoxedSendSyncUnpinStream<I> = Box<dyn Stream<Item = I> + Send + Sync + Unpin>;
 
+pub type StreamItem = anyhow::Result<Bytes>;
+
+pub type DynStreamFilter = dyn StreamFilter<Item = StreamItem> + Send + Sync;
+
 pub trait StreamFilter {
     type Item;
     fn filter_stream(
@@ -18,6 +48,193 @@ pub trait StreamFilter {
     ) -> BoxedSendSyncUnpinStream<Self::Item>;
 }
 
+/// Runs filters one after the other, each on the output of the previous one.
+pub struct ChainStreamFilter {
+    filters: Vec<Arc<DynStreamFilter>>,
+}
+
+impl ChainStreamFilter {
+    pub fn new(filters: Vec<Arc<DynStreamFilter>>) -> Self {
+        Self { filters }
+    }
+}
+
+impl StreamFilter for ChainStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        self.filters
+            .iter()
+            .fold(s, |s, filter| filter.filter_stream(s))
+    }
+}
+
+/// Per-invocation state of a filter which needs to see the end of the input,
+/// e.g. to flush a trailing record that is not terminated by a newline.
+pub trait ChunkProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes>;
+    fn finish(&mut self) -> anyhow::Result<Bytes>;
+}
+
+/// Runs `processor` over every chunk of `s`, then calls `finish` once the input is exhausted.
+pub fn process_stream<P>(
+    s: BoxedSendSyncUnpinStream<StreamItem>,
+    mut processor: P,
+) -> BoxedSendSyncUnpinStream<StreamItem>
+where
+    P: ChunkProcessor + Send + Sync + 'static,
+{
+    Box::new(
+        s.map(Some)
+            .chain(stream::once(future::ready(None)))
+            .map(move |item| match item {
+                Some(Ok(chunk)) => processor.process(chunk),
+                Some(Err(error)) => Err(error),
+                None => processor.finish(),
+            }),
+    )
+}
+
+/// Like [`process_stream`], but runs `processor` on the blocking threads of the runtime, so that
+/// a CPU-bound stage does not hold up the requests served alongside.
+pub fn process_stream_blocking<P>(
+    s: BoxedSendSyncUnpinStream<StreamItem>,
+    processor: P,
+) -> BoxedSendSyncUnpinStream<StreamItem>
+where
+    P: ChunkProcessor + Send + Sync + 'static,
+{
+    let items = s.map(Some).chain(stream::once(future::ready(None)));
+    let processed = stream::unfold(
+        (items, Some(processor)),
+        |(mut items, processor)| async move {
+            // gone if it panicked
+            let mut processor = processor?;
+            let (processor, result) = match items.next().await? {
+                Some(Err(error)) => (Some(processor), Err(error)),
+                item => {
+                    let processed = tokio::task::spawn_blocking(move || {
+                        let result = match item {
+                            Some(Ok(chunk)) => processor.process(chunk),
+                            _ => processor.finish(),
+                        };
+                        (processor, result)
+                    });
+                    match processed.await {
+                        Ok((processor, result)) => (Some(processor), result),
+                        Err(error) => (None, Err(error.into())),
+                    }
+                }
+            };
+            Some((result, (items, processor)))
+        },
+    );
+    Box::new(Box::pin(processed))
+}
+
+/// How the lines reaching a stage are delimited, as decided by the decode stage.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum LineFormat {
+    #[default]
+    Text,
+    /// CSV records, whose quoted fields may hold line breaks, after a header row if set
+    Csv { has_header: bool },
+}
+
+impl LineFormat {
+    pub fn line_buffer(self) -> LineBuffer {
+        match self {
+            LineFormat::Text => LineBuffer::default(),
+            LineFormat::Csv { .. } => LineBuffer::csv(),
+        }
+    }
+}
+
+/// Reassembles lines which are split across chunks.
+#[derive(Default)]
+pub struct LineBuffer {
+    leftover: BytesMut,
+    /// whether the scanned input is within a quoted field, for CSV records
+    quoted: Option<bool>,
+}
+
+impl LineBuffer {
+    /// Reassembles CSV records instead, whose quoted fields may hold line breaks.
+    pub fn csv() -> Self {
+        Self {
+            leftover: BytesMut::new(),
+            quoted: Some(false),
+        }
+    }
+
+    /// Calls `f` with every line completed by `chunk`, without its `\n` or `\r\n` terminator.
+    pub fn push<F>(&mut self, chunk: &[u8], mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&[u8]) -> anyhow::Result<()>,
+    {
+        let mut rest = chunk;
+        while let Some(position) = self.line_end(rest) {
+            let (line, tail) = rest.split_at(position);
+            rest = &tail[1..];
+            if self.leftover.is_empty() {
+                f(trim_cr(line))?;
+            } else {
+                self.leftover.put_slice(line);
+                let line = mem::take(&mut self.leftover);
+                f(trim_cr(&line))?;
+            }
+        }
+        self.leftover.put_slice(rest);
+        Ok(())
+    }
+
+    /// The position of the first line terminator of `rest`, scanning every byte once.
+    fn line_end(&mut self, rest: &[u8]) -> Option<usize> {
+        match &mut self.quoted {
+            None => rest.iter().position(|&b| b == b'\n'),
+            // an escaped quote `""` leaves and enters the quoted field again
+            Some(quoted) => rest.iter().position(|&b| {
+                if b == b'"' {
+                    *quoted = !*quoted;
+                }
+                b == b'\n' && !*quoted
+            }),
+        }
+    }
+
+    /// Calls `f` with the trailing line if the input did not end with a line terminator.
+    pub fn finish<F>(&mut self, mut f: F) -> anyhow::Result<()>
+    where
+        F: FnMut(&[u8]) -> anyhow::Result<()>,
+    {
+        if self.leftover.is_empty() {
+            return Ok(());
+        }
+        let line = mem::take(&mut self.leftover);
+        f(trim_cr(&line))
+    }
+}
+
+fn trim_cr(line: &[u8]) -> &[u8] {
+    line.strip_suffix(b"\r").unwrap_or(line)
+}
+
+/// The normalized number of an output line holding just a number.
+fn line_number(regex: &Regex, line: &str) -> Option<String> {
+    regex
+        .is_match(line)
+        .then(|| phone::normalize(line))
+        .flatten()
+}
+
+/// The normalized number of an output line holding just a number, the line itself otherwise,
+/// so that different spellings of a number share a key.
+fn number_key<'a>(regex: &Regex, line: &'a str) -> Cow<'a, str> {
+    line_number(regex, line).map_or(Cow::Borrowed(line), Cow::Owned)
+}
+
 #[cfg_attr(test, faux::create)]
 pub struct RegexStreamFilter {
     regex: Arc<Regex>,
@@ -34,44 +251,11 @@ impl RegexStreamFilter {
 
 #[cfg_attr(test, faux::methods)]
 impl StreamFilter for RegexStreamFilter {
-    type Item = Result<Bytes, Error>;
+    type Item = StreamItem;
     fn filter_stream(
         &self,
         s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
     ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
-        let mut leftover = BytesMut::new();
-        let regex = self.regex.clone();
-        Box::new(s.map(move |item| {
-            match item {
-                Ok(bytes) => {
-                    let mut output = BytesMut::new();
-                    let mut new_leftover = BytesMut::new();
-                    mem::swap(&mut leftover, &mut new_leftover);
-                    new_leftover.put(bytes);
-                    let input = new_leftover.freeze();
-                    let mut lines: Vec<_> = input
-                        .split(|&v| v == b'\n')
-                        .map(|c| from_utf8(c).unwrap_or(""))
-                        .collect();
-                    let last = lines.pop().unwrap_or("");
-                    lines.iter().for_each(|&l| {
-                        if regex.is_match(l) {
-                            output.put_slice(l.as_bytes());
-                            output.put_u8(b'\n');
-                        }
-                    });
-                    // the last element might be a partial
-                    if regex.is_match(last) {
-                        output.put_slice(last.as_bytes());
-                        output.put_u8(b'\n');
-                    } else {
-                        let mut new_leftover = BytesMut::from(last);
-                        mem::swap(&mut leftover, &mut new_leftover);
-                    }
-                    Ok(output.freeze())
-                }
-                Err(error) => Err(error),
-            }
-        }))
+        line::filter_lines(s, self.regex.clone(), Some(Default::default()))
     }
 }
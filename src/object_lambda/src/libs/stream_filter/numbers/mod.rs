@@ -0,0 +1,127 @@
+use std::str::from_utf8;
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::libs::phone::{self, NumberType, Verdict};
+
+use super::line::{LineAction, LineFilter};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum Keep {
+    #[default]
+    Valid,
+    Invalid,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct MatchConfig {
+    /// the lines holding just a valid number, or all the other lines
+    #[serde(default)]
+    pub keep: Keep,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ClassifyConfig {
+    /// keeps the numbers of these types, any type if empty
+    #[serde(default)]
+    pub types: Vec<NumberType>,
+    /// keeps the numbers with these area codes or mobile prefixes, any if empty
+    #[serde(default)]
+    pub area_codes: Vec<String>,
+}
+
+impl ClassifyConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.types.is_empty() && self.area_codes.is_empty() {
+            bail!("classify needs types or areaCodes");
+        }
+        Ok(())
+    }
+}
+
+fn number(line: &[u8]) -> Option<String> {
+    match phone::verdict(from_utf8(line).ok()?) {
+        Verdict::Valid(number) => Some(number),
+        Verdict::Rejected(_) => None,
+    }
+}
+
+/// Keeps the lines holding just a valid number, or the other ones.
+pub struct MatchFilter {
+    config: MatchConfig,
+}
+
+impl MatchFilter {
+    pub fn new(config: MatchConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl LineFilter for MatchFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        if number(line).is_some() == (self.config.keep == Keep::Valid) {
+            LineAction::Keep
+        } else {
+            LineAction::Drop
+        }
+    }
+}
+
+/// Rewrites the lines holding just a valid number to E.164, leaving the other lines as they are.
+#[derive(Default)]
+pub struct NormalizeFilter {}
+
+impl NormalizeFilter {
+    pub fn new() -> Self {
+        Self {}
+    }
+}
+
+impl LineFilter for NormalizeFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        match number(line) {
+            Some(number) => LineAction::Replace(number.into_bytes()),
+            None => LineAction::Keep,
+        }
+    }
+}
+
+/// Keeps the lines holding just a valid number of the configured types and area codes.
+pub struct ClassifyFilter {
+    config: ClassifyConfig,
+}
+
+impl ClassifyFilter {
+    pub fn new(config: ClassifyConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl LineFilter for ClassifyFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        let ClassifyConfig { types, area_codes } = &self.config;
+        let keep = number(line)
+            .and_then(|number| {
+                phone::classify(&number).map(|classification| {
+                    (types.is_empty() || types.contains(&classification.number_type))
+                        && (area_codes.is_empty()
+                            || area_codes
+                                .iter()
+                                .any(|code| code == classification.area_code))
+                })
+            })
+            .unwrap_or(false);
+        if keep {
+            LineAction::Keep
+        } else {
+            LineAction::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+
+use bytes::Bytes;
+
+use crate::libs::stream_filter::line::LineStreamFilter;
+use crate::libs::stream_filter::DynStreamFilter;
+
+use super::*;
+
+const INPUT: &str = "+36 1 234 5678\nphone\n0036 30 123 4567\n+36 80 123 4567\n+49 30 1234567";
+
+async fn run(filter: &DynStreamFilter) -> String {
+    let input = stream::iter([Ok(Bytes::from_static(INPUT.as_bytes()))]);
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    from_utf8(&output.concat()).unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_match_valid() {
+    // when
+    let output = run(&LineStreamFilter::new(MatchFilter::new(
+        MatchConfig::default(),
+    )))
+    .await;
+
+    // then
+    assert_eq!(
+        output,
+        "+36 1 234 5678\n0036 30 123 4567\n+36 80 123 4567\n"
+    );
+}
+
+#[tokio::test]
+async fn test_match_invalid() {
+    // when
+    let output = run(&LineStreamFilter::new(MatchFilter::new(MatchConfig {
+        keep: Keep::Invalid,
+    })))
+    .await;
+
+    // then
+    assert_eq!(output, "phone\n+49 30 1234567\n");
+}
+
+#[tokio::test]
+async fn test_normalize() {
+    // when
+    let output = run(&LineStreamFilter::new(NormalizeFilter::new())).await;
+
+    // then
+    assert_eq!(
+        output,
+        "+3612345678\nphone\n+36301234567\n+36801234567\n+49 30 1234567\n"
+    );
+}
+
+#[tokio::test]
+async fn test_classify() {
+    // when
+    let output = run(&LineStreamFilter::new(ClassifyFilter::new(
+        ClassifyConfig {
+            types: vec![NumberType::Fixed, NumberType::Mobile],
+            area_codes: vec![],
+        },
+    )))
+    .await;
+
+    // then
+    assert_eq!(output, "+36 1 234 5678\n0036 30 123 4567\n");
+}
+
+#[tokio::test]
+async fn test_classify_by_area_code() {
+    // when
+    let output = run(&LineStreamFilter::new(ClassifyFilter::new(
+        ClassifyConfig {
+            types: vec![],
+            area_codes: vec!["80".into()],
+        },
+    )))
+    .await;
+
+    // then
+    assert_eq!(output, "+36 80 123 4567\n");
+}
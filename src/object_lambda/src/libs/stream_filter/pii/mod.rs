@@ -0,0 +1,106 @@
+use std::str::from_utf8;
+
+use anyhow::bail;
+use serde::Deserialize;
+
+use crate::libs::pii::{Detector, Entity};
+
+use super::line::{LineAction, LineFilter};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum PiiMode {
+    /// outputs a `<entity>\t<value>` line for every entity found
+    Extract,
+    /// keeps the lines holding an entity
+    Filter,
+    /// replaces every entity with its label, e.g. `[iban]`
+    #[default]
+    Redact,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PiiConfig {
+    #[serde(default)]
+    pub mode: PiiMode,
+    /// the entities to look for, all of them if not set
+    #[serde(default = "default_entities")]
+    pub entities: Vec<Entity>,
+}
+
+fn default_entities() -> Vec<Entity> {
+    Entity::ALL.to_vec()
+}
+
+impl PiiConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.entities.is_empty() {
+            bail!("pii needs at least one entity");
+        }
+        Ok(())
+    }
+}
+
+/// Scans every line for personal data, dropping the lines which are not UTF-8 as they cannot
+/// be scanned.
+pub struct PiiFilter {
+    detector: Detector,
+    mode: PiiMode,
+}
+
+impl PiiFilter {
+    pub fn new(config: &PiiConfig) -> Self {
+        Self {
+            detector: Detector::new(&config.entities),
+            mode: config.mode,
+        }
+    }
+}
+
+impl LineFilter for PiiFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        let Ok(line) = from_utf8(line) else {
+            return LineAction::Drop;
+        };
+        let detections = self.detector.detect(line);
+        if detections.is_empty() {
+            return match self.mode {
+                PiiMode::Redact => LineAction::Keep,
+                PiiMode::Extract | PiiMode::Filter => LineAction::Drop,
+            };
+        }
+        match self.mode {
+            PiiMode::Filter => LineAction::Keep,
+            PiiMode::Extract => {
+                let records: Vec<_> = detections
+                    .iter()
+                    .map(|detection| {
+                        format!(
+                            "{}\t{}",
+                            detection.entity.code(),
+                            &line[detection.start..detection.end]
+                        )
+                    })
+                    .collect();
+                LineAction::Replace(records.join("\n").into_bytes())
+            }
+            PiiMode::Redact => {
+                let mut output = String::with_capacity(line.len());
+                let mut position = 0;
+                for detection in &detections {
+                    output.push_str(&line[position..detection.start]);
+                    output.push('[');
+                    output.push_str(detection.entity.code());
+                    output.push(']');
+                    position = detection.end;
+                }
+                output.push_str(&line[position..]);
+                LineAction::Replace(output.into_bytes())
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,74 @@
+use std::str::from_utf8;
+
+use bytes::Bytes;
+use futures::{stream, StreamExt};
+
+use crate::libs::stream_filter::line::LineStreamFilter;
+use crate::libs::stream_filter::StreamFilter;
+
+use super::*;
+
+const INPUT: &str = "name,email,card\n\
+                     Jane,jane@example.hu,4111 1111 1111 1111\n\
+                     John,john@example,4111 1111 1111 1112\n";
+
+async fn run(mode: PiiMode, entities: Vec<Entity>) -> String {
+    let filter = LineStreamFilter::new(PiiFilter::new(&PiiConfig { mode, entities }));
+    let input = stream::iter([Ok(Bytes::from_static(INPUT.as_bytes()))]);
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    from_utf8(&output.concat()).unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_redact() {
+    // when
+    let output = run(PiiMode::Redact, default_entities()).await;
+
+    // then
+    assert_eq!(
+        output,
+        "name,email,card\n\
+         Jane,[email],[card]\n\
+         John,john@example,4111 1111 1111 1112\n"
+    );
+}
+
+#[test]
+fn test_redact_overlapping_entities() {
+    // given
+    let filter = PiiFilter::new(&PiiConfig {
+        mode: PiiMode::Redact,
+        entities: default_entities(),
+    });
+
+    // when
+    let action = filter.filter_line(b"Jane,8123456786,4111 1111 1111 1111@example.hu");
+
+    // then
+    assert_eq!(action, LineAction::Replace(b"Jane,[taxId],[card]".to_vec()));
+}
+
+#[tokio::test]
+async fn test_extract() {
+    // when
+    let output = run(PiiMode::Extract, default_entities()).await;
+
+    // then
+    assert_eq!(
+        output,
+        "email\tjane@example.hu\ncard\t4111 1111 1111 1111\n"
+    );
+}
+
+#[tokio::test]
+async fn test_filter_selected_entities() {
+    // when
+    let output = run(PiiMode::Filter, vec![Entity::Card]).await;
+
+    // then
+    assert_eq!(output, "Jane,jane@example.hu,4111 1111 1111 1111\n");
+}
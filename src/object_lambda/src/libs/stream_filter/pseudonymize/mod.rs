@@ -0,0 +1,181 @@
+use std::fs;
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use bytes::{BufMut, Bytes, BytesMut};
+use hmac::{Hmac, Mac};
+use regex::Regex;
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::libs::deps::env;
+
+use super::{
+    line_number, process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer,
+    StreamFilter, StreamItem,
+};
+
+/// Environment variable holding the key unless `keyEnv` or `keyFile` is configured.
+pub const KEY_VAR: &str = "PSEUDONYMIZATION_KEY";
+
+/// Shorter keys make the pseudonyms of the few billion possible numbers easy to brute-force.
+const MIN_KEY_LEN: usize = 16;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum DigestEncoding {
+    #[default]
+    Hex,
+    Base64,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct PseudonymizeConfig {
+    /// prefixed to every pseudonym, e.g. `2024a:`, so that the key can be rotated
+    pub key_id: String,
+    /// environment variable holding the key
+    #[serde(default)]
+    pub key_env: Option<String>,
+    /// file holding the key, instead of an environment variable
+    #[serde(default)]
+    pub key_file: Option<String>,
+    #[serde(default)]
+    pub encoding: DigestEncoding,
+}
+
+impl PseudonymizeConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.key_id.is_empty() || self.key_id.contains([':', '\n']) {
+            bail!("pseudonymization keyId must be non-empty, without `:`");
+        }
+        if self.key_env.is_some() && self.key_file.is_some() {
+            bail!("pseudonymization key comes either from keyEnv or from keyFile");
+        }
+        Ok(())
+    }
+
+    /// Reads the key, without the trailing newline of a key file.
+    fn load_key(&self, env: &env::Env) -> anyhow::Result<Vec<u8>> {
+        let key = match &self.key_file {
+            Some(path) => fs::read_to_string(path)
+                .with_context(|| format!("could not read pseudonymization key file {path}"))?,
+            None => {
+                let var = self.key_env.as_deref().unwrap_or(KEY_VAR);
+                env.var(var)
+                    .with_context(|| format!("pseudonymization key {var} is not set"))?
+            }
+        };
+        let key = key.trim_end_matches(['\r', '\n']);
+        if key.len() < MIN_KEY_LEN {
+            bail!("pseudonymization key must be at least {MIN_KEY_LEN} bytes long");
+        }
+        Ok(key.as_bytes().to_vec())
+    }
+}
+
+/// Replaces every output number with `<key id>:<HMAC-SHA256(key, E.164 number)>`.
+/// Lines not holding just a number are dropped, so that nothing leaks in clear.
+pub struct PseudonymizeStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<PseudonymizeConfig>,
+    mac: Arc<Hmac<Sha256>>,
+}
+
+impl PseudonymizeStreamFilter {
+    pub fn new(regex: Regex, config: PseudonymizeConfig, env: &env::Env) -> anyhow::Result<Self> {
+        let mac = Hmac::new_from_slice(&config.load_key(env)?)?;
+        Ok(Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+            mac: Arc::new(mac),
+        })
+    }
+}
+
+impl StreamFilter for PseudonymizeStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            PseudonymizeProcessor {
+                lines: LineBuffer::default(),
+                pseudonymizer: Pseudonymizer {
+                    regex: self.regex.clone(),
+                    config: self.config.clone(),
+                    mac: self.mac.clone(),
+                    dropped: 0,
+                },
+            },
+        )
+    }
+}
+
+struct PseudonymizeProcessor {
+    lines: LineBuffer,
+    pseudonymizer: Pseudonymizer,
+}
+
+struct Pseudonymizer {
+    regex: Arc<Regex>,
+    config: Arc<PseudonymizeConfig>,
+    mac: Arc<Hmac<Sha256>>,
+    dropped: u64,
+}
+
+impl Pseudonymizer {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) {
+        let number = from_utf8(line)
+            .ok()
+            .and_then(|line| line_number(&self.regex, line));
+        let Some(number) = number else {
+            self.dropped += 1;
+            return;
+        };
+        let mut mac = Hmac::clone(&self.mac);
+        mac.update(number.as_bytes());
+        let digest = mac.finalize().into_bytes();
+        output.put_slice(self.config.key_id.as_bytes());
+        output.put_u8(b':');
+        match self.config.encoding {
+            DigestEncoding::Hex => output.put_slice(hex::encode(digest).as_bytes()),
+            DigestEncoding::Base64 => output.put_slice(STANDARD.encode(digest).as_bytes()),
+        }
+        output.put_u8(b'\n');
+    }
+}
+
+impl ChunkProcessor for PseudonymizeProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.push(&chunk, |line| {
+            self.pseudonymizer.on_line(line, &mut output);
+            Ok(())
+        })?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.finish(|line| {
+            self.pseudonymizer.on_line(line, &mut output);
+            Ok(())
+        })?;
+        if self.pseudonymizer.dropped > 0 {
+            tracing::warn!(
+                "dropped {} lines not holding just a number",
+                self.pseudonymizer.dropped
+            );
+        }
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
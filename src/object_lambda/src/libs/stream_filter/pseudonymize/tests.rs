@@ -0,0 +1,103 @@
+use std::env::VarError;
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::*;
+
+const KEY: &str = "0123456789abcdef";
+
+/// HMAC-SHA256 of `+3612345678` with [`KEY`]
+const HEX_DIGEST: &str = "8b2edc04f8ca58f61999299e1b7e47897b241cb92677bd68ad8ccc0d6eaf119d";
+const BASE64_DIGEST: &str = "iy7cBPjKWPYZmSmeG35HiXskHLkmd71orYzMDW6vEZ0=";
+
+fn config(encoding: DigestEncoding) -> PseudonymizeConfig {
+    PseudonymizeConfig {
+        key_id: "k1".into(),
+        key_env: None,
+        key_file: None,
+        encoding,
+    }
+}
+
+fn mock_env(key: &'static str) -> env::Env {
+    let mut env = env::Env::faux();
+    faux::when!(env.var(KEY_VAR)).then_return(Ok(key.into()));
+    env
+}
+
+async fn run(filter: PseudonymizeStreamFilter, input: &'static str) -> String {
+    let input = stream::iter([Ok(Bytes::from_static(input.as_bytes()))]);
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk.unwrap()).unwrap());
+    }
+    output
+}
+
+fn regex() -> Regex {
+    Regex::new(HU_PATTERN).unwrap()
+}
+
+#[tokio::test]
+async fn test_hex() {
+    // given
+    let filter =
+        PseudonymizeStreamFilter::new(regex(), config(DigestEncoding::Hex), &mock_env(KEY))
+            .unwrap();
+
+    // when
+    let output = run(filter, "+36 1 234 5678\nname,phone\n0036 1 234 56 78").await;
+
+    // then
+    assert_eq!(output, format!("k1:{HEX_DIGEST}\nk1:{HEX_DIGEST}\n"));
+}
+
+#[tokio::test]
+async fn test_base64_with_key_file() {
+    // given
+    let path = std::env::temp_dir().join(format!("pseudonymization-key-{}", std::process::id()));
+    fs::write(&path, format!("{KEY}\n")).unwrap();
+    let config = PseudonymizeConfig {
+        key_file: Some(path.to_string_lossy().into()),
+        ..config(DigestEncoding::Base64)
+    };
+    let mut env = env::Env::faux();
+    faux::when!(env.var).then(|_| panic!("key read from the environment"));
+
+    // when
+    let filter = PseudonymizeStreamFilter::new(regex(), config, &env);
+    fs::remove_file(&path).unwrap();
+
+    // then
+    assert_eq!(
+        run(filter.unwrap(), "+36 1 234 5678\n").await,
+        format!("k1:{BASE64_DIGEST}\n")
+    );
+}
+
+#[test]
+fn test_short_key() {
+    // when
+    let filter =
+        PseudonymizeStreamFilter::new(regex(), config(DigestEncoding::Hex), &mock_env("short"));
+
+    // then
+    assert!(filter.is_err());
+}
+
+#[test]
+fn test_missing_key() {
+    // given
+    let mut env = env::Env::faux();
+    faux::when!(env.var(KEY_VAR)).then_return(Err(VarError::NotPresent));
+
+    // when
+    let filter = PseudonymizeStreamFilter::new(regex(), config(DigestEncoding::Hex), &env);
+
+    // then
+    assert!(filter.is_err());
+}
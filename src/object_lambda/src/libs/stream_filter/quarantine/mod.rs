@@ -0,0 +1,210 @@
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::channel::mpsc;
+use futures::{future, stream, SinkExt, Stream, StreamExt};
+use serde::Deserialize;
+
+use crate::libs::deps::s3;
+use crate::libs::phone::{self, Rejection, Verdict};
+
+use super::csv::write_record;
+use super::{BoxedSendSyncUnpinStream, DynStreamFilter, LineBuffer, StreamItem};
+
+mod upload;
+
+/// Chunks of quarantine records buffered ahead of the upload, beyond which the accepted
+/// lines wait for it.
+const CHUNKS_IN_FLIGHT: usize = 16;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct QuarantineConfig {
+    #[serde(default)]
+    pub bucket: String,
+    /// the rejected lines of a task are written to `<prefix><task id>.csv`
+    #[serde(default = "default_prefix")]
+    pub prefix: String,
+    /// longer lines are rejected, and cut to this length in the quarantine
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+}
+
+fn default_prefix() -> String {
+    "quarantine/".into()
+}
+
+fn default_max_line_bytes() -> usize {
+    4096
+}
+
+impl QuarantineConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.bucket.is_empty() {
+            bail!("quarantine needs a bucket, or the REPORTS_BUCKET environment variable");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Reason {
+    InvalidUtf8,
+    TooLong,
+    Rejected(Rejection),
+}
+
+impl Reason {
+    fn as_str(self) -> &'static str {
+        match self {
+            Reason::InvalidUtf8 => "invalid_utf8",
+            Reason::TooLong => "too_long",
+            Reason::Rejected(rejection) => rejection.code(),
+        }
+    }
+}
+
+/// Stands in for the `match` stage of the valid numbers, teeing the lines the phone matcher
+/// rejects into a separate object as `line,reason,content` CSV records, the reason being a
+/// [`Rejection`] code if the line is valid UTF-8 of acceptable length.
+pub struct Quarantine {
+    config: Arc<QuarantineConfig>,
+    /// the stages after the `match` stage
+    matched: Arc<DynStreamFilter>,
+}
+
+impl Quarantine {
+    pub fn new(config: QuarantineConfig, matched: Arc<DynStreamFilter>) -> Self {
+        Self {
+            config: Arc::new(config),
+            matched,
+        }
+    }
+
+    /// Splits `s`, the input of the `match` stage, into the output of the stages after it and
+    /// the quarantine records of the rejected lines. Both streams are to be consumed
+    /// concurrently: the accepted lines only go on while the upload keeps up with the records.
+    pub fn split(
+        &self,
+        s: BoxedSendSyncUnpinStream<StreamItem>,
+    ) -> (BoxedSendSyncUnpinStream<StreamItem>, mpsc::Receiver<Bytes>) {
+        let (sender, receiver) = mpsc::channel(CHUNKS_IN_FLIGHT);
+        let processor = QuarantineProcessor {
+            lines: LineBuffer::default(),
+            splitter: Splitter {
+                max_line_bytes: self.config.max_line_bytes,
+                line_number: 0,
+            },
+            sender: Some(sender),
+        };
+        let chunks = s.map(Some).chain(stream::once(future::ready(None)));
+        let accepted = stream::unfold(
+            (chunks, processor),
+            |(mut chunks, mut processor)| async move {
+                let result = match chunks.next().await? {
+                    Some(Ok(chunk)) => processor.process(&chunk).await,
+                    Some(Err(error)) => Err(error),
+                    None => processor.finish().await,
+                };
+                Some((result, (chunks, processor)))
+            },
+        );
+        let matched = self.matched.filter_stream(Box::new(Box::pin(accepted)));
+        (matched, receiver)
+    }
+
+    /// Uploads the records of `split` while they are produced, failures are logged.
+    pub async fn upload(
+        &self,
+        s3: &s3::S3,
+        task_id: &str,
+        records: impl Stream<Item = Bytes> + Unpin,
+    ) {
+        let key = format!("{}{task_id}.csv", self.config.prefix);
+        // dropping `records` on failure lets the splitter go on without buffering them
+        if let Err(error) = upload::upload(s3, &self.config.bucket, &key, records).await {
+            tracing::warn!(
+                "could not write quarantine to s3://{}/{key}: {error:#}",
+                self.config.bucket
+            );
+        }
+    }
+}
+
+struct QuarantineProcessor {
+    lines: LineBuffer,
+    splitter: Splitter,
+    sender: Option<mpsc::Sender<Bytes>>,
+}
+
+impl QuarantineProcessor {
+    async fn process(&mut self, chunk: &[u8]) -> anyhow::Result<Bytes> {
+        let (mut output, mut records) = (BytesMut::new(), BytesMut::new());
+        self.lines.push(chunk, |line| {
+            self.splitter.on_line(line, &mut output, &mut records);
+            Ok(())
+        })?;
+        self.send(records).await;
+        Ok(output.freeze())
+    }
+
+    async fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let (mut output, mut records) = (BytesMut::new(), BytesMut::new());
+        self.lines.finish(|line| {
+            self.splitter.on_line(line, &mut output, &mut records);
+            Ok(())
+        })?;
+        self.send(records).await;
+        // closes the channel
+        self.sender = None;
+        Ok(output.freeze())
+    }
+
+    /// Waits for room in the channel, which holds back the accepted lines of the chunk.
+    async fn send(&mut self, records: BytesMut) {
+        if let (false, Some(sender)) = (records.is_empty(), &mut self.sender) {
+            // the receiver is only gone if the upload was given up
+            if sender.send(records.freeze()).await.is_err() {
+                self.sender = None;
+            }
+        }
+    }
+}
+
+struct Splitter {
+    max_line_bytes: usize,
+    line_number: u64,
+}
+
+impl Splitter {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut, records: &mut BytesMut) {
+        self.line_number += 1;
+        let reason = match from_utf8(line) {
+            _ if line.len() > self.max_line_bytes => Reason::TooLong,
+            Err(_) => Reason::InvalidUtf8,
+            Ok(text) => match phone::verdict(text) {
+                Verdict::Rejected(rejection) => Reason::Rejected(rejection),
+                Verdict::Valid(_) => {
+                    output.put_slice(line);
+                    output.put_u8(b'\n');
+                    return;
+                }
+            },
+        };
+        let content = String::from_utf8_lossy(&line[..line.len().min(self.max_line_bytes)]);
+        write_record(
+            records,
+            &[
+                self.line_number.to_string().as_bytes(),
+                reason.as_str().as_bytes(),
+                content.as_bytes(),
+            ],
+            b',',
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests;
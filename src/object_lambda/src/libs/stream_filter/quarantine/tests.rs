@@ -0,0 +1,208 @@
+#![allow(clippy::result_large_err)]
+
+use std::str::from_utf8;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use aws_sdk_s3::error::SdkError;
+use aws_sdk_s3::operation::abort_multipart_upload::AbortMultipartUploadOutput;
+use aws_sdk_s3::operation::complete_multipart_upload::CompleteMultipartUploadOutput;
+use aws_sdk_s3::operation::create_multipart_upload::CreateMultipartUploadOutput;
+use aws_sdk_s3::operation::put_object::PutObjectOutput;
+use aws_sdk_s3::operation::upload_part::UploadPartOutput;
+use aws_sdk_s3::primitives::ByteStream;
+use futures::{stream, StreamExt};
+
+use crate::libs::stream_filter::ChainStreamFilter;
+
+use super::upload::PART_SIZE;
+use super::*;
+
+fn quarantine(max_line_bytes: usize) -> Quarantine {
+    Quarantine::new(
+        QuarantineConfig {
+            bucket: "bucket".into(),
+            prefix: default_prefix(),
+            max_line_bytes,
+        },
+        Arc::new(ChainStreamFilter::new(vec![])),
+    )
+}
+
+fn body(body: ByteStream) -> Vec<u8> {
+    body.into_inner().bytes().unwrap().to_vec()
+}
+
+#[tokio::test]
+async fn test_split() {
+    // given
+    let input = stream::iter([
+        Ok(Bytes::from_static(
+            b"+36 1 234 5678\r\nphone, \"home\"\nbad \xff",
+        )),
+        Ok(Bytes::from_static(
+            b"\n0036 30 123 4567\n+36 1 234 5678 or later\n+49 30 1234567\n+36 1 234 567",
+        )),
+    ]);
+
+    // when
+    let (accepted, records) = quarantine(16).split(Box::new(input));
+    let (accepted, quarantined) = futures::join!(
+        accepted.map(|chunk| chunk.unwrap()).collect::<Vec<_>>(),
+        records.map(|record| record.to_vec()).concat()
+    );
+
+    // then
+    assert_eq!(accepted.concat(), b"+36 1 234 5678\n0036 30 123 4567\n");
+    assert_eq!(
+        from_utf8(&quarantined).unwrap(),
+        "2,illegal_character,\"phone, \"\"home\"\"\"\n\
+         3,invalid_utf8,bad \u{fffd}\n\
+         5,too_long,+36 1 234 5678 o\n\
+         6,wrong_country_code,+49 30 1234567\n\
+         7,too_few_digits,+36 1 234 567\n"
+    );
+}
+
+#[tokio::test]
+async fn test_split_waits_for_the_upload() {
+    // given
+    let chunks = (0..CHUNKS_IN_FLIGHT + 3).map(|_| Ok(Bytes::from_static(b"bad\n")));
+    let input = stream::iter(chunks.collect::<Vec<_>>());
+
+    // when
+    let (mut accepted, mut records) = quarantine(16).split(Box::new(input));
+    let mut ahead = 0;
+    while tokio::time::timeout(Duration::from_millis(50), accepted.next())
+        .await
+        .is_ok()
+    {
+        ahead += 1;
+    }
+    records.next().await.unwrap();
+    let resumed = accepted.next().await;
+
+    // then
+    assert!((CHUNKS_IN_FLIGHT..CHUNKS_IN_FLIGHT + 3).contains(&ahead));
+    assert!(resumed.is_some());
+}
+
+#[tokio::test]
+async fn test_upload_single_part() {
+    // given
+    let written = Arc::new(Mutex::new(vec![]));
+    let mut s3 = s3::S3::faux();
+    faux::when!(s3.put_object("bucket", "quarantine/task.csv", _, "text/csv")).then({
+        let written = written.clone();
+        move |(_, _, stream, _)| {
+            *written.lock().unwrap() = body(stream);
+            Ok(PutObjectOutput::builder().build())
+        }
+    });
+    let (sender, records) = mpsc::unbounded();
+    sender
+        .unbounded_send(Bytes::from_static(b"1,no_match,a\n"))
+        .unwrap();
+    sender
+        .unbounded_send(Bytes::from_static(b"2,illegal_character,b\n"))
+        .unwrap();
+    drop(sender);
+
+    // when
+    quarantine(16).upload(&s3, "task", records).await;
+
+    // then
+    assert_eq!(
+        *written.lock().unwrap(),
+        b"1,no_match,a\n2,illegal_character,b\n"
+    );
+}
+
+#[tokio::test]
+async fn test_upload_multipart() {
+    // given
+    let parts = Arc::new(Mutex::new(vec![]));
+    let completed = Arc::new(Mutex::new(vec![]));
+    let mut s3 = s3::S3::faux();
+    faux::when!(s3.create_multipart_upload("bucket", "quarantine/task.csv", "text/csv")).then(
+        |_| {
+            Ok(CreateMultipartUploadOutput::builder()
+                .upload_id("upload-1")
+                .build())
+        },
+    );
+    faux::when!(s3.upload_part("bucket", "quarantine/task.csv", "upload-1", _, _)).then({
+        let parts = parts.clone();
+        move |(_, _, _, part_number, stream)| {
+            parts
+                .lock()
+                .unwrap()
+                .push((part_number, body(stream).len()));
+            Ok(UploadPartOutput::builder()
+                .e_tag(format!("etag-{part_number}"))
+                .build())
+        }
+    });
+    faux::when!(s3.complete_multipart_upload("bucket", "quarantine/task.csv", "upload-1", _)).then(
+        {
+            let completed = completed.clone();
+            move |(_, _, _, parts)| {
+                *completed.lock().unwrap() = parts;
+                Ok(CompleteMultipartUploadOutput::builder().build())
+            }
+        },
+    );
+    let (sender, records) = mpsc::unbounded();
+    for _ in 0..3 {
+        sender
+            .unbounded_send(Bytes::from(vec![b'x'; PART_SIZE / 2]))
+            .unwrap();
+    }
+    drop(sender);
+
+    // when
+    quarantine(16).upload(&s3, "task", records).await;
+
+    // then
+    assert_eq!(
+        *parts.lock().unwrap(),
+        vec![(1, PART_SIZE), (2, PART_SIZE / 2)]
+    );
+    let completed = completed.lock().unwrap();
+    assert_eq!(completed.len(), 2);
+    assert_eq!(completed[1].part_number, Some(2));
+    assert_eq!(completed[1].e_tag.as_deref(), Some("etag-2"));
+}
+
+#[tokio::test]
+async fn test_failed_multipart_upload_is_aborted() {
+    // given
+    let aborted = Arc::new(Mutex::new(false));
+    let mut s3 = s3::S3::faux();
+    faux::when!(s3.create_multipart_upload).then(|_| {
+        Ok(CreateMultipartUploadOutput::builder()
+            .upload_id("upload-1")
+            .build())
+    });
+    faux::when!(s3.upload_part).then(|_| Err(SdkError::timeout_error("timeout")));
+    faux::when!(s3.abort_multipart_upload("bucket", "quarantine/task.csv", "upload-1")).then({
+        let aborted = aborted.clone();
+        move |_| {
+            *aborted.lock().unwrap() = true;
+            Ok(AbortMultipartUploadOutput::builder().build())
+        }
+    });
+    let (sender, records) = mpsc::unbounded();
+    sender
+        .unbounded_send(Bytes::from(vec![b'x'; PART_SIZE]))
+        .unwrap();
+
+    // when
+    quarantine(16).upload(&s3, "task", records).await;
+
+    // then
+    assert!(*aborted.lock().unwrap());
+    assert!(sender
+        .unbounded_send(Bytes::from_static(b"1,no_match,a\n"))
+        .is_err());
+}
@@ -0,0 +1,90 @@
+use anyhow::Context;
+use aws_sdk_s3::primitives::ByteStream;
+use aws_sdk_s3::types::CompletedPart;
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt};
+
+use crate::libs::deps::s3;
+
+/// The minimum size of all but the last part of a multipart upload.
+pub(super) const PART_SIZE: usize = 5 * 1024 * 1024;
+
+const CONTENT_TYPE: &str = "text/csv";
+
+/// Writes `records` with a single put if they fit into one part, otherwise with a
+/// multipart upload, which is aborted on failure.
+pub(super) async fn upload(
+    s3: &s3::S3,
+    bucket: &str,
+    key: &str,
+    mut records: impl Stream<Item = Bytes> + Unpin,
+) -> anyhow::Result<()> {
+    let mut part = BytesMut::new();
+    while part.len() < PART_SIZE {
+        match records.next().await {
+            Some(record) => part.extend_from_slice(&record),
+            None => {
+                s3.put_object(bucket, key, ByteStream::from(part.freeze()), CONTENT_TYPE)
+                    .await?;
+                return Ok(());
+            }
+        }
+    }
+    let upload_id = s3
+        .create_multipart_upload(bucket, key, CONTENT_TYPE)
+        .await?
+        .upload_id
+        .context("no upload id in the multipart upload")?;
+    let result = upload_parts(s3, bucket, key, &upload_id, part, &mut records).await;
+    if result.is_err() {
+        if let Err(error) = s3.abort_multipart_upload(bucket, key, &upload_id).await {
+            tracing::warn!("could not abort the multipart upload of s3://{bucket}/{key}: {error}");
+        }
+    }
+    result
+}
+
+async fn upload_parts(
+    s3: &s3::S3,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    mut part: BytesMut,
+    records: &mut (impl Stream<Item = Bytes> + Unpin),
+) -> anyhow::Result<()> {
+    let mut parts = vec![];
+    let mut done = false;
+    while !done {
+        while part.len() < PART_SIZE {
+            match records.next().await {
+                Some(record) => part.extend_from_slice(&record),
+                None => {
+                    done = true;
+                    break;
+                }
+            }
+        }
+        if part.is_empty() {
+            break;
+        }
+        let part_number = parts.len() as i32 + 1;
+        let output = s3
+            .upload_part(
+                bucket,
+                key,
+                upload_id,
+                part_number,
+                ByteStream::from(part.split().freeze()),
+            )
+            .await?;
+        parts.push(
+            CompletedPart::builder()
+                .part_number(part_number)
+                .set_e_tag(output.e_tag)
+                .build(),
+        );
+    }
+    s3.complete_multipart_upload(bucket, key, upload_id, parts)
+        .await?;
+    Ok(())
+}
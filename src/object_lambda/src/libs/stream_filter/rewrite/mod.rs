@@ -0,0 +1,120 @@
+use std::str::from_utf8;
+
+use anyhow::{bail, Context};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::libs::phone::{self, Verdict};
+
+use super::line::{LineAction, LineFilter};
+
+/// Splits a Hungarian number in E.164 into its parts.
+pub const DEFAULT_PATTERN: &str = r"^\+(?P<country>36)(?P<area>1|[2-9][0-9])(?P<subscriber>(?P<exchange>[0-9]{3})(?P<line>[0-9]{4}))$";
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct RewriteConfig {
+    /// matched against the number in E.164, [`DEFAULT_PATTERN`] if not set
+    #[serde(default)]
+    pub pattern: Option<String>,
+    /// the output line, with `$name`, `${name}` or `$1` replaced by a capture group and `$$` by `$`
+    pub template: String,
+}
+
+impl RewriteConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        RewriteFilter::new(self).map(drop)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum Segment {
+    Literal(String),
+    Group(usize),
+}
+
+/// Parses `template`, resolving the group references to their index in `regex`.
+fn parse_template(template: &str, regex: &Regex) -> anyhow::Result<Vec<Segment>> {
+    let mut segments = vec![];
+    let mut literal = String::new();
+    let mut rest = template;
+    while let Some(position) = rest.find('$') {
+        literal.push_str(&rest[..position]);
+        rest = &rest[position + 1..];
+        let name = if let Some(braced) = rest.strip_prefix('{') {
+            let Some(end) = braced.find('}') else {
+                bail!("unclosed `${{` in rewrite template");
+            };
+            rest = &braced[end + 1..];
+            &braced[..end]
+        } else if let Some(escaped) = rest.strip_prefix('$') {
+            literal.push('$');
+            rest = escaped;
+            continue;
+        } else {
+            let end = rest
+                .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            let name = &rest[..end];
+            rest = &rest[end..];
+            name
+        };
+        let index = match name.parse::<usize>() {
+            Ok(index) if index < regex.captures_len() => index,
+            Ok(_) => bail!("rewrite template references missing group ${name}"),
+            Err(_) => regex
+                .capture_names()
+                .position(|capture| capture == Some(name))
+                .with_context(|| format!("rewrite template references unknown group `{name}`"))?,
+        };
+        if !literal.is_empty() {
+            segments.push(Segment::Literal(std::mem::take(&mut literal)));
+        }
+        segments.push(Segment::Group(index));
+    }
+    literal.push_str(rest);
+    if !literal.is_empty() {
+        segments.push(Segment::Literal(literal));
+    }
+    Ok(segments)
+}
+
+/// Rewrites the lines holding just a valid number whose E.164 form matches the pattern, passing
+/// the other lines as they are.
+pub struct RewriteFilter {
+    regex: Regex,
+    template: Vec<Segment>,
+}
+
+impl RewriteFilter {
+    pub fn new(config: &RewriteConfig) -> anyhow::Result<Self> {
+        let pattern = config.pattern.as_deref().unwrap_or(DEFAULT_PATTERN);
+        let regex = Regex::new(pattern).context("rewrite pattern must be a valid regex")?;
+        let template = parse_template(&config.template, &regex)?;
+        Ok(Self { regex, template })
+    }
+}
+
+impl LineFilter for RewriteFilter {
+    fn filter_line(&self, line: &[u8]) -> LineAction {
+        let Some(Verdict::Valid(number)) = from_utf8(line).ok().map(phone::verdict) else {
+            return LineAction::Keep;
+        };
+        let Some(captures) = self.regex.captures(&number) else {
+            return LineAction::Keep;
+        };
+        let mut output = String::new();
+        for segment in &self.template {
+            match segment {
+                Segment::Literal(literal) => output.push_str(literal),
+                Segment::Group(index) => {
+                    output.push_str(captures.get(*index).map_or("", |group| group.as_str()))
+                }
+            }
+        }
+        LineAction::Replace(output.into_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,74 @@
+use std::str::from_utf8;
+
+use super::*;
+
+fn rewrite(pattern: Option<&str>, template: &str, line: &str) -> String {
+    let filter = RewriteFilter::new(&RewriteConfig {
+        pattern: pattern.map(Into::into),
+        template: template.into(),
+    })
+    .unwrap();
+    match filter.filter_line(line.as_bytes()) {
+        LineAction::Replace(output) => from_utf8(&output).unwrap().to_string(),
+        LineAction::Keep => line.to_string(),
+        LineAction::Drop => panic!("{line} was dropped"),
+    }
+}
+
+#[test]
+fn test_default_pattern() {
+    for (template, line, expected) in [
+        (
+            "${country};${area};${subscriber}",
+            "+36 30 123 4567",
+            "36;30;1234567",
+        ),
+        (
+            "(+$country) $area-$exchange-$line",
+            "0036 30 123 4567",
+            "(+36) 30-123-4567",
+        ),
+        ("$$ $area/$subscriber", "+36 1 234 5678", "$ 1/2345678"),
+        ("${3}", "+36 1 234 5678", "2345678"),
+    ] {
+        // when
+        let output = rewrite(None, template, line);
+
+        // then
+        assert_eq!(output, expected);
+    }
+}
+
+#[test]
+fn test_lines_passed_as_they_are() {
+    for line in ["phone", "06 30 123 4567", "+36 1 234 5678"] {
+        // when
+        let output = rewrite(Some(r"^\+3630(?P<rest>\d+)$"), "30/$rest", line);
+
+        // then
+        assert_eq!(output, line);
+    }
+}
+
+#[test]
+fn test_invalid_templates() {
+    for (pattern, template) in [
+        (None, "$area;$unknown"),
+        (None, "${area"),
+        (None, "$9"),
+        (Some("(?P<a>"), "$a"),
+        (Some(r"^\+(?P<a>\d+)$"), "${b}"),
+    ] {
+        // given
+        let config = RewriteConfig {
+            pattern: pattern.map(Into::into),
+            template: template.into(),
+        };
+
+        // when
+        let result = config.validate();
+
+        // then
+        assert!(result.is_err(), "{template}");
+    }
+}
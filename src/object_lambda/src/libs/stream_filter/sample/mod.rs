@@ -0,0 +1,164 @@
+use std::collections::hash_map::RandomState;
+use std::hash::BuildHasher;
+
+use anyhow::bail;
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::Deserialize;
+
+use super::{
+    process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, LineFormat, StreamFilter,
+    StreamItem,
+};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SampleConfig {
+    /// the number of lines kept
+    pub size: usize,
+    /// makes the sample reproducible, a random one is drawn per invocation otherwise
+    #[serde(default)]
+    pub seed: Option<u64>,
+}
+
+impl SampleConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.size == 0 {
+            bail!("sample size must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Keeps a uniform random sample of the output lines, written in input order once the input
+/// ends. Only the sampled lines are held in memory. CSV records are sampled as a whole, and a
+/// header row is always written first.
+pub struct SampleStreamFilter {
+    config: SampleConfig,
+    format: LineFormat,
+}
+
+impl SampleStreamFilter {
+    pub fn new(config: SampleConfig, format: LineFormat) -> Self {
+        Self { config, format }
+    }
+}
+
+impl StreamFilter for SampleStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        let seed = self
+            .config
+            .seed
+            .unwrap_or_else(|| RandomState::new().hash_one(0));
+        process_stream(
+            s,
+            SampleProcessor {
+                lines: self.format.line_buffer(),
+                sampler: Sampler {
+                    header: matches!(self.format, LineFormat::Csv { has_header: true }),
+                    reservoir: Reservoir {
+                        size: self.config.size,
+                        seen: 0,
+                        sample: Vec::new(),
+                        rng: SplitMix64(seed),
+                    },
+                },
+            },
+        )
+    }
+}
+
+/// Reservoir sampling, algorithm R: the i-th line replaces a random sampled one
+/// with a probability of `size / i`.
+struct Reservoir {
+    size: usize,
+    seen: u64,
+    /// the sampled lines with their position in the input
+    sample: Vec<(u64, Bytes)>,
+    rng: SplitMix64,
+}
+
+impl Reservoir {
+    fn on_line(&mut self, line: &[u8]) {
+        let position = self.seen;
+        self.seen += 1;
+        if self.sample.len() < self.size {
+            self.sample.push((position, Bytes::copy_from_slice(line)));
+            return;
+        }
+        let index = self.rng.below(self.seen);
+        if index < self.size as u64 {
+            self.sample[index as usize] = (position, Bytes::copy_from_slice(line));
+        }
+    }
+}
+
+/// A small, fast generator, good enough for sampling and reproducible from its seed.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `0..bound`, by Lemire's multiply-shift.
+    fn below(&mut self, bound: u64) -> u64 {
+        ((self.next() as u128 * bound as u128) >> 64) as u64
+    }
+}
+
+struct SampleProcessor {
+    lines: LineBuffer,
+    sampler: Sampler,
+}
+
+struct Sampler {
+    /// whether the next line is a header row, written as it is
+    header: bool,
+    reservoir: Reservoir,
+}
+
+impl Sampler {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        if self.header {
+            self.header = false;
+            output.put_slice(line);
+            output.put_u8(b'\n');
+        } else {
+            self.reservoir.on_line(line);
+        }
+        Ok(())
+    }
+}
+
+impl ChunkProcessor for SampleProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .push(&chunk, |line| self.sampler.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .finish(|line| self.sampler.on_line(line, &mut output))?;
+        let mut sample = std::mem::take(&mut self.sampler.reservoir.sample);
+        sample.sort_unstable_by_key(|(position, _)| *position);
+        for (_, line) in sample {
+            output.put(line);
+            output.put_u8(b'\n');
+        }
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
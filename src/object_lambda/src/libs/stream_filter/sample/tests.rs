@@ -0,0 +1,118 @@
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+use regex::Regex;
+
+use super::*;
+
+async fn run(config: SampleConfig, input: String) -> Vec<String> {
+    from_utf8(&run_with_format(config, input, LineFormat::Text).await)
+        .unwrap()
+        .lines()
+        .map(str::to_string)
+        .collect()
+}
+
+async fn run_with_format(config: SampleConfig, input: String, format: LineFormat) -> Vec<u8> {
+    let filter = SampleStreamFilter::new(config, format);
+    let input = stream::iter([Ok(Bytes::from(input))]);
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    output.concat()
+}
+
+fn numbers(count: usize) -> String {
+    (0..count).map(|i| format!("+3630{i:07}\n")).collect()
+}
+
+#[tokio::test]
+async fn test_sample_is_reproducible_with_seed() {
+    // given
+    let config = SampleConfig {
+        size: 10,
+        seed: Some(42),
+    };
+
+    // when
+    let first = run(config.clone(), numbers(1000)).await;
+    let second = run(config, numbers(1000)).await;
+
+    // then
+    assert_eq!(first.len(), 10);
+    assert_eq!(first, second);
+    let mut sorted = first.clone();
+    sorted.sort();
+    sorted.dedup();
+    assert_eq!(sorted, first);
+}
+
+#[tokio::test]
+async fn test_short_input_is_kept() {
+    // when
+    let sample = run(
+        SampleConfig {
+            size: 10,
+            seed: None,
+        },
+        "+36 1 234 5678\n+36 30 123 4567".into(),
+    )
+    .await;
+
+    // then
+    assert_eq!(sample, ["+36 1 234 5678", "+36 30 123 4567"]);
+}
+
+#[tokio::test]
+async fn test_csv_records_with_multi_line_fields() {
+    // given
+    let records: String = (0..100)
+        .map(|i| format!("\"Anna\n{i}\";+3630{i:07}\n"))
+        .collect();
+
+    // when
+    let sample = run_with_format(
+        SampleConfig {
+            size: 5,
+            seed: Some(42),
+        },
+        format!("name;phone\n{records}"),
+        LineFormat::Csv { has_header: true },
+    )
+    .await;
+
+    // then
+    let records = Regex::new(r#"^name;phone\n("Anna\n\d+";\+3630\d{7}\n){5}$"#).unwrap();
+    assert!(records.is_match(from_utf8(&sample).unwrap()));
+}
+
+#[test]
+fn test_sample_is_uniform() {
+    // given
+    let mut hits = [0u32; 10];
+
+    // when
+    for seed in 0..2000 {
+        let mut reservoir = Reservoir {
+            size: 2,
+            seen: 0,
+            sample: Vec::new(),
+            rng: SplitMix64(seed),
+        };
+        for i in 0..10u8 {
+            reservoir.on_line(&[i]);
+        }
+        for (position, _) in reservoir.sample {
+            hits[position as usize] += 1;
+        }
+    }
+
+    // then
+    // every line is sampled with a probability of 2 / 10, i.e. 400 times of 2000
+    assert!(
+        hits.iter().all(|&hit| (320..480).contains(&hit)),
+        "{hits:?}"
+    );
+}
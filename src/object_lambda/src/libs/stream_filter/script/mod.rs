@@ -0,0 +1,189 @@
+use std::str::from_utf8;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use bytes::{Bytes, BytesMut};
+use rhai::{Dynamic, Engine, Map, Scope, AST};
+use serde::Deserialize;
+
+use crate::libs::phone::{self, Verdict};
+
+use super::line::{write_line, LineAction};
+use super::{
+    process_stream_blocking, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter,
+    StreamItem,
+};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ScriptConfig {
+    /// Rhai script run for every line, which is in the `line` variable
+    pub script: String,
+    /// the script fails once it runs more operations on a line
+    #[serde(default = "default_max_operations")]
+    pub max_operations: u64,
+}
+
+fn default_max_operations() -> u64 {
+    100_000
+}
+
+impl ScriptConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_operations == 0 {
+            bail!("script maxOperations must be positive");
+        }
+        ScriptStreamFilter::new(self).map(drop)
+    }
+}
+
+fn number(line: &str) -> Option<String> {
+    match phone::verdict(line) {
+        Verdict::Valid(number) => Some(number),
+        Verdict::Rejected(_) => None,
+    }
+}
+
+/// The sandboxed engine, with the phone helpers and limits on what a script may allocate.
+fn engine(config: &ScriptConfig) -> Engine {
+    let mut engine = Engine::new();
+    engine
+        .set_max_operations(config.max_operations)
+        .set_max_call_levels(32)
+        .set_max_expr_depths(64, 32)
+        .set_max_string_size(1024 * 1024)
+        .set_max_array_size(10_000)
+        .set_max_map_size(10_000)
+        .on_print(|text| tracing::info!("script: {text}"))
+        .on_debug(|text, _, position| tracing::debug!("script at {position}: {text}"));
+    engine
+        .register_fn("is_valid", |line: &str| number(line).is_some())
+        .register_fn("normalize_phone", |line: &str| {
+            number(line).map_or(Dynamic::UNIT, Dynamic::from)
+        })
+        .register_fn("classify", |line: &str| {
+            let Some(number) = number(line) else {
+                return Dynamic::UNIT;
+            };
+            let Some(classification) = phone::classify(&number) else {
+                return Dynamic::UNIT;
+            };
+            let mut map = Map::new();
+            map.insert(
+                "areaCode".into(),
+                classification.area_code.to_string().into(),
+            );
+            map.insert("type".into(), classification.number_type.code().into());
+            map.into()
+        });
+    engine
+}
+
+/// Runs a Rhai script on every line, which keeps the line by returning `true` or `()`, drops
+/// it by returning `false`, or replaces it by returning a string. The script is compiled once,
+/// when the filter is built, and the compiled script is reused by every invocation. Scripts run
+/// on the blocking threads of the runtime.
+pub struct ScriptStreamFilter {
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+}
+
+impl ScriptStreamFilter {
+    pub fn new(config: &ScriptConfig) -> anyhow::Result<Self> {
+        let engine = engine(config);
+        let ast = engine
+            .compile(&config.script)
+            .map_err(|error| anyhow!("script does not compile: {error}"))?;
+        Ok(Self {
+            engine: Arc::new(engine),
+            ast: Arc::new(ast),
+        })
+    }
+}
+
+impl StreamFilter for ScriptStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream_blocking(
+            s,
+            ScriptProcessor {
+                lines: LineBuffer::default(),
+                script: Script {
+                    engine: self.engine.clone(),
+                    ast: self.ast.clone(),
+                    line_number: 0,
+                },
+            },
+        )
+    }
+}
+
+struct ScriptProcessor {
+    lines: LineBuffer,
+    script: Script,
+}
+
+struct Script {
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+    line_number: u64,
+}
+
+impl Script {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        self.line_number += 1;
+        let action = self
+            .run(line)
+            .with_context(|| format!("script failed on line {}", self.line_number))?;
+        write_line(line, action, output);
+        Ok(())
+    }
+
+    fn run(&self, line: &[u8]) -> anyhow::Result<LineAction> {
+        let Ok(line) = from_utf8(line) else {
+            return Ok(LineAction::Drop);
+        };
+        let mut scope = Scope::new();
+        scope.push_constant("line", line.to_string());
+        let result: Dynamic = self
+            .engine
+            .eval_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|error| anyhow!("{error}"))?;
+        if result.is_unit() {
+            return Ok(LineAction::Keep);
+        }
+        if let Ok(keep) = result.as_bool() {
+            return Ok(if keep {
+                LineAction::Keep
+            } else {
+                LineAction::Drop
+            });
+        }
+        match result.into_string() {
+            Ok(replacement) => Ok(LineAction::Replace(replacement.into_bytes())),
+            Err(type_name) => bail!("script returned {type_name}, not a bool, string or ()"),
+        }
+    }
+}
+
+impl ChunkProcessor for ScriptProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .push(&chunk, |line| self.script.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .finish(|line| self.script.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,91 @@
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+
+use super::*;
+
+async fn run(script: &str, input: &'static str) -> anyhow::Result<String> {
+    let filter = ScriptStreamFilter::new(&ScriptConfig {
+        script: script.into(),
+        max_operations: default_max_operations(),
+    })?;
+    let input = stream::iter([Ok(Bytes::from_static(input.as_bytes()))]);
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk?).unwrap());
+    }
+    Ok(output)
+}
+
+const INPUT: &str = "+36 1 234 5678\nphone\n0036 30 123 4567\n+36 90 123 4567";
+
+#[tokio::test]
+async fn test_keep_drop_and_rewrite() {
+    // given
+    let script = r#"
+        if !is_valid(line) {
+            return false;
+        }
+        let info = classify(line);
+        if info.type == "premiumRate" {
+            return false;
+        }
+        if info.type == "mobile" {
+            return `${normalize_phone(line)} (${info.areaCode})`;
+        }
+    "#;
+
+    // when
+    let output = run(script, INPUT).await;
+
+    // then
+    assert_eq!(output.unwrap(), "+36 1 234 5678\n+36301234567 (30)\n");
+}
+
+#[tokio::test]
+async fn test_helpers_on_invalid_lines() {
+    // when
+    let output = run(
+        r#"normalize_phone(line) == () && classify(line) == () && line.len() > 0"#,
+        "phone\n\n",
+    )
+    .await;
+
+    // then
+    assert_eq!(output.unwrap(), "phone\n");
+}
+
+#[tokio::test]
+async fn test_runaway_script() {
+    // when
+    let output = run("loop {}", INPUT).await;
+
+    // then
+    let error = format!("{:#}", output.unwrap_err());
+    assert!(error.contains("line 1"), "{error}");
+}
+
+#[tokio::test]
+async fn test_invalid_result() {
+    // when
+    let output = run("42", INPUT).await;
+
+    // then
+    assert!(output.is_err());
+}
+
+#[test]
+fn test_invalid_script() {
+    // given
+    let config = ScriptConfig {
+        script: "if line {".into(),
+        max_operations: default_max_operations(),
+    };
+
+    // when
+    let result = config.validate();
+
+    // then
+    assert!(result.is_err());
+}
@@ -0,0 +1,153 @@
+use std::str::from_utf8;
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{Deserialize, Serialize};
+
+use crate::libs::phone::{self, NumberType, Verdict};
+
+use super::csv::write_record;
+use super::{
+    process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter, StreamItem,
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SerializeFormat {
+    /// one JSON object per line
+    #[default]
+    Jsonl,
+    /// a single JSON array
+    Json,
+    /// CSV with a header
+    Csv,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SerializeConfig {
+    #[serde(default)]
+    pub format: SerializeFormat,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Record<'a> {
+    number: &'a str,
+    area_code: Option<&'a str>,
+    #[serde(rename = "type")]
+    number_type: Option<NumberType>,
+}
+
+/// Writes every line holding just a valid number as a record of the number in E.164, its area
+/// code and its type. Other lines are dropped.
+pub struct SerializeStreamFilter {
+    config: SerializeConfig,
+}
+
+impl SerializeStreamFilter {
+    pub fn new(config: SerializeConfig) -> Self {
+        Self { config }
+    }
+}
+
+impl StreamFilter for SerializeStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            SerializeProcessor {
+                lines: LineBuffer::default(),
+                serializer: Serializer {
+                    format: self.config.format,
+                    records: 0,
+                },
+            },
+        )
+    }
+}
+
+struct SerializeProcessor {
+    lines: LineBuffer,
+    serializer: Serializer,
+}
+
+struct Serializer {
+    format: SerializeFormat,
+    records: u64,
+}
+
+impl Serializer {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        let verdict = from_utf8(line).map(phone::verdict);
+        let Ok(Verdict::Valid(number)) = verdict else {
+            return Ok(());
+        };
+        let classification = phone::classify(&number);
+        let record = Record {
+            number: &number,
+            area_code: classification.map(|c| c.area_code),
+            number_type: classification.map(|c| c.number_type),
+        };
+        let first = self.records == 0;
+        self.records += 1;
+        match self.format {
+            SerializeFormat::Jsonl => {
+                output.put_slice(&serde_json::to_vec(&record)?);
+                output.put_u8(b'\n');
+            }
+            SerializeFormat::Json => {
+                output.put_slice(if first { b"[\n" } else { b",\n" });
+                output.put_slice(&serde_json::to_vec(&record)?);
+            }
+            SerializeFormat::Csv => {
+                if first {
+                    write_record(output, &[b"number", b"areaCode", b"type"], b',');
+                }
+                write_record(
+                    output,
+                    &[
+                        record.number.as_bytes(),
+                        record.area_code.unwrap_or_default().as_bytes(),
+                        record.number_type.map_or("", NumberType::code).as_bytes(),
+                    ],
+                    b',',
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, output: &mut BytesMut) {
+        match self.format {
+            SerializeFormat::Json if self.records == 0 => output.put_slice(b"[]\n"),
+            SerializeFormat::Json => output.put_slice(b"\n]\n"),
+            SerializeFormat::Csv if self.records == 0 => {
+                write_record(output, &[b"number", b"areaCode", b"type"], b',')
+            }
+            _ => {}
+        }
+    }
+}
+
+impl ChunkProcessor for SerializeProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .push(&chunk, |line| self.serializer.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .finish(|line| self.serializer.on_line(line, &mut output))?;
+        self.serializer.finish(&mut output);
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,63 @@
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+
+use super::*;
+
+async fn run(format: SerializeFormat, input: &'static str) -> String {
+    let filter = SerializeStreamFilter::new(SerializeConfig { format });
+    let input = stream::iter([Ok(Bytes::from_static(input.as_bytes()))]);
+    let output: Vec<_> = filter
+        .filter_stream(Box::new(input))
+        .map(|chunk| chunk.unwrap())
+        .collect()
+        .await;
+    from_utf8(&output.concat()).unwrap().to_string()
+}
+
+const INPUT: &str = "+36 1 234 5678\nphone\n0036 30 123 4567";
+
+#[tokio::test]
+async fn test_jsonl() {
+    // when
+    let output = run(SerializeFormat::Jsonl, INPUT).await;
+
+    // then
+    assert_eq!(
+        output,
+        "{\"number\":\"+3612345678\",\"areaCode\":\"1\",\"type\":\"fixed\"}\n\
+         {\"number\":\"+36301234567\",\"areaCode\":\"30\",\"type\":\"mobile\"}\n"
+    );
+}
+
+#[tokio::test]
+async fn test_json() {
+    // when
+    let output = run(SerializeFormat::Json, INPUT).await;
+
+    // then
+    let records: serde_json::Value = serde_json::from_str(&output).unwrap();
+    assert_eq!(records[1]["number"], "+36301234567");
+    assert_eq!(records.as_array().unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn test_json_without_numbers() {
+    // when
+    let output = run(SerializeFormat::Json, "phone\n").await;
+
+    // then
+    assert_eq!(output, "[]\n");
+}
+
+#[tokio::test]
+async fn test_csv() {
+    // when
+    let output = run(SerializeFormat::Csv, INPUT).await;
+
+    // then
+    assert_eq!(
+        output,
+        "number,areaCode,type\n+3612345678,1,fixed\n+36301234567,30,mobile\n"
+    );
+}
@@ -0,0 +1,341 @@
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+use std::mem::{self, size_of};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::from_utf8;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::vec;
+
+use anyhow::{bail, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::{stream, StreamExt};
+use regex::Regex;
+use serde::Deserialize;
+
+use super::{line_number, BoxedSendSyncUnpinStream, LineBuffer, StreamFilter, StreamItem};
+
+/// The size of the output chunks of the merge.
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// The bytes a run takes per number besides its digits: the value and the digit count.
+const RUN_ENTRY_BYTES: u64 = (size_of::<u64>() + size_of::<u32>()) as u64;
+
+/// Tells apart the runs of the invocations of a Lambda instance.
+static SORTS: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SortConfig {
+    /// numbers are sorted in memory up to this size, then spilled as a sorted run
+    #[serde(default = "default_memory_bytes")]
+    pub memory_bytes: usize,
+    #[serde(default = "default_spill_dir")]
+    pub spill_dir: String,
+    /// the invocation fails once its runs would grow over this size, keep it within the
+    /// ephemeral storage of the function
+    #[serde(default = "default_max_spill_bytes")]
+    pub max_spill_bytes: u64,
+}
+
+fn default_memory_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_spill_dir() -> String {
+    "/tmp".into()
+}
+
+fn default_max_spill_bytes() -> u64 {
+    448 * 1024 * 1024
+}
+
+impl SortConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.memory_bytes < size_of::<Entry>() {
+            bail!("sort memoryBytes must hold at least one number");
+        }
+        if self.spill_dir.is_empty() {
+            bail!("sort spillDir must be set");
+        }
+        Ok(())
+    }
+}
+
+/// Outputs the numbers of the output lines in E.164, sorted and without duplicates, with an
+/// external merge sort. Lines not holding just a number are dropped.
+///
+/// Numbers are ordered by their value, then by their digits: the spellings of a value with a
+/// different number of leading zeros, e.g. `+036…` and `+36…`, are kept apart, the longer one
+/// first. The runs are written and merged on the blocking threads of the runtime.
+pub struct SortStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<SortConfig>,
+}
+
+impl SortStreamFilter {
+    pub fn new(regex: Regex, config: SortConfig) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl StreamFilter for SortStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        let regex = self.regex.clone();
+        let config = self.config.clone();
+        let sorted = stream::once(Box::pin(async move {
+            let mut sorter = Sorter::new(regex, config);
+            sorter.read(s).await?;
+            tokio::task::spawn_blocking(move || sorter.merge()).await?
+        }));
+        Box::new(
+            sorted.flat_map(|merge| -> BoxedSendSyncUnpinStream<StreamItem> {
+                match merge {
+                    Ok(merge) => merge.into_stream(),
+                    Err(error) => Box::new(stream::iter([Err(error)])),
+                }
+            }),
+        )
+    }
+}
+
+/// A number as its value, which orders it, and its digits in E.164, which are written out.
+type Entry = (u64, Box<[u8]>);
+
+struct Sorter {
+    regex: Arc<Regex>,
+    numbers: Vec<Entry>,
+    /// the bytes taken by `numbers`
+    memory: usize,
+    memory_bytes: usize,
+    runs: Runs,
+    dropped: u64,
+}
+
+impl Sorter {
+    fn new(regex: Arc<Regex>, config: Arc<SortConfig>) -> Self {
+        Self {
+            regex,
+            numbers: Vec::new(),
+            memory: 0,
+            memory_bytes: config.memory_bytes,
+            runs: Runs {
+                config,
+                id: SORTS.fetch_add(1, Ordering::Relaxed),
+                paths: vec![],
+                spilled: 0,
+            },
+            dropped: 0,
+        }
+    }
+
+    async fn read(&mut self, mut s: BoxedSendSyncUnpinStream<StreamItem>) -> anyhow::Result<()> {
+        let mut lines = LineBuffer::default();
+        // the numbers are spilled once the chunk filling the memory is read
+        while let Some(chunk) = s.next().await {
+            lines.push(&chunk?, |line| {
+                self.on_line(line);
+                Ok(())
+            })?;
+            if self.memory >= self.memory_bytes {
+                self.memory = 0;
+                self.runs.spill(mem::take(&mut self.numbers)).await?;
+            }
+        }
+        lines.finish(|line| {
+            self.on_line(line);
+            Ok(())
+        })
+    }
+
+    fn on_line(&mut self, line: &[u8]) {
+        let entry: Option<Entry> = from_utf8(line)
+            .ok()
+            .and_then(|line| line_number(&self.regex, line))
+            .and_then(|number| {
+                let digits = &number.as_bytes()[1..];
+                let value = number[1..].parse().ok()?;
+                Some((value, Box::from(digits)))
+            });
+        let Some(entry) = entry else {
+            self.dropped += 1;
+            return;
+        };
+        self.memory += size_of::<Entry>() + entry.1.len();
+        self.numbers.push(entry);
+    }
+
+    fn merge(mut self) -> anyhow::Result<Merge> {
+        if self.dropped > 0 {
+            tracing::warn!("dropped {} lines not holding just a number", self.dropped);
+        }
+        self.numbers.sort_unstable();
+        self.numbers.dedup();
+        let mut sources = vec![Source::Memory(mem::take(&mut self.numbers).into_iter())];
+        for path in &self.runs.paths {
+            let file = File::open(path)
+                .with_context(|| format!("could not open sorted run {}", path.display()))?;
+            sources.push(Source::Run(BufReader::new(file)));
+        }
+        let mut heap = BinaryHeap::new();
+        for (index, source) in sources.iter_mut().enumerate() {
+            if let Some(entry) = source.next()? {
+                heap.push(Reverse((entry, index)));
+            }
+        }
+        Ok(Merge {
+            _runs: self.runs,
+            sources,
+            heap,
+            last: None,
+        })
+    }
+}
+
+/// The sorted runs spilled by an invocation, removed once it is dropped.
+struct Runs {
+    config: Arc<SortConfig>,
+    id: u64,
+    paths: Vec<PathBuf>,
+    spilled: u64,
+}
+
+impl Runs {
+    async fn spill(&mut self, mut numbers: Vec<Entry>) -> anyhow::Result<()> {
+        let path = Path::new(&self.config.spill_dir).join(format!(
+            "sort-{}-{}-{}.run",
+            process::id(),
+            self.id,
+            self.paths.len()
+        ));
+        self.paths.push(path.clone());
+        let (max_spill_bytes, spilled) = (self.config.max_spill_bytes, self.spilled);
+        let size = tokio::task::spawn_blocking(move || -> anyhow::Result<u64> {
+            numbers.sort_unstable();
+            numbers.dedup();
+            let size = (numbers.iter())
+                .map(|(_, digits)| RUN_ENTRY_BYTES + digits.len() as u64)
+                .sum::<u64>();
+            if spilled + size > max_spill_bytes {
+                bail!("sorting needs more than the maxSpillBytes of {max_spill_bytes} bytes");
+            }
+            let write = || -> std::io::Result<()> {
+                let mut file = BufWriter::new(File::create(&path)?);
+                for (value, digits) in &numbers {
+                    file.write_all(&value.to_le_bytes())?;
+                    file.write_all(&(digits.len() as u32).to_le_bytes())?;
+                    file.write_all(digits)?;
+                }
+                file.flush()
+            };
+            write().with_context(|| format!("could not spill sorted run {}", path.display()))?;
+            Ok(size)
+        })
+        .await??;
+        self.spilled += size;
+        Ok(())
+    }
+}
+
+impl Drop for Runs {
+    fn drop(&mut self) {
+        for path in &self.paths {
+            if let Err(error) = fs::remove_file(path) {
+                if error.kind() != ErrorKind::NotFound {
+                    tracing::warn!("could not remove sorted run {}: {error}", path.display());
+                }
+            }
+        }
+    }
+}
+
+enum Source {
+    Memory(vec::IntoIter<Entry>),
+    Run(BufReader<File>),
+}
+
+impl Source {
+    fn next(&mut self) -> anyhow::Result<Option<Entry>> {
+        match self {
+            Source::Memory(numbers) => Ok(numbers.next()),
+            Source::Run(file) => {
+                let mut value = [0; size_of::<u64>()];
+                match file.read_exact(&mut value) {
+                    Ok(()) => (),
+                    Err(error) if error.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+                    Err(error) => return Err(error).context("could not read sorted run"),
+                }
+                let mut len = [0; size_of::<u32>()];
+                file.read_exact(&mut len)
+                    .context("could not read sorted run")?;
+                let mut digits = vec![0; u32::from_le_bytes(len) as usize];
+                file.read_exact(&mut digits)
+                    .context("could not read sorted run")?;
+                Ok(Some((u64::from_le_bytes(value), digits.into())))
+            }
+        }
+    }
+}
+
+/// The k-way merge of the sorted runs, read a chunk at a time.
+struct Merge {
+    /// keeps the runs until the merge is dropped
+    _runs: Runs,
+    sources: Vec<Source>,
+    heap: BinaryHeap<Reverse<(Entry, usize)>>,
+    last: Option<Entry>,
+}
+
+impl Merge {
+    fn next_chunk(&mut self) -> anyhow::Result<Option<Bytes>> {
+        let mut chunk = BytesMut::new();
+        while chunk.len() < CHUNK_LEN {
+            let Some(Reverse((entry, index))) = self.heap.pop() else {
+                break;
+            };
+            if let Some(next) = self.sources[index].next()? {
+                self.heap.push(Reverse((next, index)));
+            }
+            // runs are free of duplicates, but not of those of other runs
+            if self.last.as_ref() != Some(&entry) {
+                chunk.put_u8(b'+');
+                chunk.put_slice(&entry.1);
+                chunk.put_u8(b'\n');
+                self.last = Some(entry);
+            }
+        }
+        Ok((!chunk.is_empty()).then(|| chunk.freeze()))
+    }
+
+    /// Reads the chunks on the blocking threads, where the runs are also removed once the
+    /// merge ends.
+    fn into_stream(self) -> BoxedSendSyncUnpinStream<StreamItem> {
+        let chunks = stream::unfold(Some(self), |merge| async move {
+            let mut merge = merge?;
+            let next = tokio::task::spawn_blocking(move || {
+                let chunk = merge.next_chunk().transpose();
+                let merge = matches!(chunk, Some(Ok(_))).then_some(merge);
+                (chunk, merge)
+            })
+            .await;
+            match next {
+                Ok((chunk, merge)) => Some((chunk?, merge)),
+                Err(error) => Some((Err(error.into()), None)),
+            }
+        });
+        Box::new(Box::pin(chunks))
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,117 @@
+use std::env;
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::*;
+
+/// A spill directory of its own for every test, as they run in parallel.
+fn spill_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("sort-test-{}-{name}", process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn filter(memory_bytes: usize, spill_dir: &Path, max_spill_bytes: u64) -> SortStreamFilter {
+    SortStreamFilter::new(
+        Regex::new(HU_PATTERN).unwrap(),
+        SortConfig {
+            memory_bytes,
+            spill_dir: spill_dir.to_str().unwrap().into(),
+            max_spill_bytes,
+        },
+    )
+}
+
+async fn run(filter: SortStreamFilter, input: Vec<String>) -> Vec<StreamItem> {
+    let input = stream::iter(
+        input
+            .into_iter()
+            .map(|chunk| Ok(Bytes::from(chunk)))
+            .collect::<Vec<_>>(),
+    );
+    filter.filter_stream(Box::new(input)).collect().await
+}
+
+#[tokio::test]
+async fn test_sort_in_memory() {
+    // given
+    let dir = spill_dir("memory");
+    let input = vec![
+        "+36 30 123 4567\n+36 1 234 5678\nphone\n".into(),
+        "0036 1 234 5678".into(),
+    ];
+
+    // when
+    let output = run(filter(1024, &dir, 1024), input).await;
+
+    // then
+    let output: Vec<_> = output.into_iter().map(Result::unwrap).collect();
+    assert_eq!(output.concat(), b"+3612345678\n+36301234567\n");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_sort_with_spilled_runs() {
+    // given
+    let dir = spill_dir("spilled");
+    let input = (0..100)
+        .rev()
+        .map(|i| format!("+36 30 {:07}\n+36 1 {:07}\n", i % 40, i))
+        .collect();
+
+    // when
+    // a run every two chunks, of four numbers
+    let output = run(filter(100, &dir, 1024 * 1024), input).await;
+
+    // then
+    let output: Vec<_> = output.into_iter().map(Result::unwrap).collect();
+    let output = String::from_utf8(output.concat()).unwrap();
+    let mut expected: Vec<_> = (0..100).map(|i| format!("+361{i:07}")).collect();
+    expected.extend((0..40).map(|i| format!("+3630{i:07}")));
+    assert_eq!(output.lines().collect::<Vec<_>>(), expected);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_spill_space_exceeded() {
+    // given
+    let dir = spill_dir("exceeded");
+    let input = (1..=4).map(|i| format!("+36 1 234 567{i}\n")).collect();
+
+    // when
+    // a run of one number, 22 bytes, per chunk, two runs fit
+    let output = run(filter(24, &dir, 44), input).await;
+
+    // then
+    assert_eq!(output.len(), 1);
+    let error = output.into_iter().next().unwrap().unwrap_err();
+    assert!(error.to_string().contains("maxSpillBytes"), "{error}");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn test_leading_zeros_are_kept() {
+    // given
+    let dir = spill_dir("zeros");
+    let filter = SortStreamFilter::new(
+        Regex::new(r"^\+[0-9]+$").unwrap(),
+        SortConfig {
+            memory_bytes: 64,
+            spill_dir: dir.to_str().unwrap().into(),
+            max_spill_bytes: 1024,
+        },
+    );
+    let input = vec!["+036\n+36\n".into(), "+0036\n+35\n".into(), "+036\n".into()];
+
+    // when
+    let output = run(filter, input).await;
+
+    // then
+    let output: Vec<_> = output.into_iter().map(Result::unwrap).collect();
+    assert_eq!(output.concat(), b"+35\n+0036\n+036\n+36\n");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+    fs::remove_dir(&dir).unwrap();
+}
@@ -0,0 +1,218 @@
+use std::io::{BufReader, Cursor, Read};
+use std::str::from_utf8;
+
+use anyhow::{bail, Context};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+
+const OLE_MAGIC: &[u8] = b"\xd0\xcf\x11\xe0\xa1\xb1\x1a\xe1";
+const ODS_MIMETYPE: &[u8] = b"application/vnd.oasis.opendocument.spreadsheet";
+
+/// The block of rows and columns spanned by the cells of a sheet.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub(super) struct Extent(Option<((u64, u64), (u64, u64))>);
+
+impl Extent {
+    /// Widens the extent to cover the block from `start` to `end`, both inclusive.
+    pub(super) fn add(&mut self, start: (u64, u64), end: (u64, u64)) {
+        self.0 = Some(match self.0 {
+            None => (start, end),
+            Some((first, last)) => (
+                (first.0.min(start.0), first.1.min(start.1)),
+                (last.0.max(end.0), last.1.max(end.1)),
+            ),
+        });
+    }
+
+    pub(super) fn cells(&self) -> u64 {
+        self.0.map_or(0, |(first, last)| {
+            (last.0 - first.0 + 1).saturating_mul(last.1 - first.1 + 1)
+        })
+    }
+}
+
+/// Extents of the sheets of XLS and ODS workbooks, which calamine expands into dense ranges
+/// while opening them, before any sheet can be looked at. `None` for other formats.
+pub(super) fn scan(input: &[u8]) -> anyhow::Result<Option<Vec<Extent>>> {
+    if input.starts_with(OLE_MAGIC) {
+        xls(input)
+    } else {
+        ods(input)
+    }
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<u64> {
+    Some(u16::from_le_bytes(data.get(at..at + 2)?.try_into().ok()?) as u64)
+}
+
+fn read_u32(data: &[u8], at: usize) -> Option<u64> {
+    Some(u32::from_le_bytes(data.get(at..at + 4)?.try_into().ok()?) as u64)
+}
+
+/// Walks the BIFF records of the workbook stream, as calamine does.
+fn xls(input: &[u8]) -> anyhow::Result<Option<Vec<Extent>>> {
+    let Ok(mut file) = cfb::CompoundFile::open(Cursor::new(input)) else {
+        return Ok(None);
+    };
+    let Ok(mut stream) = file
+        .open_stream("Workbook")
+        .or_else(|_| file.open_stream("Book"))
+    else {
+        return Ok(None);
+    };
+    let mut workbook = vec![];
+    stream
+        .read_to_end(&mut workbook)
+        .context("could not read XLS workbook stream")?;
+
+    let mut extents = vec![];
+    // the first substream holds the workbook globals, every further one a sheet
+    let mut current: Option<Extent> = None;
+    let mut records = &workbook[..];
+    while let (Some(typ), Some(len)) = (read_u16(records, 0), read_u16(records, 2)) {
+        let Some(data) = records.get(4..4 + len as usize) else {
+            break;
+        };
+        records = &records[4 + len as usize..];
+        let extent = current.get_or_insert_with(Extent::default);
+        match typ {
+            // encrypted records cannot be read, calamine rejects the workbook anyway
+            0x002F if read_u16(data, 0) != Some(0) => return Ok(None),
+            // Dimensions, for which calamine reserves the cells up front
+            0x0200 => {
+                let (first_row, last_row, first_column, last_column) = match data.len() {
+                    10 => (
+                        read_u16(data, 0),
+                        read_u16(data, 2),
+                        read_u16(data, 4),
+                        read_u16(data, 6),
+                    ),
+                    _ => (
+                        read_u32(data, 0),
+                        read_u32(data, 4),
+                        read_u16(data, 8),
+                        read_u16(data, 10),
+                    ),
+                };
+                if let (Some(first_row), Some(end_row), Some(first_column), Some(end_column)) =
+                    (first_row, last_row, first_column, last_column)
+                {
+                    if end_row >= 1 && end_column >= 1 {
+                        if first_row >= end_row || first_column >= end_column {
+                            bail!("malformed XLS dimensions");
+                        }
+                        extent.add((first_row, first_column), (end_row - 1, end_column - 1));
+                    }
+                }
+            }
+            // Number, Label, BoolErr, Rk, LabelSst and Formula
+            0x0203 | 0x0204 | 0x0205 | 0x027E | 0x00FD | 0x0006 => {
+                if let (Some(row), Some(column)) = (read_u16(data, 0), read_u16(data, 2)) {
+                    extent.add((row, column), (row, column));
+                }
+            }
+            // MulRk
+            0x00BD => {
+                if let (Some(row), Some(first_column), Some(last_column)) = (
+                    read_u16(data, 0),
+                    read_u16(data, 2),
+                    read_u16(data, data.len().saturating_sub(2)),
+                ) {
+                    extent.add((row, first_column), (row, last_column.max(first_column)));
+                }
+            }
+            // EOF
+            0x000A => {
+                let extent = current.take().unwrap_or_default();
+                extents.push(extent);
+            }
+            _ => (),
+        }
+    }
+    if !extents.is_empty() {
+        extents.remove(0);
+    }
+    Ok(Some(extents))
+}
+
+fn repeats(element: &BytesStart, name: &str) -> anyhow::Result<u64> {
+    match element.try_get_attribute(name)? {
+        Some(attribute) => Ok(from_utf8(&attribute.value)?
+            .parse()
+            .with_context(|| format!("invalid ODS attribute {name}"))?),
+        None => Ok(1),
+    }
+}
+
+/// Walks the rows and cells of `content.xml`, expanding repeated ones as calamine does:
+/// only the non-empty cells count, but any rows and columns between them are filled in.
+fn ods(input: &[u8]) -> anyhow::Result<Option<Vec<Extent>>> {
+    let Ok(mut archive) = zip::ZipArchive::new(Cursor::new(input)) else {
+        return Ok(None);
+    };
+    let mut mimetype = vec![];
+    match archive.by_name("mimetype") {
+        Ok(mut file) => {
+            file.read_to_end(&mut mimetype)?;
+        }
+        Err(_) => return Ok(None),
+    }
+    if mimetype != ODS_MIMETYPE {
+        return Ok(None);
+    }
+    let content = archive
+        .by_name("content.xml")
+        .context("ODS workbook has no content.xml")?;
+    let mut reader = Reader::from_reader(BufReader::new(content));
+
+    let mut extents = vec![];
+    let (mut row, mut row_repeats, mut column) = (0u64, 1u64, 0u64);
+    let mut buf = vec![];
+    loop {
+        let (element, has_content) = match reader.read_event_into(&mut buf)? {
+            Event::Start(element) => (element, true),
+            Event::Empty(element) => (element, false),
+            Event::End(element) => {
+                if element.name().as_ref() == b"table:table-row" {
+                    row = row.saturating_add(row_repeats);
+                }
+                buf.clear();
+                continue;
+            }
+            Event::Eof => break,
+            _ => {
+                buf.clear();
+                continue;
+            }
+        };
+        match element.name().as_ref() {
+            b"table:table" if has_content => {
+                extents.push(Extent::default());
+                row = 0;
+            }
+            b"table:table-row" if has_content => {
+                row_repeats = repeats(&element, "table:number-rows-repeated")?;
+                column = 0;
+            }
+            b"table:table-cell" | b"table:covered-table-cell" => {
+                let columns = repeats(&element, "table:number-columns-repeated")?;
+                let non_empty = has_content
+                    || element.try_get_attribute("office:value-type")?.is_some()
+                    || element.try_get_attribute("table:formula")?.is_some();
+                if let (true, Some(extent)) = (non_empty && columns > 0, extents.last_mut()) {
+                    extent.add(
+                        (row, column),
+                        (
+                            row.saturating_add(row_repeats.saturating_sub(1)),
+                            column.saturating_add(columns - 1),
+                        ),
+                    );
+                }
+                column = column.saturating_add(columns);
+            }
+            _ => (),
+        }
+        buf.clear();
+    }
+    Ok(Some(extents))
+}
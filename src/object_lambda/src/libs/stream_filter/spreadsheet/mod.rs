@@ -0,0 +1,273 @@
+use std::fmt::Display;
+use std::io::Cursor;
+use std::sync::Arc;
+
+use anyhow::{anyhow, bail, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+use calamine::{open_workbook_auto_from_rs, Cell, Data, DataRef, Dimensions, Reader, Sheets};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::libs::phone;
+
+use super::{process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, StreamFilter, StreamItem};
+
+use self::extent::Extent;
+
+mod extent;
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SpreadsheetOutput {
+    /// the matching cell values, one per line
+    #[default]
+    Values,
+    /// the normalized matching numbers, one per line
+    Numbers,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SpreadsheetConfig {
+    /// names of the sheets to read, all of them if empty
+    #[serde(default)]
+    pub sheets: Vec<String>,
+    /// letters of the columns to read, e.g. `["B", "AA"]`, all of them if empty
+    #[serde(default)]
+    pub columns: Vec<String>,
+    #[serde(default)]
+    pub output: SpreadsheetOutput,
+    /// workbooks have to be held in memory, larger inputs are rejected
+    #[serde(default = "default_max_input_bytes")]
+    pub max_input_bytes: usize,
+    /// largest number of cells (rows * columns of the used range) read from a single sheet,
+    /// checked before the cells are held in memory
+    #[serde(default = "default_max_cells_per_sheet")]
+    pub max_cells_per_sheet: usize,
+}
+
+fn default_max_input_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_max_cells_per_sheet() -> usize {
+    10_000_000
+}
+
+impl SpreadsheetConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        for column in &self.columns {
+            column_index(column)?;
+        }
+        Ok(())
+    }
+}
+
+/// Zero-based index of a column given by its letters, e.g. `A` is 0 and `AA` is 26.
+fn column_index(letters: &str) -> anyhow::Result<u32> {
+    if letters.is_empty() || letters.len() > 3 {
+        bail!("invalid spreadsheet column `{letters}`");
+    }
+    letters
+        .chars()
+        .try_fold(0u32, |index, c| {
+            if !c.is_ascii_alphabetic() {
+                bail!("invalid spreadsheet column `{letters}`");
+            }
+            Ok(index * 26 + (c.to_ascii_uppercase() as u32 - 'A' as u32 + 1))
+        })
+        .map(|index| index - 1)
+}
+
+/// Pushes the cells of XLSX, XLS or ODS workbooks through the phone matcher.
+///
+/// The workbook formats are ZIP or OLE containers which can only be read
+/// once complete, so the input is buffered up to `max_input_bytes`.
+pub struct SpreadsheetStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<SpreadsheetConfig>,
+    columns: Arc<Vec<u32>>,
+}
+
+impl SpreadsheetStreamFilter {
+    pub fn new(regex: Regex, config: SpreadsheetConfig) -> Self {
+        let columns = config
+            .columns
+            .iter()
+            .filter_map(|column| column_index(column).ok())
+            .collect();
+        Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+            columns: Arc::new(columns),
+        }
+    }
+}
+
+impl StreamFilter for SpreadsheetStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            SpreadsheetProcessor {
+                regex: self.regex.clone(),
+                config: self.config.clone(),
+                columns: self.columns.clone(),
+                input: BytesMut::new(),
+            },
+        )
+    }
+}
+
+struct SpreadsheetProcessor {
+    regex: Arc<Regex>,
+    config: Arc<SpreadsheetConfig>,
+    columns: Arc<Vec<u32>>,
+    input: BytesMut,
+}
+
+impl SpreadsheetProcessor {
+    fn on_cell(&self, column: u32, value: &Data, output: &mut BytesMut) {
+        if !self.columns.is_empty() && !self.columns.contains(&column) {
+            return;
+        }
+        let value = match value {
+            Data::String(value) => value.clone(),
+            Data::Int(_) | Data::Float(_) => value.to_string(),
+            _ => return,
+        };
+        if !self.regex.is_match(&value) {
+            return;
+        }
+        let value = match self.config.output {
+            SpreadsheetOutput::Values => value,
+            SpreadsheetOutput::Numbers => match phone::normalize(&value) {
+                Some(number) => number,
+                None => return,
+            },
+        };
+        output.put_slice(value.trim().as_bytes());
+        output.put_u8(b'\n');
+    }
+
+    fn check_extent(&self, sheet: &str, extent: &Extent) -> anyhow::Result<()> {
+        if extent.cells() > self.config.max_cells_per_sheet as u64 {
+            bail!(
+                "sheet `{sheet}` exceeds the limit of {} cells",
+                self.config.max_cells_per_sheet
+            );
+        }
+        Ok(())
+    }
+
+    /// Reads the cells of a sheet one by one, checking the declared dimensions up front
+    /// and the extent of the cells seen so far, in case the declaration is missing or wrong.
+    fn read_cells<'a>(
+        &self,
+        sheet: &str,
+        dimensions: Dimensions,
+        mut next_cell: impl FnMut() -> anyhow::Result<Option<Cell<DataRef<'a>>>>,
+        output: &mut BytesMut,
+    ) -> anyhow::Result<()> {
+        let (start, end) = (dimensions.start, dimensions.end);
+        if start.0 > end.0 || start.1 > end.1 {
+            bail!("sheet `{sheet}` has malformed dimensions");
+        }
+        let mut extent = Extent::default();
+        extent.add(
+            (start.0 as u64, start.1 as u64),
+            (end.0 as u64, end.1 as u64),
+        );
+        self.check_extent(sheet, &extent)?;
+        let mut extent = Extent::default();
+        while let Some(cell) = next_cell()? {
+            if cell.get_value() == &DataRef::Empty {
+                continue;
+            }
+            let (row, column) = cell.get_position();
+            extent.add((row as u64, column as u64), (row as u64, column as u64));
+            self.check_extent(sheet, &extent)?;
+            self.on_cell(column, &Data::from(cell.get_value().clone()), output);
+        }
+        Ok(())
+    }
+}
+
+impl ChunkProcessor for SpreadsheetProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        if self.input.len() + chunk.len() > self.config.max_input_bytes {
+            bail!(
+                "spreadsheet exceeds the limit of {} bytes",
+                self.config.max_input_bytes
+            );
+        }
+        self.input.put(chunk);
+        Ok(Bytes::new())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let input = self.input.split().freeze();
+        // calamine holds every sheet of these formats as a dense range once opened,
+        // so all of them are checked, whichever are to be read
+        if let Some(extents) = extent::scan(&input).context("could not read spreadsheet")? {
+            for (index, extent) in extents.iter().enumerate() {
+                self.check_extent(&(index + 1).to_string(), extent)?;
+            }
+        }
+        let mut workbook =
+            open_workbook_auto_from_rs(Cursor::new(input)).context("could not open spreadsheet")?;
+        let sheets = if self.config.sheets.is_empty() {
+            workbook.sheet_names()
+        } else {
+            self.config.sheets.clone()
+        };
+        let mut output = BytesMut::new();
+        for sheet in sheets {
+            let read = |error: &dyn Display| anyhow!("could not read sheet `{sheet}`: {error}");
+            match &mut workbook {
+                // read cell by cell, without ever holding the whole sheet
+                Sheets::Xlsx(workbook) => {
+                    let mut cells = workbook
+                        .worksheet_cells_reader(&sheet)
+                        .map_err(|error| read(&error))?;
+                    let dimensions = cells.dimensions();
+                    self.read_cells(
+                        &sheet,
+                        dimensions,
+                        || cells.next_cell().map_err(|error| read(&error)),
+                        &mut output,
+                    )?;
+                }
+                Sheets::Xlsb(workbook) => {
+                    let mut cells = workbook
+                        .worksheet_cells_reader(&sheet)
+                        .map_err(|error| read(&error))?;
+                    let dimensions = cells.dimensions();
+                    self.read_cells(
+                        &sheet,
+                        dimensions,
+                        || cells.next_cell().map_err(|error| read(&error)),
+                        &mut output,
+                    )?;
+                }
+                // already in memory, within the limit checked above
+                workbook => {
+                    let range = workbook
+                        .worksheet_range(&sheet)
+                        .map_err(|error| read(&error))?;
+                    let first_column = range.start().map(|(_, column)| column).unwrap_or_default();
+                    for (_, column, value) in range.used_cells() {
+                        self.on_cell(first_column + column as u32, value, &mut output);
+                    }
+                }
+            }
+        }
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,299 @@
+use std::io::Write;
+
+use bytes::BufMut;
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+use zip::write::SimpleFileOptions;
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::*;
+
+async fn run(config: SpreadsheetConfig, input: Vec<u8>) -> anyhow::Result<String> {
+    let filter = SpreadsheetStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), config);
+    let chunks: Vec<StreamItem> = input
+        .chunks(100)
+        .map(|chunk| Ok(Bytes::copy_from_slice(chunk)))
+        .collect();
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(stream::iter(chunks)));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk?)?);
+    }
+    Ok(output)
+}
+
+fn config() -> SpreadsheetConfig {
+    SpreadsheetConfig {
+        sheets: vec![],
+        columns: vec![],
+        output: SpreadsheetOutput::Values,
+        max_input_bytes: default_max_input_bytes(),
+        max_cells_per_sheet: default_max_cells_per_sheet(),
+    }
+}
+
+fn zip(files: &[(&str, String)]) -> Vec<u8> {
+    let mut writer = zip::ZipWriter::new(Cursor::new(vec![]));
+    for (name, content) in files {
+        writer
+            .start_file(*name, SimpleFileOptions::default())
+            .unwrap();
+        writer.write_all(content.as_bytes()).unwrap();
+    }
+    writer.finish().unwrap().into_inner()
+}
+
+fn xlsx_sheet(rows: &[[&str; 2]]) -> String {
+    let rows: String = rows
+        .iter()
+        .enumerate()
+        .map(|(i, [a, b])| {
+            format!(
+                r#"<row r="{r}"><c r="A{r}" t="inlineStr"><is><t>{a}</t></is></c><c r="B{r}" t="inlineStr"><is><t>{b}</t></is></c></row>"#,
+                r = i + 1
+            )
+        })
+        .collect();
+    format!(
+        r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>{rows}</sheetData></worksheet>"#
+    )
+}
+
+fn xlsx() -> Vec<u8> {
+    xlsx_workbook(
+        xlsx_sheet(&[
+            ["Name", "Phone"],
+            ["Anna", "+36 1 234 5678"],
+            ["Béla", "n/a"],
+        ]),
+        xlsx_sheet(&[["Phone", "Notes"], ["0036 30 123 4567", "+36 20 987 6543"]]),
+    )
+}
+
+fn xlsx_workbook(sheet1: String, sheet2: String) -> Vec<u8> {
+    zip(&[
+        (
+            "[Content_Types].xml",
+            r#"<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/></Types>"#.to_string(),
+        ),
+        (
+            "xl/workbook.xml",
+            r#"<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="Budapest" sheetId="1" r:id="rId1"/><sheet name="Pest" sheetId="2" r:id="rId2"/></sheets></workbook>"#.to_string(),
+        ),
+        (
+            "xl/_rels/workbook.xml.rels",
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet2.xml"/></Relationships>"#.to_string(),
+        ),
+        ("xl/worksheets/sheet1.xml", sheet1),
+        ("xl/worksheets/sheet2.xml", sheet2),
+    ])
+}
+
+fn ods(table: &str) -> Vec<u8> {
+    zip(&[
+        (
+            "mimetype",
+            "application/vnd.oasis.opendocument.spreadsheet".to_string(),
+        ),
+        (
+            "META-INF/manifest.xml",
+            r#"<manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0"><manifest:file-entry manifest:full-path="content.xml" manifest:media-type="text/xml"/></manifest:manifest>"#.to_string(),
+        ),
+        (
+            "content.xml",
+            format!(
+                r#"<office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"><office:body><office:spreadsheet><table:table table:name="Contacts">{table}</table:table></office:spreadsheet></office:body></office:document-content>"#
+            ),
+        ),
+    ])
+}
+
+/// A compound file holding a BIFF8 workbook stream with a single sheet of the given records.
+fn xls(sheet: &[(u16, Vec<u8>)]) -> Vec<u8> {
+    let bof = |kind: u16| {
+        (
+            0x0809,
+            [&0x0600u16.to_le_bytes()[..], &kind.to_le_bytes(), &[0; 12]].concat(),
+        )
+    };
+    let mut records = vec![bof(0x0005), (0x000A, vec![]), bof(0x0010)];
+    records.extend_from_slice(sheet);
+    records.push((0x000A, vec![]));
+    let mut workbook = vec![];
+    for (typ, data) in records {
+        workbook.put_u16_le(typ);
+        workbook.put_u16_le(data.len() as u16);
+        workbook.put_slice(&data);
+    }
+    let mut file = cfb::CompoundFile::create(Cursor::new(vec![])).unwrap();
+    file.create_stream("Workbook")
+        .unwrap()
+        .write_all(&workbook)
+        .unwrap();
+    file.into_inner().into_inner()
+}
+
+fn xls_cell(typ: u16, row: u16, column: u16) -> (u16, Vec<u8>) {
+    (
+        typ,
+        [&row.to_le_bytes()[..], &column.to_le_bytes(), &[0; 10]].concat(),
+    )
+}
+
+#[tokio::test]
+async fn test_xlsx_all_sheets() {
+    // when
+    let output = run(config(), xlsx()).await;
+
+    // then
+    assert_eq!(
+        output.unwrap(),
+        "+36 1 234 5678\n0036 30 123 4567\n+36 20 987 6543\n"
+    );
+}
+
+#[tokio::test]
+async fn test_xlsx_selected_sheet_and_column() {
+    // given
+    let config = SpreadsheetConfig {
+        sheets: vec!["Pest".into()],
+        columns: vec!["a".into()],
+        output: SpreadsheetOutput::Numbers,
+        ..config()
+    };
+
+    // when
+    let output = run(config, xlsx()).await;
+
+    // then
+    assert_eq!(output.unwrap(), "+36301234567\n");
+}
+
+#[tokio::test]
+async fn test_ods() {
+    // given
+    let ods = ods(
+        r#"<table:table-row><table:table-cell office:value-type="string"><text:p>Anna</text:p></table:table-cell><table:table-cell office:value-type="string"><text:p>+36 1 234 5678</text:p></table:table-cell></table:table-row><table:table-row table:number-rows-repeated="1048575"><table:table-cell table:number-columns-repeated="1024"/></table:table-row>"#,
+    );
+
+    // when
+    let output = run(config(), ods).await;
+
+    // then
+    assert_eq!(output.unwrap(), "+36 1 234 5678\n");
+}
+
+#[tokio::test]
+async fn test_input_size_limit() {
+    // given
+    let config = SpreadsheetConfig {
+        max_input_bytes: 1000,
+        ..config()
+    };
+
+    // when
+    let output = run(config, xlsx()).await;
+
+    // then
+    assert!(output.is_err());
+}
+
+#[tokio::test]
+async fn test_cell_limit() {
+    // given
+    let config = SpreadsheetConfig {
+        max_cells_per_sheet: 4,
+        ..config()
+    };
+
+    // when
+    let output = run(config, xlsx()).await;
+
+    // then
+    assert!(output.is_err());
+}
+
+#[tokio::test]
+async fn test_sparse_xlsx_sheet_with_huge_extent() {
+    // given
+    // two cells in opposite corners of the largest possible sheet
+    let sheet = r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><dimension ref="A1:XFD1048576"/><sheetData><row r="1"><c r="A1" t="inlineStr"><is><t>+36 1 234 5678</t></is></c></row><row r="1048576"><c r="XFD1048576" t="inlineStr"><is><t>+36 20 987 6543</t></is></c></row></sheetData></worksheet>"#;
+    let workbook = xlsx_workbook(sheet.to_string(), xlsx_sheet(&[]));
+
+    // when
+    let output = run(config(), workbook).await;
+
+    // then
+    assert_eq!(
+        output.unwrap_err().to_string(),
+        "sheet `Budapest` exceeds the limit of 10000000 cells"
+    );
+}
+
+#[tokio::test]
+async fn test_ods_sheet_with_huge_extent() {
+    // given
+    // expanded by calamine into a billion cells
+    let ods = ods(
+        r#"<table:table-row table:number-rows-repeated="1048576"><table:table-cell office:value-type="string" table:number-columns-repeated="1024"><text:p>+36 1 234 5678</text:p></table:table-cell></table:table-row>"#,
+    );
+
+    // when
+    let output = run(config(), ods).await;
+
+    // then
+    assert_eq!(
+        output.unwrap_err().to_string(),
+        "sheet `1` exceeds the limit of 10000000 cells"
+    );
+}
+
+#[tokio::test]
+async fn test_xls_sheet_with_huge_dimensions() {
+    // given
+    // calamine reserves the declared dimensions up front
+    let dimensions = [
+        &0u32.to_le_bytes()[..],
+        &1_000_000u32.to_le_bytes(),
+        &0u16.to_le_bytes(),
+        &256u16.to_le_bytes(),
+        &[0; 2],
+    ]
+    .concat();
+    let workbook = xls(&[(0x0200, dimensions), xls_cell(0x0203, 0, 0)]);
+
+    // when
+    let output = run(config(), workbook).await;
+
+    // then
+    assert_eq!(
+        output.unwrap_err().to_string(),
+        "sheet `1` exceeds the limit of 10000000 cells"
+    );
+}
+
+#[test]
+fn test_xls_extent() {
+    // given
+    let mul_rk = (0x00BD, [&[5, 0, 2, 0][..], &[0; 12], &[4, 0]].concat());
+    let workbook = xls(&[xls_cell(0x0203, 1, 1), xls_cell(0x00FD, 9, 3), mul_rk]);
+
+    // when
+    let extents = extent::scan(&workbook).unwrap().unwrap();
+
+    // then
+    // rows 1 to 9 and columns 1 to 4
+    assert_eq!(extents.iter().map(|e| e.cells()).collect::<Vec<_>>(), [36]);
+}
+
+#[test]
+fn test_column_index() {
+    assert_eq!(column_index("A").unwrap(), 0);
+    assert_eq!(column_index("z").unwrap(), 25);
+    assert_eq!(column_index("AA").unwrap(), 26);
+    assert_eq!(column_index("XFD").unwrap(), 16383);
+    assert!(column_index("A1").is_err());
+    assert!(column_index("").is_err());
+}
@@ -0,0 +1,326 @@
+use std::str::from_utf8;
+use std::sync::{Arc, PoisonError, RwLock};
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, bail, Context};
+use bytes::{BufMut, Bytes, BytesMut};
+use futures::stream;
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::libs::deps::s3;
+use crate::libs::phone;
+
+use super::csv::{read_fields, CsvColumn};
+use super::jsonl::JsonPath;
+use super::{
+    number_key, process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter,
+    StreamItem,
+};
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SuppressionMode {
+    /// drop the listed numbers
+    #[default]
+    Exclude,
+    /// keep only the listed numbers
+    Only,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct SuppressionConfig {
+    pub bucket: String,
+    /// a text object with one internationally formatted number per line
+    pub key: String,
+    /// reload the list once it is older than this, otherwise it is only loaded at cold start
+    #[serde(default)]
+    pub ttl_seconds: Option<u64>,
+    #[serde(default)]
+    pub mode: SuppressionMode,
+}
+
+impl SuppressionConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.bucket.is_empty() || self.key.is_empty() {
+            bail!("suppression list needs a bucket and a key");
+        }
+        Ok(())
+    }
+}
+
+/// Where the number of an output line is, given the input format it was decoded from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum NumberField {
+    /// the line holds just a number
+    #[default]
+    Line,
+    /// a field of a CSV row; the header row, which names the columns, is always kept
+    Csv {
+        column: CsvColumn,
+        delimiter: u8,
+        has_header: bool,
+    },
+    /// a JSON record, listed if any of the numbers at these paths is
+    Json(Vec<JsonPath>),
+}
+
+/// E.164 numbers as integers, e.g. `+3612345678` as 3612345678, sorted for binary search.
+struct Snapshot {
+    numbers: Vec<u64>,
+    loaded_at: Instant,
+}
+
+impl Snapshot {
+    fn contains(&self, number: &str) -> bool {
+        number_value(number).is_some_and(|value| self.numbers.binary_search(&value).is_ok())
+    }
+}
+
+fn number_value(number: &str) -> Option<u64> {
+    number.strip_prefix('+')?.parse().ok()
+}
+
+/// A do-not-call list held in memory, shared by the invocations of a Lambda instance.
+///
+/// Every invocation filters against the snapshot it started with, so that a reload
+/// never changes the list under a running invocation.
+pub struct SuppressionList {
+    config: SuppressionConfig,
+    snapshot: RwLock<Option<Arc<Snapshot>>>,
+}
+
+impl SuppressionList {
+    pub fn new(config: SuppressionConfig) -> Self {
+        Self {
+            config,
+            snapshot: RwLock::new(None),
+        }
+    }
+
+    /// Loads the list if it was not loaded yet or its TTL has passed.
+    /// A failed reload keeps the previous list.
+    pub async fn refresh(&self, s3: &s3::S3) -> anyhow::Result<()> {
+        let stale = match self.current() {
+            None => true,
+            Some(snapshot) => self
+                .config
+                .ttl_seconds
+                .is_some_and(|ttl| snapshot.loaded_at.elapsed() >= Duration::from_secs(ttl)),
+        };
+        if !stale {
+            return Ok(());
+        }
+        match self.load(s3).await {
+            Ok(snapshot) => {
+                *self
+                    .snapshot
+                    .write()
+                    .unwrap_or_else(PoisonError::into_inner) = Some(Arc::new(snapshot));
+                Ok(())
+            }
+            Err(error) if self.current().is_some() => {
+                tracing::warn!("keeping the previous suppression list: {error:#}");
+                Ok(())
+            }
+            Err(error) => Err(error),
+        }
+    }
+
+    fn current(&self) -> Option<Arc<Snapshot>> {
+        self.snapshot
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    async fn load(&self, s3: &s3::S3) -> anyhow::Result<Snapshot> {
+        let SuppressionConfig { bucket, key, .. } = &self.config;
+        let mut body = s3
+            .get_object(bucket, key)
+            .await
+            .with_context(|| format!("could not fetch suppression list s3://{bucket}/{key}"))?
+            .body;
+        let mut lines = LineBuffer::default();
+        let mut numbers = vec![];
+        let mut invalid = 0;
+        let mut on_line = |line: &[u8]| {
+            let line = from_utf8(line).unwrap_or_default().trim();
+            if line.is_empty() || line.starts_with('#') {
+                return Ok(());
+            }
+            match phone::normalize(line).as_deref().and_then(number_value) {
+                Some(value) => numbers.push(value),
+                None => invalid += 1,
+            }
+            Ok(())
+        };
+        while let Some(chunk) = body.try_next().await? {
+            lines.push(&chunk, &mut on_line)?;
+        }
+        lines.finish(&mut on_line)?;
+        numbers.sort_unstable();
+        numbers.dedup();
+        numbers.shrink_to_fit();
+        tracing::info!(
+            "loaded {} numbers from suppression list s3://{bucket}/{key}, skipped {invalid} invalid lines",
+            numbers.len()
+        );
+        Ok(Snapshot {
+            numbers,
+            loaded_at: Instant::now(),
+        })
+    }
+}
+
+/// Drops the output lines whose number is on the suppression list, or keeps only those.
+/// The number is read from the `field` of a line; lines without a number are never on the list.
+pub struct SuppressionStreamFilter {
+    regex: Arc<Regex>,
+    list: Arc<SuppressionList>,
+    field: Arc<NumberField>,
+}
+
+impl SuppressionStreamFilter {
+    pub fn new(regex: Regex, list: Arc<SuppressionList>, field: NumberField) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            list,
+            field: Arc::new(field),
+        }
+    }
+}
+
+impl StreamFilter for SuppressionStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        let Some(snapshot) = self.list.current() else {
+            return Box::new(stream::iter([Err(anyhow!(
+                "suppression list is not loaded"
+            ))]));
+        };
+        process_stream(
+            s,
+            SuppressionProcessor {
+                lines: match self.field.as_ref() {
+                    NumberField::Csv { .. } => LineBuffer::csv(),
+                    _ => LineBuffer::default(),
+                },
+                suppression: Suppression {
+                    regex: self.regex.clone(),
+                    snapshot,
+                    mode: self.list.config.mode,
+                    column: match self.field.as_ref() {
+                        NumberField::Csv {
+                            column: CsvColumn::Index(index),
+                            ..
+                        } => *index,
+                        // resolved once the header is read
+                        _ => 0,
+                    },
+                    header_pending: matches!(
+                        self.field.as_ref(),
+                        NumberField::Csv {
+                            has_header: true,
+                            ..
+                        }
+                    ),
+                    field: self.field.clone(),
+                },
+            },
+        )
+    }
+}
+
+struct SuppressionProcessor {
+    lines: LineBuffer,
+    suppression: Suppression,
+}
+
+struct Suppression {
+    regex: Arc<Regex>,
+    snapshot: Arc<Snapshot>,
+    mode: SuppressionMode,
+    field: Arc<NumberField>,
+    /// the CSV column holding the number
+    column: usize,
+    header_pending: bool,
+}
+
+impl Suppression {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        let keep = if self.header_pending {
+            self.on_header(line)?;
+            true
+        } else {
+            let listed = self.is_listed(line);
+            match self.mode {
+                SuppressionMode::Exclude => !listed,
+                SuppressionMode::Only => listed,
+            }
+        };
+        if keep {
+            output.put_slice(line);
+            output.put_u8(b'\n');
+        }
+        Ok(())
+    }
+
+    fn on_header(&mut self, line: &[u8]) -> anyhow::Result<()> {
+        self.header_pending = false;
+        if let NumberField::Csv {
+            column: CsvColumn::Name(name),
+            delimiter,
+            ..
+        } = self.field.as_ref()
+        {
+            self.column = read_fields(line, *delimiter)
+                .iter()
+                .position(|field| field.trim_ascii() == name.as_bytes())
+                .ok_or_else(|| anyhow!("column `{name}` not found in CSV header"))?;
+        }
+        Ok(())
+    }
+
+    fn is_listed(&self, line: &[u8]) -> bool {
+        let listed = |value: &str| self.snapshot.contains(&number_key(&self.regex, value));
+        match self.field.as_ref() {
+            NumberField::Line => from_utf8(line).is_ok_and(listed),
+            NumberField::Csv { delimiter, .. } => read_fields(line, *delimiter)
+                .get(self.column)
+                .and_then(|field| from_utf8(field).ok())
+                .is_some_and(listed),
+            NumberField::Json(paths) => serde_json::from_slice::<Value>(line).is_ok_and(|record| {
+                paths
+                    .iter()
+                    .flat_map(|path| path.select(&record))
+                    .filter_map(Value::as_str)
+                    .any(listed)
+            }),
+        }
+    }
+}
+
+impl ChunkProcessor for SuppressionProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .push(&chunk, |line| self.suppression.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines
+            .finish(|line| self.suppression.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
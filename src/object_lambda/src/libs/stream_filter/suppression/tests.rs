@@ -0,0 +1,216 @@
+#![allow(clippy::result_large_err)]
+
+use std::str::from_utf8;
+
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::primitives::ByteStream;
+use futures::StreamExt;
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::*;
+
+fn config(mode: SuppressionMode, ttl_seconds: Option<u64>) -> SuppressionConfig {
+    SuppressionConfig {
+        bucket: "bucket".into(),
+        key: "dnc.txt".into(),
+        ttl_seconds,
+        mode,
+    }
+}
+
+fn mock_s3(list: &'static str) -> s3::S3 {
+    let mut s3 = s3::S3::faux();
+    faux::when!(s3.get_object("bucket", "dnc.txt")).then(|_| {
+        Ok(GetObjectOutput::builder()
+            .body(ByteStream::from_static(list.as_bytes()))
+            .build())
+    });
+    s3
+}
+
+async fn run(list: Arc<SuppressionList>, input: &'static str) -> String {
+    run_with_field(list, NumberField::Line, input).await
+}
+
+async fn run_with_field(
+    list: Arc<SuppressionList>,
+    field: NumberField,
+    input: &'static str,
+) -> String {
+    let filter = SuppressionStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), list, field);
+    let input = stream::iter([Ok(Bytes::from_static(input.as_bytes()))]);
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk.unwrap()).unwrap());
+    }
+    output
+}
+
+const LIST: &str = "# do not call\n+36 1 234 5678\r\n0036301234567\nnot a number\n";
+
+const INPUT: &str = "0036 1 234 56 78\n+36 20 987 6543\nphone\n+36 30 123 4567";
+
+#[tokio::test]
+async fn test_exclude() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Exclude, None)));
+    list.refresh(&mock_s3(LIST)).await.unwrap();
+
+    // when
+    let output = run(list, INPUT).await;
+
+    // then
+    assert_eq!(output, "+36 20 987 6543\nphone\n");
+}
+
+#[tokio::test]
+async fn test_only() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Only, None)));
+    list.refresh(&mock_s3(LIST)).await.unwrap();
+
+    // when
+    let output = run(list, INPUT).await;
+
+    // then
+    assert_eq!(output, "0036 1 234 56 78\n+36 30 123 4567\n");
+}
+
+#[tokio::test]
+async fn test_csv_rows() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Exclude, None)));
+    list.refresh(&mock_s3(LIST)).await.unwrap();
+    let field = NumberField::Csv {
+        column: CsvColumn::Name("phone".into()),
+        delimiter: b';',
+        has_header: true,
+    };
+
+    // when
+    let output = run_with_field(
+        list,
+        field,
+        "name;phone\nAnna;0036 1 234 56 78\n\"Béla; Jr\";+36 20 987 6543\nCecil;\"+36 30 123 4567\"\n",
+    )
+    .await;
+
+    // then
+    assert_eq!(output, "name;phone\n\"Béla; Jr\";+36 20 987 6543\n");
+}
+
+#[tokio::test]
+async fn test_csv_rows_with_multi_line_fields() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Exclude, None)));
+    list.refresh(&mock_s3(LIST)).await.unwrap();
+    let field = NumberField::Csv {
+        column: CsvColumn::Name("phone".into()),
+        delimiter: b';',
+        has_header: true,
+    };
+
+    // when
+    let output = run_with_field(
+        list,
+        field,
+        "name;phone\n\"Anna\nthe \"\"first\"\"\";0036 1 234 56 78\n\"Béla\r\nJr\";+36 20 987 6543\n",
+    )
+    .await;
+
+    // then
+    assert_eq!(output, "name;phone\n\"Béla\r\nJr\";+36 20 987 6543\n");
+}
+
+#[tokio::test]
+async fn test_csv_rows_only_keep_header() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Only, None)));
+    list.refresh(&mock_s3(LIST)).await.unwrap();
+    let field = NumberField::Csv {
+        column: CsvColumn::Index(1),
+        delimiter: b',',
+        has_header: true,
+    };
+
+    // when
+    let output = run_with_field(
+        list,
+        field,
+        "name,phone\nAnna,+36 1 234 5678\nBéla,+36 20 987 6543\n",
+    )
+    .await;
+
+    // then
+    assert_eq!(output, "name,phone\nAnna,+36 1 234 5678\n");
+}
+
+#[tokio::test]
+async fn test_jsonl_records() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Exclude, None)));
+    list.refresh(&mock_s3(LIST)).await.unwrap();
+    let field = NumberField::Json(vec![JsonPath::parse("phones[*]").unwrap()]);
+
+    // when
+    let output = run_with_field(
+        list,
+        field,
+        "{\"name\": \"Anna\", \"phones\": [\"+36 20 987 6543\", \"0036 1 234 5678\"]}\n\
+         {\"name\": \"Béla\", \"phones\": [\"+36 20 987 6543\"]}\n",
+    )
+    .await;
+
+    // then
+    assert_eq!(
+        output,
+        "{\"name\": \"Béla\", \"phones\": [\"+36 20 987 6543\"]}\n"
+    );
+}
+
+#[tokio::test]
+async fn test_not_loaded() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Exclude, None)));
+    let filter =
+        SuppressionStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), list, NumberField::Line);
+
+    // when
+    let mut stream = filter.filter_stream(Box::new(stream::empty()));
+
+    // then
+    assert!(stream.next().await.unwrap().is_err());
+}
+
+#[tokio::test]
+async fn test_reload_after_ttl() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Only, Some(0))));
+    list.refresh(&mock_s3("+36 1 234 5678\n")).await.unwrap();
+    let before = list.current().unwrap();
+
+    // when
+    list.refresh(&mock_s3("+36 20 987 6543\n")).await.unwrap();
+
+    // then
+    assert_eq!(run(list, INPUT).await, "+36 20 987 6543\n");
+    // a running invocation keeps its snapshot
+    assert!(before.contains("+3612345678"));
+}
+
+#[tokio::test]
+async fn test_no_reload_before_ttl() {
+    // given
+    let list = Arc::new(SuppressionList::new(config(SuppressionMode::Only, None)));
+    list.refresh(&mock_s3("+36 1 234 5678\n")).await.unwrap();
+
+    // when
+    let mut s3 = s3::S3::faux();
+    faux::when!(s3.get_object).then(|_| panic!("reloaded"));
+    let refreshed = list.refresh(&s3).await;
+
+    // then
+    assert!(refreshed.is_ok());
+}
@@ -0,0 +1,248 @@
+use std::mem;
+use std::sync::Arc;
+
+use anyhow::bail;
+use bytes::{Bytes, BytesMut};
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::libs::phone;
+
+use super::csv::write_record;
+use super::{
+    process_stream, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter, StreamItem,
+};
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct VcardConfig {
+    /// append the TEL TYPE parameters, e.g. `cell;voice`
+    #[serde(default)]
+    pub include_type: bool,
+    /// append the contact's formatted name (FN)
+    #[serde(default)]
+    pub include_name: bool,
+    /// longer properties once unfolded are skipped, such as embedded photos
+    #[serde(default = "default_max_property_bytes")]
+    pub max_property_bytes: usize,
+}
+
+fn default_max_property_bytes() -> usize {
+    64 * 1024
+}
+
+impl Default for VcardConfig {
+    fn default() -> Self {
+        Self {
+            include_type: false,
+            include_name: false,
+            max_property_bytes: default_max_property_bytes(),
+        }
+    }
+}
+
+impl VcardConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.max_property_bytes == 0 {
+            bail!("vCard maxPropertyBytes must be positive");
+        }
+        Ok(())
+    }
+}
+
+pub struct VcardStreamFilter {
+    regex: Arc<Regex>,
+    config: Arc<VcardConfig>,
+}
+
+impl VcardStreamFilter {
+    pub fn new(regex: Regex, config: VcardConfig) -> Self {
+        Self {
+            regex: Arc::new(regex),
+            config: Arc::new(config),
+        }
+    }
+}
+
+impl StreamFilter for VcardStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream(
+            s,
+            VcardProcessor {
+                lines: LineBuffer::default(),
+                contacts: Contacts {
+                    regex: self.regex.clone(),
+                    config: self.config.clone(),
+                    unfolded: String::new(),
+                    oversized: false,
+                    name: None,
+                    tels: vec![],
+                },
+            },
+        )
+    }
+}
+
+struct Tel {
+    value: String,
+    types: Vec<String>,
+}
+
+struct VcardProcessor {
+    lines: LineBuffer,
+    contacts: Contacts,
+}
+
+/// Collects the properties of the current contact from the unfolded content lines.
+struct Contacts {
+    regex: Arc<Regex>,
+    config: Arc<VcardConfig>,
+    unfolded: String,
+    /// whether the current property exceeds `max_property_bytes`, so that it is skipped
+    oversized: bool,
+    name: Option<String>,
+    tels: Vec<Tel>,
+}
+
+impl Contacts {
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) {
+        let line = String::from_utf8_lossy(line);
+        // a line starting with a space or tab continues the previous one (RFC 6350 3.2)
+        if let Some(continuation) = line.strip_prefix([' ', '\t']) {
+            if !self.oversized {
+                self.unfolded.push_str(continuation);
+                self.check_size();
+            }
+            return;
+        }
+        let previous = mem::replace(&mut self.unfolded, line.into_owned());
+        let skipped = mem::replace(&mut self.oversized, false);
+        self.check_size();
+        if !skipped {
+            self.on_property(&previous, output);
+        }
+    }
+
+    /// Drops the current property once it exceeds `max_property_bytes`, rather than holding it.
+    fn check_size(&mut self) {
+        if self.unfolded.len() > self.config.max_property_bytes {
+            self.oversized = true;
+            self.unfolded = String::new();
+        }
+    }
+
+    fn finish(&mut self, output: &mut BytesMut) {
+        let last = mem::take(&mut self.unfolded);
+        if !self.oversized {
+            self.on_property(&last, output);
+        }
+        // a truncated file may lack the final END:VCARD
+        self.write_contact(output);
+    }
+
+    fn on_property(&mut self, property: &str, output: &mut BytesMut) {
+        let Some((key, value)) = property.split_once(':') else {
+            return;
+        };
+        let mut params = key.split(';');
+        let name = params.next().unwrap_or_default();
+        // drop the optional group prefix, e.g. `item1.TEL`
+        let name = name.rsplit('.').next().unwrap_or_default();
+        if name.eq_ignore_ascii_case("BEGIN") {
+            self.name = None;
+            self.tels.clear();
+        } else if name.eq_ignore_ascii_case("END") {
+            self.write_contact(output);
+        } else if name.eq_ignore_ascii_case("FN") {
+            self.name = Some(unescape(value));
+        } else if name.eq_ignore_ascii_case("TEL") {
+            let value = value.strip_prefix("tel:").unwrap_or(value);
+            self.tels.push(Tel {
+                value: value.to_string(),
+                types: params.flat_map(tel_types).collect(),
+            });
+        }
+    }
+
+    fn write_contact(&mut self, output: &mut BytesMut) {
+        let name = self.name.take().unwrap_or_default();
+        for tel in mem::take(&mut self.tels) {
+            // RFC 3966 visual separators are not accepted by the phone matcher
+            let value = tel.value.replace(['-', '.', '(', ')'], " ");
+            if !self.regex.is_match(&value) {
+                continue;
+            }
+            let Some(number) = phone::normalize(&value) else {
+                continue;
+            };
+            let types = tel.types.join(";");
+            let mut fields = vec![number.as_bytes()];
+            if self.config.include_type {
+                fields.push(types.as_bytes());
+            }
+            if self.config.include_name {
+                fields.push(name.as_bytes());
+            }
+            write_record(output, &fields, b',');
+        }
+    }
+}
+
+/// The TYPE values of a TEL parameter: `TYPE=cell,voice`, `TYPE="cell,voice"` or vCard 2.1's bare `CELL`.
+fn tel_types(param: &str) -> Vec<String> {
+    let values = match param.split_once('=') {
+        Some((name, values)) if name.eq_ignore_ascii_case("TYPE") => values.trim_matches('"'),
+        Some(_) => return vec![],
+        None => param,
+    };
+    values
+        .split(',')
+        .filter(|value| !value.is_empty())
+        .map(str::to_ascii_lowercase)
+        .collect()
+}
+
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n' | 'N') => unescaped.push('\n'),
+            Some(escaped) => unescaped.push(escaped),
+            None => unescaped.push('\\'),
+        }
+    }
+    unescaped
+}
+
+impl ChunkProcessor for VcardProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.push(&chunk, |line| {
+            self.contacts.on_line(line, &mut output);
+            Ok(())
+        })?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        self.lines.finish(|line| {
+            self.contacts.on_line(line, &mut output);
+            Ok(())
+        })?;
+        self.contacts.finish(&mut output);
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
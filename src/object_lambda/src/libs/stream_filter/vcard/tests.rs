@@ -0,0 +1,86 @@
+use std::str::from_utf8;
+
+use futures::{stream, StreamExt};
+
+use crate::libs::phone::HU_PATTERN;
+
+use super::*;
+
+async fn run(config: VcardConfig, chunks: &[&'static str]) -> String {
+    let filter = VcardStreamFilter::new(Regex::new(HU_PATTERN).unwrap(), config);
+    let input = stream::iter(
+        chunks
+            .iter()
+            .map(|&chunk| Ok(Bytes::from_static(chunk.as_bytes())))
+            .collect::<Vec<StreamItem>>(),
+    );
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk.unwrap()).unwrap());
+    }
+    output
+}
+
+const INPUT: [&str; 2] = [
+    "BEGIN:VCARD\r\nVERSION:3.0\r\nTEL;TYPE=CELL:+36 30 12\r\n 3 4567\r\nFN:Kovács\\, Anna\r\nTEL;TYPE=\"work,voice\":+49 30 1234567\r\nEND:VC",
+    "ARD\r\nBEGIN:VCARD\r\nVERSION:2.1\r\nN:Nagy;Béla\r\nitem1.TEL;HOME:tel:+36-1-234-5678\r\nEND:VCARD\r\n",
+];
+
+#[tokio::test]
+async fn test_numbers_only() {
+    // when
+    let output = run(VcardConfig::default(), &INPUT).await;
+
+    // then
+    assert_eq!(output, "+36301234567\n+3612345678\n");
+}
+
+#[tokio::test]
+async fn test_with_type_and_name() {
+    // given
+    let config = VcardConfig {
+        include_type: true,
+        include_name: true,
+        ..Default::default()
+    };
+
+    // when
+    let output = run(config, &INPUT).await;
+
+    // then
+    assert_eq!(
+        output,
+        "+36301234567,cell,\"Kovács, Anna\"\n+3612345678,home,\n"
+    );
+}
+
+#[tokio::test]
+async fn test_truncated_card() {
+    // when
+    let output = run(VcardConfig::default(), &["BEGIN:VCARD\nTEL:+36 1 234 5678"]).await;
+
+    // then
+    assert_eq!(output, "+3612345678\n");
+}
+
+#[tokio::test]
+async fn test_long_property_is_skipped() {
+    // given
+    let config = VcardConfig {
+        include_name: true,
+        max_property_bytes: 32,
+        ..Default::default()
+    };
+    let input = [
+        "BEGIN:VCARD\nFN:Kovács\n  Anna\nPHOTO;ENCODING=b:",
+        "AAAAAAAAAAAAAAAAAAAA\n AAAAAAAAAAAAAAAAAAAA\n AAAAAAAAAAAAAAAAAAAA\nTEL:+36 1 234 5678\n",
+        "FN:Nagy Béla with a name far too long to keep\nEND:VCARD\n",
+    ];
+
+    // when
+    let output = run(config, &input).await;
+
+    // then
+    assert_eq!(output, "+3612345678,Kovács Anna\n");
+}
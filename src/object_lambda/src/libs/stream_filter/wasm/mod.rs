@@ -0,0 +1,277 @@
+use std::fs;
+use std::sync::Arc;
+
+use anyhow::{bail, Context};
+use bytes::{Bytes, BytesMut};
+use serde::Deserialize;
+use wasmtime::{
+    Config as EngineConfig, Engine, InstancePre, Linker, Memory, Module, Store, StoreLimits,
+    StoreLimitsBuilder, TypedFunc,
+};
+
+use crate::libs::deps::s3;
+
+use super::line::{write_line, LineAction};
+use super::{
+    process_stream_blocking, BoxedSendSyncUnpinStream, ChunkProcessor, LineBuffer, StreamFilter,
+    StreamItem,
+};
+
+/// Returned by `filter_line` to drop the line.
+const DROP: i64 = -1;
+/// Returned by `filter_line` to keep the line.
+const KEEP: i64 = -2;
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct WasmConfig {
+    /// module bundled with the function, e.g. `/var/task/plugin.wasm`
+    #[serde(default)]
+    pub path: Option<String>,
+    /// bucket of the module in S3, instead of a bundled file
+    #[serde(default)]
+    pub bucket: Option<String>,
+    #[serde(default)]
+    pub key: Option<String>,
+    /// roughly the number of instructions the plugin may run per invocation
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    /// the part of `fuel` a single line may take, so that one line cannot stall the stream
+    #[serde(default = "default_fuel_per_line")]
+    pub fuel_per_line: u64,
+    /// the memory the plugin may grow to per invocation
+    #[serde(default = "default_max_memory_bytes")]
+    pub max_memory_bytes: usize,
+    /// longer replacements fail the invocation
+    #[serde(default = "default_max_line_bytes")]
+    pub max_line_bytes: usize,
+}
+
+fn default_fuel() -> u64 {
+    10_000_000_000
+}
+
+fn default_fuel_per_line() -> u64 {
+    100_000_000
+}
+
+fn default_max_memory_bytes() -> usize {
+    64 * 1024 * 1024
+}
+
+fn default_max_line_bytes() -> usize {
+    1024 * 1024
+}
+
+impl WasmConfig {
+    pub fn validate(&self) -> anyhow::Result<()> {
+        match (&self.path, &self.bucket, &self.key) {
+            (Some(_), None, None) | (None, Some(_), Some(_)) => {}
+            _ => bail!("wasm plugin comes either from path or from bucket and key"),
+        }
+        if self.fuel == 0 || self.fuel_per_line == 0 || self.max_memory_bytes == 0 {
+            bail!("wasm plugin needs some fuel and memory");
+        }
+        if self.max_line_bytes == 0 {
+            bail!("wasm plugin maxLineBytes must be positive");
+        }
+        Ok(())
+    }
+}
+
+/// Runs a WebAssembly line filter plugin on every line, sandboxed under wasmtime: the plugin
+/// cannot import anything, and gets a fresh instance with its own fuel and memory limits for
+/// every invocation. Every line may take up to `fuelPerLine` of it, and the plugin runs on the
+/// blocking threads of the runtime.
+///
+/// The plugin exports its `memory`, `alloc(len: i32) -> i32` returning a buffer for the host to
+/// write a line of `len` bytes to, and `filter_line(ptr: i32, len: i32) -> i64` returning -1 to
+/// drop the line, -2 to keep it, or `ptr << 32 | len` of a replacement in its memory.
+pub struct WasmStreamFilter {
+    instance: InstancePre<StoreLimits>,
+    config: Arc<WasmConfig>,
+}
+
+impl WasmStreamFilter {
+    /// Reads and compiles the module, checking that it implements the ABI.
+    pub async fn load(config: WasmConfig, s3: &s3::S3) -> anyhow::Result<Self> {
+        let (bytes, source) = match (&config.path, &config.bucket, &config.key) {
+            (Some(path), _, _) => (
+                fs::read(path).with_context(|| format!("could not read wasm plugin {path}"))?,
+                path.clone(),
+            ),
+            (None, Some(bucket), Some(key)) => {
+                let source = format!("s3://{bucket}/{key}");
+                let body = s3
+                    .get_object(bucket, key)
+                    .await
+                    .with_context(|| format!("could not fetch wasm plugin {source}"))?
+                    .body
+                    .collect()
+                    .await
+                    .with_context(|| format!("could not read wasm plugin {source}"))?;
+                (body.to_vec(), source)
+            }
+            _ => bail!("wasm plugin has no source"),
+        };
+        let mut engine_config = EngineConfig::new();
+        engine_config.consume_fuel(true);
+        let engine = Engine::new(&engine_config)?;
+        let module = Module::new(&engine, bytes)
+            .with_context(|| format!("{source} is not a valid wasm module"))?;
+        let instance = Linker::new(&engine)
+            .instantiate_pre(&module)
+            .with_context(|| format!("wasm plugin {source} must not import anything"))?;
+        let filter = Self {
+            instance,
+            config: Arc::new(config),
+        };
+        Plugin::new(&filter.instance, &filter.config).with_context(|| {
+            format!("wasm plugin {source} does not implement the line filter ABI")
+        })?;
+        tracing::info!("loaded wasm plugin {source}");
+        Ok(filter)
+    }
+}
+
+impl StreamFilter for WasmStreamFilter {
+    type Item = StreamItem;
+    fn filter_stream(
+        &self,
+        s: BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item>,
+    ) -> BoxedSendSyncUnpinStream<<Self as StreamFilter>::Item> {
+        process_stream_blocking(
+            s,
+            WasmProcessor {
+                lines: LineBuffer::default(),
+                instance: self.instance.clone(),
+                config: self.config.clone(),
+                plugin: None,
+            },
+        )
+    }
+}
+
+/// An instance of the plugin for a single invocation.
+struct Plugin {
+    store: Store<StoreLimits>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    filter_line: TypedFunc<(i32, i32), i64>,
+    fuel_per_line: u64,
+    max_line_bytes: usize,
+    line_number: u64,
+}
+
+impl Plugin {
+    fn new(instance: &InstancePre<StoreLimits>, config: &WasmConfig) -> anyhow::Result<Self> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(config.max_memory_bytes)
+            .build();
+        let mut store = Store::new(instance.module().engine(), limits);
+        store.limiter(|limits| limits);
+        store.set_fuel(config.fuel)?;
+        let instance = instance.instantiate(&mut store)?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .context("no `memory` export")?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let filter_line = instance.get_typed_func(&mut store, "filter_line")?;
+        Ok(Self {
+            store,
+            memory,
+            alloc,
+            filter_line,
+            fuel_per_line: config.fuel_per_line,
+            max_line_bytes: config.max_line_bytes,
+            line_number: 0,
+        })
+    }
+
+    fn on_line(&mut self, line: &[u8], output: &mut BytesMut) -> anyhow::Result<()> {
+        self.line_number += 1;
+        let action = self
+            .filter_line(line)
+            .with_context(|| format!("wasm plugin failed on line {}", self.line_number))?;
+        write_line(line, action, output);
+        Ok(())
+    }
+
+    /// Runs the plugin on `line` with at most `fuel_per_line` of the fuel left.
+    fn filter_line(&mut self, line: &[u8]) -> anyhow::Result<LineAction> {
+        let fuel = self.store.get_fuel()?;
+        let budget = fuel.min(self.fuel_per_line);
+        self.store.set_fuel(budget)?;
+        let action = self.call(line);
+        let used = budget - self.store.get_fuel()?;
+        self.store.set_fuel(fuel - used)?;
+        action
+    }
+
+    fn call(&mut self, line: &[u8]) -> anyhow::Result<LineAction> {
+        let len = i32::try_from(line.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, line)?;
+        Ok(match self.filter_line.call(&mut self.store, (ptr, len))? {
+            DROP => LineAction::Drop,
+            KEEP => LineAction::Keep,
+            result if result >= 0 => {
+                let (ptr, len) = ((result >> 32) as usize, (result & 0xffff_ffff) as usize);
+                if len > self.max_line_bytes {
+                    bail!(
+                        "replacement of {len} bytes exceeds maxLineBytes of {}",
+                        self.max_line_bytes
+                    );
+                }
+                if ptr + len > self.memory.data_size(&self.store) {
+                    bail!("replacement at {ptr} of {len} bytes is out of the plugin memory");
+                }
+                let mut replacement = vec![0; len];
+                self.memory.read(&self.store, ptr, &mut replacement)?;
+                LineAction::Replace(replacement)
+            }
+            result => bail!("invalid result {result}"),
+        })
+    }
+}
+
+struct WasmProcessor {
+    lines: LineBuffer,
+    instance: InstancePre<StoreLimits>,
+    config: Arc<WasmConfig>,
+    /// instantiated on the first chunk, so that a failure fails the stream
+    plugin: Option<Plugin>,
+}
+
+impl WasmProcessor {
+    fn plugin(&mut self) -> anyhow::Result<Plugin> {
+        match self.plugin.take() {
+            Some(plugin) => Ok(plugin),
+            None => Plugin::new(&self.instance, &self.config),
+        }
+    }
+}
+
+impl ChunkProcessor for WasmProcessor {
+    fn process(&mut self, chunk: Bytes) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        let plugin = self.plugin()?;
+        let plugin = self.plugin.insert(plugin);
+        self.lines
+            .push(&chunk, |line| plugin.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+
+    fn finish(&mut self) -> anyhow::Result<Bytes> {
+        let mut output = BytesMut::new();
+        let plugin = self.plugin()?;
+        let plugin = self.plugin.insert(plugin);
+        self.lines
+            .finish(|line| plugin.on_line(line, &mut output))?;
+        Ok(output.freeze())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,199 @@
+#![allow(clippy::result_large_err)]
+
+use std::str::from_utf8;
+
+use aws_sdk_s3::operation::get_object::GetObjectOutput;
+use aws_sdk_s3::primitives::ByteStream;
+use futures::{stream, StreamExt};
+
+use super::*;
+
+const EXAMPLE: &[u8] = include_bytes!("../../../../plugins/example.wat");
+
+fn config() -> WasmConfig {
+    WasmConfig {
+        path: Some(concat!(env!("CARGO_MANIFEST_DIR"), "/plugins/example.wat").into()),
+        bucket: None,
+        key: None,
+        fuel: default_fuel(),
+        fuel_per_line: default_fuel_per_line(),
+        max_memory_bytes: default_max_memory_bytes(),
+        max_line_bytes: default_max_line_bytes(),
+    }
+}
+
+fn mock_s3(module: &'static [u8]) -> s3::S3 {
+    let mut s3 = s3::S3::faux();
+    faux::when!(s3.get_object("bucket", "plugin.wasm")).then(move |_| {
+        Ok(GetObjectOutput::builder()
+            .body(ByteStream::from_static(module))
+            .build())
+    });
+    s3
+}
+
+fn from_s3() -> WasmConfig {
+    WasmConfig {
+        path: None,
+        bucket: Some("bucket".into()),
+        key: Some("plugin.wasm".into()),
+        ..config()
+    }
+}
+
+async fn run(filter: &WasmStreamFilter, input: String) -> anyhow::Result<String> {
+    let input = stream::iter([Ok(Bytes::from(input))]);
+    let mut output = String::new();
+    let mut stream = filter.filter_stream(Box::new(input));
+    while let Some(chunk) = stream.next().await {
+        output.push_str(from_utf8(&chunk?).unwrap());
+    }
+    Ok(output)
+}
+
+#[tokio::test]
+async fn test_bundled_example() {
+    // given
+    let filter = WasmStreamFilter::load(config(), &s3::S3::faux())
+        .await
+        .unwrap();
+
+    // when
+    let output = run(
+        &filter,
+        "# numbers\n+36 1 234 5678\n\nphone: 06 30 123 4567".into(),
+    )
+    .await;
+
+    // then
+    assert_eq!(output.unwrap(), "+36 1 234 5678\nPHONE: 06 30 123 4567\n");
+}
+
+#[tokio::test]
+async fn test_load_from_s3() {
+    // given
+    let filter = WasmStreamFilter::load(from_s3(), &mock_s3(EXAMPLE))
+        .await
+        .unwrap();
+
+    // when
+    let output = run(&filter, "abc\n".into()).await;
+
+    // then
+    assert_eq!(output.unwrap(), "ABC\n");
+}
+
+#[tokio::test]
+async fn test_invalid_modules() {
+    for module in [
+        b"not wasm".as_slice(),
+        b"(module (memory (export \"memory\") 1))",
+        b"(module (import \"env\" \"f\" (func)))",
+    ] {
+        // when
+        let filter = WasmStreamFilter::load(from_s3(), &mock_s3(module)).await;
+
+        // then
+        assert!(filter.is_err(), "{}", from_utf8(module).unwrap());
+    }
+}
+
+#[tokio::test]
+async fn test_fuel_per_invocation() {
+    // given
+    let filter = WasmStreamFilter::load(
+        WasmConfig {
+            fuel: 2_000,
+            ..config()
+        },
+        &s3::S3::faux(),
+    )
+    .await
+    .unwrap();
+
+    // when
+    let short = [
+        run(&filter, "abc\n".into()).await,
+        run(&filter, "abc\n".into()).await,
+    ];
+    let long = run(&filter, "a".repeat(1_000)).await;
+
+    // then
+    assert_eq!(short.map(Result::unwrap), ["ABC\n", "ABC\n"]);
+    assert!(format!("{:#}", long.unwrap_err()).contains("fuel"));
+}
+
+#[tokio::test]
+async fn test_fuel_per_line() {
+    // given
+    let filter = WasmStreamFilter::load(
+        WasmConfig {
+            fuel_per_line: 2_000,
+            ..config()
+        },
+        &s3::S3::faux(),
+    )
+    .await
+    .unwrap();
+
+    // when
+    let short = run(&filter, "abc\n".repeat(100)).await;
+    let long = run(&filter, format!("abc\n{}", "a".repeat(1_000))).await;
+
+    // then
+    assert_eq!(short.unwrap(), "ABC\n".repeat(100));
+    let error = format!("{:#}", long.unwrap_err());
+    assert!(
+        error.contains("line 2") && error.contains("fuel"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn test_invalid_replacements() {
+    // a replacement of 2^32 - 1 bytes at 0
+    const MODULE: &[u8] = b"(module
+        (memory (export \"memory\") 1)
+        (func (export \"alloc\") (param i32) (result i32) (i32.const 0))
+        (func (export \"filter_line\") (param i32 i32) (result i64) (i64.const 0xffffffff)))";
+    for (max_line_bytes, expected) in [
+        (default_max_line_bytes(), "exceeds maxLineBytes"),
+        (usize::MAX, "out of the plugin memory"),
+    ] {
+        // given
+        let config = WasmConfig {
+            max_line_bytes,
+            ..from_s3()
+        };
+        let filter = WasmStreamFilter::load(config, &mock_s3(MODULE))
+            .await
+            .unwrap();
+
+        // when
+        let output = run(&filter, "abc\n".into()).await;
+
+        // then
+        let error = format!("{:#}", output.unwrap_err());
+        assert!(error.contains(expected), "{error}");
+    }
+}
+
+#[tokio::test]
+async fn test_memory_limit() {
+    // given
+    let filter = WasmStreamFilter::load(
+        WasmConfig {
+            max_memory_bytes: 65_536,
+            ..config()
+        },
+        &s3::S3::faux(),
+    )
+    .await
+    .unwrap();
+
+    // when
+    let output = run(&filter, "a".repeat(70_000)).await;
+
+    // then
+    assert!(output.is_err());
+}
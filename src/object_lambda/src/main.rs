@@ -3,16 +3,18 @@ use std::sync::Arc;
 use aws_config::BehaviorVersion;
 use aws_lambda_events::s3::object_lambda::S3ObjectLambdaEvent;
 use lambda_runtime::{service_fn, tracing, Error, LambdaEvent};
-use regex::Regex;
 use tokio::sync::OnceCell;
 
-use crate::libs::deps::reqwest::Reqwest;
-use crate::libs::deps::s3;
-use crate::libs::handlers::handler::{HandlerFn, ObjectLambdaResponse};
-use crate::libs::stream_byte_stream_adapter::StreamByteStreamAdapter;
-use crate::libs::stream_filter::RegexStreamFilter;
-
-mod libs;
+use object_lambda::libs::config::Config;
+use object_lambda::libs::deps::env::Env;
+use object_lambda::libs::deps::reqwest::Reqwest;
+use object_lambda::libs::deps::s3;
+use object_lambda::libs::handlers::handler::{HandlerFn, ObjectLambdaResponse};
+use object_lambda::libs::pipeline::{self, Output, Pipeline};
+use object_lambda::libs::stats::{LineCounts, StatsWriter};
+use object_lambda::libs::stream_byte_stream_adapter::StreamByteStreamAdapter;
+use object_lambda::libs::stream_filter::encoding::Transcoder;
+use object_lambda::libs::stream_filter::quarantine::Quarantine;
 
 static HANDLER: OnceCell<HandlerFn> = OnceCell::const_new();
 
@@ -23,11 +25,43 @@ async fn get_or_init_handler() -> &'static HandlerFn {
                 &aws_config::defaults(BehaviorVersion::latest()).load().await,
             )));
             let reqwest = Arc::new(Reqwest::new());
-            let filter = Arc::new(RegexStreamFilter::new(
-                Regex::new(r"^\s*(\+|0\s*0)\s*3\s*6\s*(1|[2-9]\s*[0-9])\s*([0-9]\s*){7}$").unwrap(),
-            ));
+            let env = Env::new();
+            let config = Config::load(&env).unwrap();
+            let stages = config.stages();
+            let pipeline = Pipeline::build(&stages, &env, &s3, &reqwest).await.unwrap();
+            let line_counts = if !config.decode().is_text() || pipeline.output == Output::Binary {
+                LineCounts::Off
+            } else if pipeline::keeps_input_lines(&stages) {
+                LineCounts::WithRejected
+            } else {
+                LineCounts::Counted
+            };
+            let transcoder = Arc::new(if config.decode().is_text() {
+                Transcoder::new(config.encoding)
+            } else {
+                Transcoder::binary()
+            });
+            let stats = config.stats.map(|stats| {
+                Arc::new(StatsWriter::new(stats, line_counts, pipeline.stats.clone()))
+            });
+            // validated to have a match stage for the quarantine to stand in for
+            let (filter, quarantine) = match (config.quarantine, pipeline.around_match) {
+                (Some(quarantine), Some((before, after))) => {
+                    (before, Some(Arc::new(Quarantine::new(quarantine, after))))
+                }
+                _ => (pipeline.filter, None),
+            };
             let adapter = Arc::new(StreamByteStreamAdapter::new());
-            libs::handlers::handler::factory(s3, reqwest, filter, adapter)
+            object_lambda::libs::handlers::handler::factory(
+                s3,
+                reqwest,
+                filter,
+                transcoder,
+                pipeline.suppression,
+                stats,
+                quarantine,
+                adapter,
+            )
         })
         .await
 }